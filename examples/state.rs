@@ -20,8 +20,22 @@ async fn main() -> Result<()> {
             "https://starknet-mainnet.g.alchemy.com/starknet/version/rpc/v0.6/{api_key}"
         ),
         data_dir: PathBuf::from("tmp"),
+        class_cache_bytes: 1024 * 1024 * 1024,
         poll_secs: 300,
         rpc_addr: ([127, 0, 0, 1], 3030).into(),
+        verify_events: false,
+        eth_methods: false,
+        settled_lookback: 64,
+        core_contract_address: None,
+        consensus_rpc: None,
+        fallback_rpc: None,
+        checkpoint: None,
+        eth_chain_id: None,
+        eth_network: None,
+        starknet_chain_id: None,
+        feeder_gateway: None,
+        eth_fee_token_address: None,
+        strk_fee_token_address: None,
     };
 
     let beerus = Client::new(&config).await?;
@@ -1,20 +1,40 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
 
-use beerus::config::Config;
-use clap::Parser;
+use beerus::{
+    config::Config,
+    gen::{Address, Felt, GetProofResult, Node, StorageKey},
+    proof::{trace_proof, Direction, NodeTrace},
+};
+use clap::{Parser, Subcommand};
+use eyre::eyre;
 use tokio::sync::RwLock;
 
 const RPC_SPEC_VERSION: &str = "0.6.0";
 
+/// Maximum number of states settled on L1 kept in memory.
+const MAX_SETTLED_STATES: usize = 16 * 1024;
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let config = get_config(Args::parse())?;
+    let args = Args::parse();
+    if let Some(Command::VerifyProof(args)) = args.command {
+        return verify_proof(args);
+    }
+
+    let config = get_config(args)?;
     config.check().await?;
 
+    beerus::exe::cache::init(
+        &config.data_dir,
+        config.expected_starknet_chain_id()?,
+        config.class_cache_bytes,
+    )?;
+
     let beerus = beerus::client::Client::new(&config).await?;
     beerus.start().await?;
+    let ethereum = beerus.ethereum();
 
     let rpc_spec_version = beerus.spec_version().await?;
     if rpc_spec_version != RPC_SPEC_VERSION {
@@ -25,12 +45,15 @@ async fn main() -> eyre::Result<()> {
     tracing::info!(?state, "initialized");
 
     let state = Arc::new(RwLock::new(state));
+    let settled = Arc::new(RwLock::new(BTreeMap::new()));
 
     {
         let state = state.clone();
+        let settled = settled.clone();
         let period = Duration::from_secs(config.poll_secs);
         tokio::spawn(async move {
             let mut tick = tokio::time::interval(period);
+            let mut since = None;
             loop {
                 tick.tick().await;
                 match beerus.get_state().await {
@@ -42,13 +65,44 @@ async fn main() -> eyre::Result<()> {
                         tracing::error!(error=?e, "state update failed");
                     }
                 }
+                match beerus.get_settled_states(since).await {
+                    Ok((latest, states)) => {
+                        since = Some(latest);
+                        let mut settled = settled.write().await;
+                        settled.extend(states);
+                        while settled.len() > MAX_SETTLED_STATES {
+                            settled.pop_first();
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!(error=?e, "settled states failed");
+                    }
+                }
             }
         });
     }
 
-    let server =
-        beerus::rpc::serve(&config.starknet_rpc, &config.rpc_addr, state)
-            .await?;
+    let (eth_fee_token_address, strk_fee_token_address) =
+        config.fee_token_addresses()?;
+    let server = beerus::rpc::serve(
+        &config.starknet_rpc,
+        &config.rpc_addr,
+        state,
+        settled,
+        Some(ethereum),
+        beerus::rpc::Options {
+            chain_id: Felt::try_new(config.expected_starknet_chain_id()?)
+                .map_err(|e| eyre!(e.message))?,
+            eth_fee_token_address: Felt::try_new(eth_fee_token_address)
+                .map_err(|e| eyre!(e.message))?,
+            strk_fee_token_address: Felt::try_new(strk_fee_token_address)
+                .map_err(|e| eyre!(e.message))?,
+            verify_events: config.verify_events,
+            eth_methods: config.eth_methods,
+            feeder_gateway: config.feeder_gateway_url().map(str::to_owned),
+        },
+    )
+    .await?;
 
     tracing::info!(port = server.port(), "rpc server started");
     server.done().await;
@@ -61,12 +115,94 @@ async fn main() -> eyre::Result<()> {
 struct Args {
     #[clap(short = 'c', long)]
     config: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Verify a saved `pathfinder_getProof` response offline
+    VerifyProof(VerifyProofArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyProofArgs {
+    /// Path to the JSON response (or just its result) of `pathfinder_getProof`
+    #[clap(short = 'p', long)]
+    proof: PathBuf,
+    /// Contract address
+    #[clap(short = 'a', long)]
+    address: String,
+    /// Storage key
+    #[clap(short = 'k', long)]
+    key: String,
+    /// Value of the storage key
+    #[clap(short = 'v', long)]
+    value: String,
+    /// Trusted global state root
+    #[clap(short = 'r', long)]
+    root: String,
 }
 
 fn get_config(args: Args) -> eyre::Result<Config> {
     Ok(if let Some(path) = args.config.as_ref() {
         Config::from_file(path)?
     } else {
-        Config::from_env()
+        Config::from_env()?
     })
 }
+
+fn verify_proof(args: VerifyProofArgs) -> eyre::Result<()> {
+    let json = std::fs::read_to_string(&args.proof)?;
+    let json: serde_json::Value = serde_json::from_str(&json)?;
+    // accept both the full JSON-RPC response and the bare result
+    let json = json.get("result").cloned().unwrap_or(json);
+    let proof: GetProofResult = serde_json::from_value(json)?;
+
+    let address = Felt::try_new(&args.address).map_err(|e| eyre!(e.message))?;
+    let key = StorageKey::try_new(&args.key).map_err(|e| eyre!(e.message))?;
+    let value = Felt::try_new(&args.value).map_err(|e| eyre!(e.message))?;
+    let root = Felt::try_new(&args.root).map_err(|e| eyre!(e.message))?;
+
+    println!("contract proof for address {}:", address.as_ref());
+    print_trace(&trace_proof(address.as_ref(), &proof.contract_proof)?);
+
+    let storage_proof = proof
+        .contract_data
+        .as_ref()
+        .and_then(|contract_data| contract_data.storage_proofs.as_ref())
+        .and_then(|storage_proofs| storage_proofs.first());
+    if let Some(storage_proof) = storage_proof {
+        println!("storage proof for key {}:", key.as_ref());
+        print_trace(&trace_proof(key.as_ref(), storage_proof)?);
+    }
+
+    proof.verify(root.clone(), Address(address), key, value)?;
+    println!("proof verified against root {}", root.as_ref());
+    Ok(())
+}
+
+fn print_trace(trace: &[NodeTrace]) {
+    for NodeTrace { depth, node, direction, hash } in trace {
+        match node {
+            Node::BinaryNode(node) => println!(
+                "  depth {depth:>3} binary left={} right={} -> {}\n            hash={}",
+                node.binary.left.as_ref(),
+                node.binary.right.as_ref(),
+                match direction {
+                    Some(Direction::Left) => "left",
+                    Some(Direction::Right) => "right",
+                    None => "?",
+                },
+                hash.as_ref()
+            ),
+            Node::EdgeNode(node) => println!(
+                "  depth {depth:>3} edge len={} path={} child={}\n            hash={}",
+                node.edge.path.len,
+                node.edge.path.value.as_ref(),
+                node.edge.child.as_ref(),
+                hash.as_ref()
+            ),
+        }
+    }
+}
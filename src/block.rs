@@ -0,0 +1,1202 @@
+use iamgroot::jsonrpc;
+use starknet::core::utils::starknet_keccak;
+use starknet_crypto::{pedersen_hash, poseidon_hash_many, FieldElement};
+use thiserror::Error as ThisError;
+
+use crate::gen::{
+    BlockTransaction, BlockWithTxs, DeclareTxn, DeployAccountTxn, EmittedEvent,
+    Felt, InvokeTxn, StateDiff, Txn,
+};
+use crate::proof::{
+    as_felt, as_field_element, pedersen, poseidon, HashFn, ProofError,
+};
+
+/// Height of the transaction, event and receipt commitment trees.
+const COMMITMENT_TREE_HEIGHT: u32 = 64;
+
+/// Version assumed for the first blocks, which carry an empty version.
+const LEGACY_VERSION: [u64; 3] = [0, 0, 0];
+
+/// Starting with this version the signatures of all transactions (and not
+/// only of invoke transactions) are part of the transaction commitment.
+const ALL_SIGNATURES_VERSION: [u64; 3] = [0, 11, 1];
+
+/// Starting with this version the block hash is a poseidon hash which also
+/// commits to the state diff, the receipts, the L1 data gas price and the
+/// data availability mode, none of which are part of the block served over
+/// RPC (see [`BlockData`]).
+const POSEIDON_HASH_VERSION: [u64; 3] = [0, 13, 2];
+
+/// Starting with this version the block hash commits to the gas prices
+/// (including the L2 gas price) through their own hash, and receipts commit
+/// to the L2 gas consumed.
+const GAS_PRICES_HASH_VERSION: [u64; 3] = [0, 13, 4];
+
+/// Failure to verify a block, or a transaction or events against the block
+/// committing to them, on top of the failures of the proofs themselves.
+#[derive(Debug, Clone, PartialEq, Eq, ThisError)]
+pub enum VerifyError {
+    #[error(transparent)]
+    Proof(#[from] ProofError),
+    #[error(
+        "block {block_number} hash mismatch: expected {expected}, computed {computed}"
+    )]
+    BlockHashMismatch { block_number: i64, expected: String, computed: String },
+    #[error("block hash cannot be verified for starknet version '{0}'")]
+    UnsupportedBlockVersion(String),
+    #[error("transaction hash cannot be verified for starknet version '{0}'")]
+    UnsupportedTransactionVersion(String),
+    #[error("block {block_number} hash cannot be verified without {name}")]
+    MissingBlockData { block_number: i64, name: &'static str },
+    #[error(
+        "transaction {transaction_hash} not found in block {block_number}"
+    )]
+    TransactionNotInBlock { block_number: i64, transaction_hash: String },
+    #[error(
+        "events do not match the verified events of blocks {from_block} to {to_block}"
+    )]
+    EventsMismatch { from_block: i64, to_block: i64 },
+}
+
+impl VerifyError {
+    /// True if the provider returned data that contradicts what is verified,
+    /// as for [`ProofError::is_invalid`].
+    pub fn is_invalid(&self) -> bool {
+        match self {
+            Self::Proof(error) => error.is_invalid(),
+            Self::BlockHashMismatch { .. }
+            | Self::TransactionNotInBlock { .. }
+            | Self::EventsMismatch { .. } => true,
+            Self::UnsupportedBlockVersion(_)
+            | Self::UnsupportedTransactionVersion(_)
+            | Self::MissingBlockData { .. } => false,
+        }
+    }
+}
+
+impl From<VerifyError> for jsonrpc::Error {
+    fn from(error: VerifyError) -> Self {
+        let code = if error.is_invalid() { -32700 } else { -32701 };
+        jsonrpc::Error::new(code, error.to_string())
+    }
+}
+
+/// Parts of the block committed to by its hash since 0.13.2 which are not
+/// part of the block served over RPC.
+#[derive(Clone, Debug)]
+pub struct BlockData {
+    pub state_diff: StateDiff,
+    pub l1_da_mode: L1DataAvailabilityMode,
+    pub l1_data_gas_price: GasPrice,
+    pub l2_gas_price: GasPrice,
+    /// Receipts of the transactions of the block, in order.
+    pub receipts: Vec<Receipt>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum L1DataAvailabilityMode {
+    Calldata,
+    Blob,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GasPrice {
+    pub price_in_wei: FieldElement,
+    pub price_in_fri: FieldElement,
+}
+
+#[derive(Clone, Debug)]
+pub struct Receipt {
+    pub transaction_hash: FieldElement,
+    pub actual_fee: FieldElement,
+    pub messages_sent: Vec<MessageToL1>,
+    /// Reason of the revert, `None` if the transaction succeeded.
+    pub revert_reason: Option<String>,
+    pub l1_gas: u128,
+    pub l1_data_gas: u128,
+    pub l2_gas: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct MessageToL1 {
+    pub from_address: FieldElement,
+    pub to_address: FieldElement,
+    pub payload: Vec<FieldElement>,
+}
+
+/// Check that the block hashes to `expected`, e.g. the hash committed to by
+/// its verified child block. `events` must contain all events emitted in
+/// the block, in order, and `data` is required starting with 0.13.2.
+pub fn verify_block_hash(
+    block: &BlockWithTxs,
+    events: &[EmittedEvent],
+    data: Option<&BlockData>,
+    expected: &Felt,
+) -> Result<(), VerifyError> {
+    let computed = block_hash(block, events, data)?;
+    if as_field_element(expected)? != as_field_element(&computed)? {
+        return Err(VerifyError::BlockHashMismatch {
+            block_number: *block.block_header.block_number.as_ref(),
+            expected: expected.as_ref().clone(),
+            computed: computed.as_ref().clone(),
+        });
+    }
+    Ok(())
+}
+
+/// True if the hash of the block commits to [`BlockData`].
+pub fn requires_block_data(block: &BlockWithTxs) -> bool {
+    parse_version(&block.block_header.starknet_version)
+        .is_some_and(|version| version.as_slice() >= &POSEIDON_HASH_VERSION[..])
+}
+
+/// Compute the hash of the block from its header, transactions and events
+/// (and since 0.13.2 from its [`BlockData`]). Before 0.13.2:
+/// H(number, state_root, sequencer, timestamp, tx_count, tx_commitment,
+/// event_count, event_commitment, 0, 0, parent_hash)
+/// where H is pedersen hash on elements. The first mainnet blocks carry an
+/// empty version and are hashed like the blocks before 0.7.
+pub fn block_hash(
+    block: &BlockWithTxs,
+    events: &[EmittedEvent],
+    data: Option<&BlockData>,
+) -> Result<Felt, VerifyError> {
+    let header = &block.block_header;
+    let version = if header.starknet_version.is_empty() {
+        LEGACY_VERSION.to_vec()
+    } else {
+        parse_version(&header.starknet_version).ok_or_else(|| {
+            VerifyError::UnsupportedBlockVersion(
+                header.starknet_version.clone(),
+            )
+        })?
+    };
+    let hash = if version.as_slice() < &POSEIDON_HASH_VERSION[..] {
+        pedersen_block_hash(block, events, &version)?
+    } else {
+        let data = data.ok_or(VerifyError::MissingBlockData {
+            block_number: *header.block_number.as_ref(),
+            name: "state diff and receipts",
+        })?;
+        poseidon_block_hash(block, events, data, &version)?
+    };
+    Ok(as_felt(hash)?)
+}
+
+fn pedersen_block_hash(
+    block: &BlockWithTxs,
+    events: &[EmittedEvent],
+    version: &[u64],
+) -> Result<FieldElement, VerifyError> {
+    let header = &block.block_header;
+    let transactions = &block.block_body_with_txs.transactions;
+    Ok(hash_on_elements(&[
+        FieldElement::from(*header.block_number.as_ref() as u64),
+        as_field_element(&header.new_root)?,
+        as_field_element(&header.sequencer_address)?,
+        FieldElement::from(*header.timestamp.as_ref() as u64),
+        FieldElement::from(transactions.len() as u64),
+        transaction_commitment(transactions, version)?,
+        FieldElement::from(events.len() as u64),
+        event_commitment(events, version)?,
+        FieldElement::ZERO,
+        FieldElement::ZERO,
+        as_field_element(&header.parent_hash.0)?,
+    ]))
+}
+
+/// Since 0.13.2:
+/// H("STARKNET_BLOCK_HASH0", number, state_root, sequencer, timestamp,
+/// counts, state_diff_commitment, tx_commitment, event_commitment,
+/// receipt_commitment, l1_gas_price_wei, l1_gas_price_fri,
+/// l1_data_gas_price_wei, l1_data_gas_price_fri, version, 0, parent_hash)
+/// where H is poseidon hash on elements, and `counts` packs the number of
+/// transactions, events and state diff entries with the DA mode. Since
+/// 0.13.4 the prefix is "STARKNET_BLOCK_HASH1" and the four gas prices are
+/// replaced by the hash of all gas prices, including the L2 gas price.
+fn poseidon_block_hash(
+    block: &BlockWithTxs,
+    events: &[EmittedEvent],
+    data: &BlockData,
+    version: &[u64],
+) -> Result<FieldElement, VerifyError> {
+    let header = &block.block_header;
+    let transactions = &block.block_body_with_txs.transactions;
+    let (state_diff_commitment, state_diff_length) = state_diff_commitment(
+        &data.state_diff,
+    )
+    .ok_or(VerifyError::MissingBlockData {
+        block_number: *header.block_number.as_ref(),
+        name: "complete state diff",
+    })?;
+    if data.receipts.len() != transactions.len() {
+        return Err(VerifyError::MissingBlockData {
+            block_number: *header.block_number.as_ref(),
+            name: "receipts of all transactions",
+        });
+    }
+    let counts = concat_counts(
+        transactions.len() as u64,
+        events.len() as u64,
+        state_diff_length,
+        data.l1_da_mode,
+    );
+
+    let l1_gas_price = GasPrice {
+        price_in_wei: as_field_element(&header.l1_gas_price.price_in_wei)?,
+        price_in_fri: as_field_element(&header.l1_gas_price.price_in_fri)?,
+    };
+    let gas_prices_hash = version >= &GAS_PRICES_HASH_VERSION[..];
+    let (prefix, gas_prices) = if gas_prices_hash {
+        let hash = poseidon_hash_many(&[
+            short_string("STARKNET_GAS_PRICES0"),
+            l1_gas_price.price_in_wei,
+            l1_gas_price.price_in_fri,
+            data.l1_data_gas_price.price_in_wei,
+            data.l1_data_gas_price.price_in_fri,
+            data.l2_gas_price.price_in_wei,
+            data.l2_gas_price.price_in_fri,
+        ]);
+        ("STARKNET_BLOCK_HASH1", vec![hash])
+    } else {
+        let prices = vec![
+            l1_gas_price.price_in_wei,
+            l1_gas_price.price_in_fri,
+            data.l1_data_gas_price.price_in_wei,
+            data.l1_data_gas_price.price_in_fri,
+        ];
+        ("STARKNET_BLOCK_HASH0", prices)
+    };
+
+    let mut elements = vec![
+        short_string(prefix),
+        FieldElement::from(*header.block_number.as_ref() as u64),
+        as_field_element(&header.new_root)?,
+        as_field_element(&header.sequencer_address)?,
+        FieldElement::from(*header.timestamp.as_ref() as u64),
+        counts,
+        state_diff_commitment,
+        transaction_commitment(transactions, version)?,
+        event_commitment(events, version)?,
+        receipt_commitment(&data.receipts, gas_prices_hash),
+    ];
+    elements.extend(gas_prices);
+    elements.extend([
+        short_string(&header.starknet_version),
+        FieldElement::ZERO,
+        as_field_element(&header.parent_hash.0)?,
+    ]);
+    Ok(poseidon_hash_many(&elements))
+}
+
+/// Root of the tree with a leaf for each transaction, keyed by its index in
+/// the block: H(transaction_hash, H(signature)) with pedersen hash before
+/// 0.13.2, and H(transaction_hash, signature...) (or H(transaction_hash, 0)
+/// without signature) with poseidon hash since.
+pub fn transaction_commitment(
+    transactions: &[BlockTransaction],
+    version: &[u64],
+) -> Result<FieldElement, ProofError> {
+    let all_signatures = version >= &ALL_SIGNATURES_VERSION[..];
+    let is_poseidon = version >= &POSEIDON_HASH_VERSION[..];
+    let leaves = transactions
+        .iter()
+        .map(|transaction| {
+            let transaction_hash =
+                as_field_element(&transaction.transaction_hash.0)?;
+            let signature = signature(&transaction.txn, all_signatures)
+                .iter()
+                .map(as_field_element)
+                .collect::<Result<Vec<_>, _>>()?;
+            if !is_poseidon {
+                return Ok(pedersen_hash(
+                    &transaction_hash,
+                    &hash_on_elements(&signature),
+                ));
+            }
+            let mut elements = vec![transaction_hash];
+            if signature.is_empty() {
+                elements.push(FieldElement::ZERO);
+            }
+            elements.extend(signature);
+            Ok(poseidon_hash_many(&elements))
+        })
+        .collect::<Result<Vec<_>, ProofError>>()?;
+    Ok(commitment_root(&leaves, tree_hash(version)))
+}
+
+/// Root of the tree with a leaf for each event, keyed by its index in the
+/// block: H(from_address, H(keys), H(data)) with pedersen hash before
+/// 0.13.2, and H(from_address, transaction_hash, key_count, keys...,
+/// data_count, data...) with poseidon hash since.
+pub fn event_commitment(
+    events: &[EmittedEvent],
+    version: &[u64],
+) -> Result<FieldElement, ProofError> {
+    let is_poseidon = version >= &POSEIDON_HASH_VERSION[..];
+    let leaves = events
+        .iter()
+        .map(|emitted| {
+            let event = &emitted.event;
+            let keys = event
+                .event_content
+                .keys
+                .iter()
+                .map(as_field_element)
+                .collect::<Result<Vec<_>, _>>()?;
+            let data = event
+                .event_content
+                .data
+                .iter()
+                .map(as_field_element)
+                .collect::<Result<Vec<_>, _>>()?;
+            let from_address = as_field_element(&event.from_address.0)?;
+            if !is_poseidon {
+                return Ok(hash_on_elements(&[
+                    from_address,
+                    hash_on_elements(&keys),
+                    hash_on_elements(&data),
+                ]));
+            }
+            let mut elements = vec![
+                from_address,
+                as_field_element(&emitted.transaction_hash.0)?,
+                FieldElement::from(keys.len() as u64),
+            ];
+            elements.extend(keys);
+            elements.push(FieldElement::from(data.len() as u64));
+            elements.extend(data);
+            Ok(poseidon_hash_many(&elements))
+        })
+        .collect::<Result<Vec<_>, ProofError>>()?;
+    Ok(commitment_root(&leaves, tree_hash(version)))
+}
+
+/// Root of the (poseidon) tree with a leaf for each receipt, keyed by the
+/// index of its transaction in the block: H(transaction_hash, actual_fee,
+/// H(messages), H(revert_reason) or 0, l2_gas, l1_gas, l1_data_gas). The
+/// L2 gas is only committed to since 0.13.4, and is 0 before.
+pub fn receipt_commitment(receipts: &[Receipt], l2_gas: bool) -> FieldElement {
+    let leaves: Vec<FieldElement> = receipts
+        .iter()
+        .map(|receipt| {
+            let mut messages =
+                vec![FieldElement::from(receipt.messages_sent.len() as u64)];
+            for message in &receipt.messages_sent {
+                messages.extend([
+                    message.from_address,
+                    message.to_address,
+                    FieldElement::from(message.payload.len() as u64),
+                ]);
+                messages.extend(message.payload.iter().copied());
+            }
+            let revert_reason = receipt
+                .revert_reason
+                .as_ref()
+                .map(|reason| starknet_keccak(reason.as_bytes()))
+                .unwrap_or(FieldElement::ZERO);
+            let l2_gas = if l2_gas { receipt.l2_gas } else { 0 };
+            poseidon_hash_many(&[
+                receipt.transaction_hash,
+                receipt.actual_fee,
+                poseidon_hash_many(&messages),
+                revert_reason,
+                FieldElement::from(l2_gas),
+                FieldElement::from(receipt.l1_gas),
+                FieldElement::from(receipt.l1_data_gas),
+            ])
+        })
+        .collect();
+    commitment_root(&leaves, poseidon)
+}
+
+/// Commitment to the state diff together with its length (the number of
+/// storage, nonce and class entries), or `None` if the state diff is not
+/// complete:
+/// H("STARKNET_STATE_DIFF0", deployed_count, (address, class_hash)...,
+/// declared_count, (class_hash, compiled_class_hash)...,
+/// deprecated_count, class_hash..., 1, 0,
+/// contract_count, (address, key_count, (key, value)...)...,
+/// nonce_count, (address, nonce)...)
+/// where H is poseidon hash on elements, deployed contracts include the
+/// replaced classes and every list is sorted.
+pub fn state_diff_commitment(
+    state_diff: &StateDiff,
+) -> Option<(FieldElement, u64)> {
+    fn felt(felt: &Felt) -> Option<FieldElement> {
+        as_field_element(felt).ok()
+    }
+    fn sorted<T>(mut items: Vec<(FieldElement, T)>) -> Vec<(FieldElement, T)> {
+        items.sort_by_key(|(key, _)| key.to_bytes_be());
+        items
+    }
+
+    let mut deployed = state_diff
+        .deployed_contracts
+        .iter()
+        .map(|item| Some((felt(&item.address)?, felt(&item.class_hash)?)))
+        .collect::<Option<Vec<_>>>()?;
+    for item in &state_diff.replaced_classes {
+        let address = felt(&item.contract_address.as_ref()?.0)?;
+        deployed.push((address, felt(item.class_hash.as_ref()?)?));
+    }
+    let declared = state_diff
+        .declared_classes
+        .iter()
+        .map(|item| {
+            Some((
+                felt(item.class_hash.as_ref()?)?,
+                felt(item.compiled_class_hash.as_ref()?)?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let deprecated = state_diff
+        .deprecated_declared_classes
+        .iter()
+        .map(|class_hash| Some((felt(class_hash)?, ())))
+        .collect::<Option<Vec<_>>>()?;
+    let storage = state_diff
+        .storage_diffs
+        .iter()
+        .filter(|item| !item.storage_entries.is_empty())
+        .map(|item| {
+            let entries = item
+                .storage_entries
+                .iter()
+                .map(|entry| {
+                    Some((
+                        felt(entry.key.as_ref()?)?,
+                        felt(entry.value.as_ref()?)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some((felt(&item.address)?, sorted(entries)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let nonces = state_diff
+        .nonces
+        .iter()
+        .map(|item| {
+            Some((
+                felt(&item.contract_address.as_ref()?.0)?,
+                felt(item.nonce.as_ref()?)?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let storage_entries: usize =
+        storage.iter().map(|(_, entries)| entries.len()).sum();
+    let length = storage_entries
+        + nonces.len()
+        + deployed.len()
+        + declared.len()
+        + deprecated.len();
+
+    let count = |n: usize| FieldElement::from(n as u64);
+    let mut elements = vec![short_string("STARKNET_STATE_DIFF0")];
+    elements.push(count(deployed.len()));
+    for (address, class_hash) in sorted(deployed) {
+        elements.extend([address, class_hash]);
+    }
+    elements.push(count(declared.len()));
+    for (class_hash, compiled_class_hash) in sorted(declared) {
+        elements.extend([class_hash, compiled_class_hash]);
+    }
+    elements.push(count(deprecated.len()));
+    for (class_hash, ()) in sorted(deprecated) {
+        elements.push(class_hash);
+    }
+    elements.extend([FieldElement::ONE, FieldElement::ZERO]);
+    elements.push(count(storage.len()));
+    for (address, entries) in sorted(storage) {
+        elements.extend([address, count(entries.len())]);
+        for (key, value) in entries {
+            elements.extend([key, value]);
+        }
+    }
+    elements.push(count(nonces.len()));
+    for (address, nonce) in sorted(nonces) {
+        elements.extend([address, nonce]);
+    }
+    Some((poseidon_hash_many(&elements), length as u64))
+}
+
+/// Transaction, event and state diff counts packed (as big-endian u64s)
+/// into one felt, followed by a byte whose top bit is set for blob DA.
+fn concat_counts(
+    transactions: u64,
+    events: u64,
+    state_diff_length: u64,
+    l1_da_mode: L1DataAvailabilityMode,
+) -> FieldElement {
+    let mut bytes = [0u8; 32];
+    bytes[0..8].copy_from_slice(&transactions.to_be_bytes());
+    bytes[8..16].copy_from_slice(&events.to_be_bytes());
+    bytes[16..24].copy_from_slice(&state_diff_length.to_be_bytes());
+    if l1_da_mode == L1DataAvailabilityMode::Blob {
+        bytes[24] = 0b1000_0000;
+    }
+    FieldElement::from_bytes_be(&bytes).expect("fits in a felt")
+}
+
+/// ASCII encoding of the string as a felt, e.g. of "0.13.2".
+fn short_string(value: &str) -> FieldElement {
+    FieldElement::from_byte_slice_be(value.as_bytes())
+        .expect("short string fits in a felt")
+}
+
+/// Hash of the commitment trees: pedersen before 0.13.2, poseidon since.
+fn tree_hash(version: &[u64]) -> HashFn {
+    if version >= &POSEIDON_HASH_VERSION[..] {
+        poseidon
+    } else {
+        pedersen
+    }
+}
+
+/// Signature committed to for the transaction. Before 0.11.1 only the
+/// signatures of invoke transactions were part of the commitment.
+fn signature(txn: &Txn, all_signatures: bool) -> &[Felt] {
+    match txn {
+        Txn::InvokeTxn(InvokeTxn::InvokeTxnV0(tx)) => &tx.signature,
+        Txn::InvokeTxn(InvokeTxn::InvokeTxnV1(tx)) => &tx.signature,
+        Txn::InvokeTxn(InvokeTxn::InvokeTxnV3(tx)) => &tx.signature,
+        _ if !all_signatures => &[],
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV0(tx)) => &tx.signature,
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV1(tx)) => &tx.signature,
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV2(tx)) => &tx.signature,
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV3(tx)) => &tx.signature,
+        Txn::DeployAccountTxn(DeployAccountTxn::DeployAccountTxnV1(tx)) => {
+            &tx.signature
+        }
+        Txn::DeployAccountTxn(DeployAccountTxn::DeployAccountTxnV3(tx)) => {
+            &tx.signature
+        }
+        Txn::DeployTxn(_) | Txn::L1HandlerTxn(_) => &[],
+    }
+}
+
+/// Parse a version like "0.13.1.1" into its numeric components.
+pub(crate) fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Pedersen hash on elements: H(...H(H(0, e1), e2)..., en), n).
+pub(crate) fn hash_on_elements(elements: &[FieldElement]) -> FieldElement {
+    let hash = elements
+        .iter()
+        .fold(FieldElement::ZERO, |acc, e| pedersen_hash(&acc, e));
+    pedersen_hash(&hash, &FieldElement::from(elements.len() as u64))
+}
+
+/// Root of the Patricia-Merkle tree of height 64 whose leaves are stored at
+/// keys 0..n.
+fn commitment_root(leaves: &[FieldElement], hash: HashFn) -> FieldElement {
+    if leaves.is_empty() {
+        return FieldElement::ZERO;
+    }
+    encode(subtree(leaves, 0, COMMITMENT_TREE_HEIGHT, hash), hash)
+}
+
+/// Subtree of the given height whose leftmost key is `start`, as the hash of
+/// its topmost binary node (or leaf) and the path leading down to it.
+fn subtree(
+    leaves: &[FieldElement],
+    start: u64,
+    height: u32,
+    hash: HashFn,
+) -> (FieldElement, u64, u32) {
+    if height == 0 {
+        return (leaves[start as usize], 0, 0);
+    }
+    let middle = start + (1u64 << (height - 1));
+    if leaves.len() as u64 <= middle {
+        // Only the left child is populated: extend the edge by a zero bit.
+        let (child, path, len) = subtree(leaves, start, height - 1, hash);
+        return (child, path, len + 1);
+    }
+    let left = encode(subtree(leaves, start, height - 1, hash), hash);
+    let right = encode(subtree(leaves, middle, height - 1, hash), hash);
+    (hash(&left, &right), 0, 0)
+}
+
+/// Hash of the node: the child hash itself if there is no edge leading to
+/// it, else H(child, path) + length.
+fn encode(
+    (child, path, len): (FieldElement, u64, u32),
+    hash: HashFn,
+) -> FieldElement {
+    if len == 0 {
+        return child;
+    }
+    hash(&child, &FieldElement::from(path)) + FieldElement::from(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn felt(value: u64) -> FieldElement {
+        FieldElement::from(value)
+    }
+
+    fn block(version: &str, signature: &[&str]) -> BlockWithTxs {
+        serde_json::from_value(serde_json::json!({
+            "status": "ACCEPTED_ON_L1",
+            "block_hash": "0x0",
+            "block_number": 7,
+            "l1_gas_price": {
+                "price_in_fri": "0x2",
+                "price_in_wei": "0x3"
+            },
+            "new_root": "0x4",
+            "parent_hash": "0x5",
+            "sequencer_address": "0x6",
+            "starknet_version": version,
+            "timestamp": 8,
+            "transactions": [{
+                "transaction_hash": "0x9",
+                "type": "DECLARE",
+                "version": "0x1",
+                "class_hash": "0xa",
+                "max_fee": "0xb",
+                "nonce": "0xc",
+                "sender_address": "0xd",
+                "signature": signature
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn events() -> Vec<EmittedEvent> {
+        serde_json::from_value(serde_json::json!([{
+            "from_address": "0xe",
+            "keys": ["0xf"],
+            "data": ["0x10", "0x11"],
+            "transaction_hash": "0x9"
+        }]))
+        .unwrap()
+    }
+
+    fn state_diff() -> StateDiff {
+        serde_json::from_value(serde_json::json!({
+            "declared_classes": [{
+                "class_hash": "0x21",
+                "compiled_class_hash": "0x22"
+            }],
+            "deployed_contracts": [{
+                "address": "0x25",
+                "class_hash": "0x26"
+            }],
+            "deprecated_declared_classes": ["0x24", "0x23"],
+            "nonces": [{
+                "contract_address": "0x27",
+                "nonce": "0x28"
+            }],
+            "replaced_classes": [{
+                "contract_address": "0x24",
+                "class_hash": "0x29"
+            }],
+            "storage_diffs": [{
+                "address": "0x2a",
+                "storage_entries": [
+                    { "key": "0x2c", "value": "0x2d" },
+                    { "key": "0x2b", "value": "0x0" }
+                ]
+            }, {
+                "address": "0x2e",
+                "storage_entries": []
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn receipt() -> Receipt {
+        Receipt {
+            transaction_hash: felt(9),
+            actual_fee: felt(0x31),
+            messages_sent: vec![MessageToL1 {
+                from_address: felt(0x32),
+                to_address: felt(0x33),
+                payload: vec![felt(0x34)],
+            }],
+            revert_reason: Some("reverted".to_owned()),
+            l1_gas: 0x35,
+            l1_data_gas: 0x36,
+            l2_gas: 0x37,
+        }
+    }
+
+    fn block_data() -> BlockData {
+        BlockData {
+            state_diff: state_diff(),
+            l1_da_mode: L1DataAvailabilityMode::Blob,
+            l1_data_gas_price: GasPrice {
+                price_in_wei: felt(0x38),
+                price_in_fri: felt(0x39),
+            },
+            l2_gas_price: GasPrice {
+                price_in_wei: felt(0x3a),
+                price_in_fri: felt(0x3b),
+            },
+            receipts: vec![receipt()],
+        }
+    }
+
+    #[test]
+    fn test_hash_on_elements() {
+        let expected =
+            pedersen_hash(&pedersen_hash(&felt(0), &felt(1)), &felt(1));
+        assert_eq!(hash_on_elements(&[felt(1)]), expected);
+        assert_eq!(hash_on_elements(&[]), pedersen_hash(&felt(0), &felt(0)));
+    }
+
+    #[test]
+    fn test_commitment_root() {
+        assert_eq!(commitment_root(&[], pedersen), FieldElement::ZERO);
+
+        // A single leaf at key 0 is reached by an all-zeros edge.
+        let expected = pedersen_hash(&felt(1), &felt(0)) + felt(64);
+        assert_eq!(commitment_root(&[felt(1)], pedersen), expected);
+
+        // Leaves at keys 0 and 1 share a binary node at the bottom.
+        let bottom = pedersen_hash(&felt(1), &felt(2));
+        let expected = pedersen_hash(&bottom, &felt(0)) + felt(63);
+        assert_eq!(commitment_root(&[felt(1), felt(2)], pedersen), expected);
+
+        // The leaf at key 2 hangs off a one-bit edge from the node above.
+        let right = pedersen_hash(&felt(3), &felt(0)) + felt(1);
+        let top = pedersen_hash(&bottom, &right);
+        let expected = pedersen_hash(&top, &felt(0)) + felt(62);
+        assert_eq!(
+            commitment_root(&[felt(1), felt(2), felt(3)], pedersen),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_transaction_commitment() {
+        let block = block("0.12.0", &["0x1", "0x2"]);
+        let transactions = &block.block_body_with_txs.transactions;
+        let leaf =
+            pedersen_hash(&felt(9), &hash_on_elements(&[felt(1), felt(2)]));
+        assert_eq!(
+            transaction_commitment(transactions, &[0, 12, 0]).unwrap(),
+            commitment_root(&[leaf], pedersen)
+        );
+
+        // Declare signatures were not committed to before 0.11.1.
+        let leaf = pedersen_hash(&felt(9), &hash_on_elements(&[]));
+        assert_eq!(
+            transaction_commitment(transactions, &[0, 11, 0]).unwrap(),
+            commitment_root(&[leaf], pedersen)
+        );
+    }
+
+    /// Mainnet block 183862 (0.12.1) with all its transactions and events.
+    fn mainnet_block() -> (BlockWithTxs, Vec<EmittedEvent>) {
+        let json: serde_json::Value = serde_json::from_str(include_str!(
+            "resources/mainnet_block_183862.json"
+        ))
+        .unwrap();
+        (
+            serde_json::from_value(json["block"].clone()).unwrap(),
+            serde_json::from_value(json["events"].clone()).unwrap(),
+        )
+    }
+
+    fn hex(value: &str) -> FieldElement {
+        FieldElement::from_hex_be(value).unwrap()
+    }
+
+    #[test]
+    fn test_mainnet_block_hash() {
+        let (block, events) = mainnet_block();
+        let transactions = &block.block_body_with_txs.transactions;
+        assert_eq!(
+            transaction_commitment(transactions, &[0, 12, 1]).unwrap(),
+            hex("0x3ef487dcb7c114e91ffba49b91567a5719a188abe7a03466943588a4b06c453")
+        );
+        assert_eq!(
+            event_commitment(&events, &[0, 12, 1]).unwrap(),
+            hex("0x4ca88a5447b4885563a074af8316c6cd0e4e8949ba8f532f08770d2f986fc91")
+        );
+
+        let expected = Felt::try_new(
+            "0x395daa726c74f1a96119796725d82b6b72452200cb62055c28e04202e07136f",
+        )
+        .unwrap();
+        assert!(verify_block_hash(&block, &events, None, &expected).is_ok());
+    }
+
+    #[test]
+    fn test_verify_block_hash() {
+        let mut block = block("0.13.1", &[]);
+        let hash = block_hash(&block, &events(), None).unwrap();
+        assert!(verify_block_hash(&block, &events(), None, &hash).is_ok());
+
+        // Dropping an event changes the event commitment.
+        let result = verify_block_hash(&block, &[], None, &hash);
+        assert!(matches!(
+            result,
+            Err(VerifyError::BlockHashMismatch { block_number: 7, .. })
+        ));
+
+        block.block_header.new_root = Felt::try_new("0x44").unwrap();
+        let result = verify_block_hash(&block, &events(), None, &hash);
+        assert!(result.unwrap_err().is_invalid());
+    }
+
+    #[test]
+    fn test_verify_error_code() {
+        let code = |error: VerifyError| jsonrpc::Error::from(error).code;
+        let mismatch =
+            VerifyError::EventsMismatch { from_block: 1, to_block: 2 };
+        assert_eq!(code(mismatch), -32700);
+        let missing = VerifyError::UnsupportedBlockVersion(String::new());
+        assert_eq!(code(missing), -32701);
+
+        let proof = ProofError::RootMismatch {
+            expected: "0x1".to_owned(),
+            computed: "0x2".to_owned(),
+        };
+        assert_eq!(code(proof.into()), -32700);
+        assert_eq!(code(ProofError::MissingContractData.into()), -32701);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let block = block("0.13.x", &[]);
+        assert_eq!(
+            block_hash(&block, &events(), None).unwrap_err(),
+            VerifyError::UnsupportedBlockVersion("0.13.x".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_empty_version() {
+        // Hashed like the blocks before 0.11.1, which commit to the signature
+        // of invoke transactions only.
+        let legacy = block("", &["0x1"]);
+        assert!(!requires_block_data(&legacy));
+        let hash = block_hash(&legacy, &events(), None).unwrap();
+        assert_eq!(
+            hash,
+            block_hash(&block("0.11.0", &[]), &events(), None).unwrap()
+        );
+        assert_ne!(
+            hash,
+            block_hash(&block("0.11.1", &["0x1"]), &events(), None).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_block_data() {
+        let block = block("0.13.2", &[]);
+        assert!(requires_block_data(&block));
+        assert!(matches!(
+            block_hash(&block, &events(), None),
+            Err(VerifyError::MissingBlockData { block_number: 7, .. })
+        ));
+
+        let mut data = block_data();
+        data.receipts.clear();
+        assert!(matches!(
+            block_hash(&block, &events(), Some(&data)),
+            Err(VerifyError::MissingBlockData { block_number: 7, .. })
+        ));
+    }
+
+    // The vectors of the 0.13.2 hashes below are the regression vectors of
+    // the sequencer (`starknet_api::block_hash`).
+
+    /// Transaction 0x1, the only part of which committed to with the
+    /// signature is its hash.
+    fn reference_transaction(signature: &[&str]) -> BlockTransaction {
+        serde_json::from_value(serde_json::json!({
+            "transaction_hash": "0x1",
+            "type": "INVOKE",
+            "version": "0x1",
+            "sender_address": "0x2",
+            "calldata": [],
+            "max_fee": "0x0",
+            "nonce": "0x0",
+            "signature": signature
+        }))
+        .unwrap()
+    }
+
+    fn reference_event(seed: u64) -> EmittedEvent {
+        let felts = |values: &[u64]| {
+            values.iter().map(|value| format!("{value:#x}")).collect::<Vec<_>>()
+        };
+        serde_json::from_value(serde_json::json!({
+            "from_address": format!("{:#x}", seed + 8),
+            "keys": felts(&[seed, seed + 1]),
+            "data": felts(&[seed + 2, seed + 3, seed + 4]),
+            "transaction_hash": "0x1234"
+        }))
+        .unwrap()
+    }
+
+    fn reference_receipt(transaction_hash: u64) -> Receipt {
+        let message = |seed: u64| MessageToL1 {
+            from_address: felt(seed),
+            to_address: felt(seed + 1),
+            payload: vec![felt(seed + 2), felt(seed + 3)],
+        };
+        Receipt {
+            transaction_hash: felt(transaction_hash),
+            actual_fee: felt(99804),
+            messages_sent: vec![message(34), message(56)],
+            revert_reason: Some("aborted".to_owned()),
+            l1_gas: 16580,
+            l1_data_gas: 32,
+            l2_gas: 0,
+        }
+    }
+
+    fn reference_state_diff() -> StateDiff {
+        serde_json::from_value(serde_json::json!({
+            "deployed_contracts": [
+                { "address": "0x0", "class_hash": "0x1" },
+                { "address": "0x2", "class_hash": "0x3" }
+            ],
+            "storage_diffs": [{
+                "address": "0x4",
+                "storage_entries": [
+                    { "key": "0x5", "value": "0x6" },
+                    { "key": "0x7", "value": "0x8" }
+                ]
+            }, {
+                "address": "0x9",
+                "storage_entries": [{ "key": "0xa", "value": "0xb" }]
+            }],
+            "declared_classes": [
+                { "class_hash": "0xc", "compiled_class_hash": "0xd" },
+                { "class_hash": "0xe", "compiled_class_hash": "0xf" }
+            ],
+            "deprecated_declared_classes": ["0x10"],
+            "nonces": [{ "contract_address": "0x11", "nonce": "0x12" }],
+            "replaced_classes": [
+                { "contract_address": "0x13", "class_hash": "0x14" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_poseidon_commitment_root() {
+        assert_eq!(
+            commitment_root(&[felt(1)], poseidon),
+            hex("0x7752582c54a42fe0fa35c40f07293bb7d8efe90e21d8d2c06a7db52d7d9b7e1")
+        );
+        assert_eq!(
+            commitment_root(&[felt(1), felt(2)], poseidon),
+            hex("0x1c1ba983ee0a0de87d87d67ea3cbee7023aa65f6b7bcf71259f122ea3af80bf")
+        );
+        assert_eq!(
+            commitment_root(&[felt(1), felt(2), felt(3)], poseidon),
+            hex("0x3b5cc7f1292eb3847c3f902d048a7e5dc7702d1c191ccd17c2d33f797e6fc32")
+        );
+    }
+
+    #[test]
+    fn test_poseidon_transaction_commitment() {
+        let transactions = vec![reference_transaction(&["0x2", "0x3"]); 2];
+        let leaf = hex(
+            "0x2f0d8840bcf3bc629598d8a6cc80cb7c0d9e52d93dab244bbf9cd0dca0ad082",
+        );
+        assert_eq!(
+            transaction_commitment(&transactions[..1], &[0, 13, 2]).unwrap(),
+            commitment_root(&[leaf], poseidon)
+        );
+        assert_eq!(
+            transaction_commitment(&transactions, &[0, 13, 2]).unwrap(),
+            hex("0x282b635972328bd1cfa86496fe920d20bd9440cd78ee8dc90ae2b383d664dcf")
+        );
+
+        // An empty signature is committed to as a single zero.
+        let transactions = [reference_transaction(&[])];
+        let leaf = hex(
+            "0xa93bf5e58b9378d093aa86ddc2f61a3295a1d1e665bd0ef3384dd07b30e033",
+        );
+        assert_eq!(
+            transaction_commitment(&transactions, &[0, 13, 2]).unwrap(),
+            commitment_root(&[leaf], poseidon)
+        );
+    }
+
+    #[test]
+    fn test_poseidon_event_commitment() {
+        // The event is bound to the transaction that emitted it.
+        let leaf = hex(
+            "0x367807f532742a4dcbe2d8a47b974b22dd7496faa75edc64a3a5fdb6709057",
+        );
+        assert_eq!(
+            event_commitment(&[reference_event(2)], &[0, 13, 2]).unwrap(),
+            commitment_root(&[leaf], poseidon)
+        );
+        let events = (0..3).map(reference_event).collect::<Vec<_>>();
+        assert_eq!(
+            event_commitment(&events, &[0, 13, 2]).unwrap(),
+            hex("0x69bb140ddbbeb01d81c7201ecfb933031306e45dab9c77ff9f9ba3cd4c2b9c3")
+        );
+    }
+
+    #[test]
+    fn test_receipt_commitment() {
+        let receipt = reference_receipt(1234);
+        let leaf = hex(
+            "0x6276abf21e7c68b2eecfdc8a845b11b44401901f5f040efe10c60d625049646",
+        );
+        let root = hex(
+            "0x31963cb891ebb825e83514deb748c89b6967b5368cbc48a9b56193a1464ca87",
+        );
+        assert_eq!(
+            receipt_commitment(std::slice::from_ref(&receipt), false),
+            commitment_root(&[leaf], poseidon)
+        );
+        assert_eq!(
+            receipt_commitment(std::slice::from_ref(&receipt), false),
+            root
+        );
+
+        // The L2 gas is only committed to since 0.13.4.
+        let mut with_l2_gas = receipt;
+        with_l2_gas.l2_gas = 0x37;
+        assert_eq!(receipt_commitment(&[with_l2_gas.clone()], false), root);
+        assert_ne!(receipt_commitment(&[with_l2_gas], true), root);
+    }
+
+    #[test]
+    fn test_state_diff_commitment() {
+        let expected = hex(
+            "0x281f5966e49ad7dad9323826d53d1d27c0c4e6ebe5525e2e2fbca549bfa0a67",
+        );
+        assert_eq!(
+            state_diff_commitment(&reference_state_diff()),
+            Some((expected, 10))
+        );
+
+        // The commitment does not depend on the order of the entries.
+        let mut shuffled = reference_state_diff();
+        shuffled.deployed_contracts.reverse();
+        shuffled.storage_diffs.reverse();
+        shuffled.storage_diffs[1].storage_entries.reverse();
+        shuffled.declared_classes.reverse();
+        assert_eq!(
+            state_diff_commitment(&shuffled),
+            state_diff_commitment(&reference_state_diff())
+        );
+
+        // Contracts with no storage entries are left out.
+        assert_eq!(
+            state_diff_commitment(&state_diff()),
+            state_diff_commitment(&{
+                let mut diff = state_diff();
+                diff.storage_diffs.pop();
+                diff
+            })
+        );
+
+        let mut incomplete = state_diff();
+        incomplete.nonces[0].nonce = None;
+        assert_eq!(state_diff_commitment(&incomplete), None);
+    }
+
+    #[test]
+    fn test_concat_counts() {
+        assert_eq!(
+            concat_counts(4, 3, 2, L1DataAvailabilityMode::Blob),
+            hex("0x0000000000000004000000000000000300000000000000028000000000000000")
+        );
+        assert_eq!(
+            concat_counts(4, 3, 2, L1DataAvailabilityMode::Calldata),
+            hex("0x0000000000000004000000000000000300000000000000020000000000000000")
+        );
+    }
+
+    #[test]
+    fn test_poseidon_block_hash() {
+        let block: BlockWithTxs = serde_json::from_value(serde_json::json!({
+            "status": "ACCEPTED_ON_L1",
+            "block_hash": "0x0",
+            "block_number": 1,
+            "l1_gas_price": {
+                "price_in_fri": "0x6",
+                "price_in_wei": "0x7"
+            },
+            "new_root": "0x2",
+            "parent_hash": "0xb",
+            "sequencer_address": "0x3",
+            "starknet_version": "10",
+            "timestamp": 4,
+            "transactions": [reference_transaction(&["0x2", "0x3"])]
+        }))
+        .unwrap();
+        let data = BlockData {
+            state_diff: reference_state_diff(),
+            l1_da_mode: L1DataAvailabilityMode::Blob,
+            l1_data_gas_price: GasPrice {
+                price_in_wei: felt(9),
+                price_in_fri: felt(10),
+            },
+            l2_gas_price: GasPrice::default(),
+            receipts: vec![reference_receipt(1)],
+        };
+        // The version of the vector is no release, so the scheme is given.
+        assert_eq!(
+            poseidon_block_hash(&block, &[], &data, &[0, 13, 2]).unwrap(),
+            hex("0x61e4998d51a248f1d0288d7e17f6287757b0e5e6c5e1e58ddf740616e312134")
+        );
+
+        // The receipts are committed to.
+        let mut data = data;
+        data.receipts[0].actual_fee = felt(0x41);
+        assert_ne!(
+            poseidon_block_hash(&block, &[], &data, &[0, 13, 2]).unwrap(),
+            hex("0x61e4998d51a248f1d0288d7e17f6287757b0e5e6c5e1e58ddf740616e312134")
+        );
+    }
+
+    #[test]
+    fn test_verify_poseidon_block_hash() {
+        let block = block("0.13.2", &["0x1"]);
+        let computed =
+            block_hash(&block, &events(), Some(&block_data())).unwrap();
+        assert!(verify_block_hash(
+            &block,
+            &events(),
+            Some(&block_data()),
+            &computed
+        )
+        .is_ok());
+
+        let mut data = block_data();
+        data.receipts[0].actual_fee = felt(0x41);
+        let result =
+            verify_block_hash(&block, &events(), Some(&data), &computed);
+        assert!(result.unwrap_err().is_invalid());
+    }
+
+    #[test]
+    fn test_poseidon_block_hash_with_gas_prices_hash() {
+        // Computed from the specification of STARKNET_BLOCK_HASH1 by a
+        // separate implementation, which also reproduces the 0.13.2 vector.
+        let block = block("0.13.4", &["0x1"]);
+        let computed =
+            block_hash(&block, &events(), Some(&block_data())).unwrap();
+        assert_eq!(
+            as_field_element(&computed).unwrap(),
+            hex("0x34ec70c17155fd1c57f2367bad7f9e965633c079180c6f8ca858864504cff02")
+        );
+
+        // The L2 gas price is committed to.
+        let mut data = block_data();
+        data.l2_gas_price.price_in_fri = felt(0x41);
+        let result =
+            verify_block_hash(&block, &events(), Some(&data), &computed);
+        assert!(result.unwrap_err().is_invalid());
+    }
+}
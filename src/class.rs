@@ -0,0 +1,204 @@
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+use starknet::core::types::contract::legacy::LegacyContractClass;
+use starknet::core::utils::starknet_keccak;
+use starknet_crypto::{poseidon_hash_many, FieldElement};
+
+use crate::exe::err::Error;
+use crate::exe::map::decode_program;
+use crate::gen::{self, ContractClass, DeprecatedContractClass, Felt};
+
+/// Compute the hash of the class, as it is committed to in the state.
+pub fn class_hash(class: &gen::GetClassResult) -> Result<FieldElement, Error> {
+    match class {
+        gen::GetClassResult::ContractClass(class) => sierra_class_hash(class),
+        gen::GetClassResult::DeprecatedContractClass(class) => {
+            legacy_class_hash(class)
+        }
+    }
+}
+
+/// Compute the hash of a Sierra class:
+/// H(CONTRACT_CLASS_V0.1.0, external, l1_handler, constructor, abi, program)
+/// where H is poseidon and the ABI is hashed with starknet keccak.
+pub fn sierra_class_hash(class: &ContractClass) -> Result<FieldElement, Error> {
+    const CONTRACT_CLASS_VERSION: &[u8] = b"CONTRACT_CLASS_V0.1.0";
+    let version = FieldElement::from_byte_slice_be(CONTRACT_CLASS_VERSION)
+        .map_err(|_| Error::Custom("invalid class version"))?;
+
+    let entry_points = &class.entry_points_by_type;
+    let external = hash_entry_points(&entry_points.external)?;
+    let l1_handler = hash_entry_points(&entry_points.l1_handler)?;
+    let constructor = hash_entry_points(&entry_points.constructor)?;
+
+    let abi = class.abi.as_deref().unwrap_or_default();
+    let abi = starknet_keccak(abi.as_bytes());
+
+    let program = class
+        .sierra_program
+        .iter()
+        .map(as_field_element)
+        .collect::<Result<Vec<_>, _>>()?;
+    let program = poseidon_hash_many(&program);
+
+    Ok(poseidon_hash_many(&[
+        version,
+        external,
+        l1_handler,
+        constructor,
+        abi,
+        program,
+    ]))
+}
+
+/// Compute the hash of a legacy (Cairo 0) class.
+pub fn legacy_class_hash(
+    class: &DeprecatedContractClass,
+) -> Result<FieldElement, Error> {
+    let program = decode_program(class.program.as_ref())?;
+
+    let mut json = serde_json::to_value(class)?;
+    json["program"] = serde_json::from_str(&program)?;
+    if json.get("abi").is_none() {
+        json["abi"] = serde_json::json!([]);
+    }
+    for entry_point_type in ["CONSTRUCTOR", "EXTERNAL", "L1_HANDLER"] {
+        let entry_points = &mut json["entry_points_by_type"];
+        if entry_points.get(entry_point_type).is_none() {
+            entry_points[entry_point_type] = serde_json::json!([]);
+        }
+    }
+
+    let class: LegacyContractClass = serde_json::from_value(json)?;
+    class
+        .class_hash()
+        .map_err(|_| Error::Custom("failed to compute legacy class hash"))
+}
+
+/// Compute the hash of the compiled class. The class commitment tree stores
+/// the hash of the class as it was compiled when declared.
+pub fn casm_class_hash(
+    casm_contract_class: &CasmContractClass,
+) -> Result<FieldElement, Error> {
+    let hash = casm_contract_class.compiled_class_hash();
+    FieldElement::from_bytes_be(&hash.to_be_bytes())
+        .map_err(|_| Error::Custom("invalid compiled class hash"))
+}
+
+fn hash_entry_points(
+    entry_points: &[gen::SierraEntryPoint],
+) -> Result<FieldElement, Error> {
+    let mut felts = Vec::with_capacity(entry_points.len() * 2);
+    for entry_point in entry_points {
+        felts.push(as_field_element(&entry_point.selector)?);
+        felts.push(FieldElement::from(entry_point.function_idx as u64));
+    }
+    Ok(poseidon_hash_many(&felts))
+}
+
+fn as_field_element(felt: &Felt) -> Result<FieldElement, Error> {
+    FieldElement::from_hex_be(felt.as_ref())
+        .map_err(|_| Error::Custom("invalid felt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The expected hashes are computed with the class hash implementations
+    // of starknet-rs (`FlattenedSierraClass`, `CompiledClass` and
+    // `LegacyContractClass`) for the same classes.
+
+    const SELECTOR: &str =
+        "0x362398bec32bc0ebb411203221a35a0301193a96f317ebe5e40be9f60d15320";
+
+    fn sierra_class() -> ContractClass {
+        serde_json::from_value(serde_json::json!({
+            "sierra_program": [
+                "0x1", "0x5", "0x0", "0x2", "0x6", "0x3", "0x2a", "0x1f", "0x7"
+            ],
+            "contract_class_version": "0.1.0",
+            "entry_points_by_type": {
+                "CONSTRUCTOR": [],
+                "EXTERNAL": [{ "selector": SELECTOR, "function_idx": 0 }],
+                "L1_HANDLER": []
+            },
+            "abi": "[{\"type\": \"function\", \"name\": \"increase_balance\", \"inputs\": [{\"name\": \"amount\", \"type\": \"core::felt252\"}], \"outputs\": [], \"state_mutability\": \"external\"}]"
+        }))
+        .unwrap()
+    }
+
+    fn hex(felt: FieldElement) -> String {
+        format!("0x{:x}", felt)
+    }
+
+    #[test]
+    fn test_sierra_class_hash() {
+        assert_eq!(
+            hex(sierra_class_hash(&sierra_class()).unwrap()),
+            "0x4ecaa997b43b2ab637540df5a6551b9b883ea7e53c7bdb8ab258c344caa7ab1"
+        );
+    }
+
+    #[test]
+    fn test_sierra_class_hash_covers_program() {
+        let mut class = sierra_class();
+        class.sierra_program[6] = Felt::try_new("0x2b").unwrap();
+        assert_ne!(
+            hex(sierra_class_hash(&class).unwrap()),
+            "0x4ecaa997b43b2ab637540df5a6551b9b883ea7e53c7bdb8ab258c344caa7ab1"
+        );
+    }
+
+    #[test]
+    fn test_legacy_class_hash() {
+        let class: DeprecatedContractClass =
+            serde_json::from_value(serde_json::json!({
+                "abi": [],
+                "entry_points_by_type": {
+                    "CONSTRUCTOR": [],
+                    "EXTERNAL": [{ "offset": "0x0", "selector": SELECTOR }],
+                    "L1_HANDLER": []
+                },
+                "program": "H4sIAAAAAAAA/5VQ0QqDMAz8lzyLpGOw4a+MUWpNNUyj1DoE8d+XOobPCySQy+UuZAOXUuR6STRD9XgWUC/cJ5bcwUQNxZkECohOWrK+I/8CZflxmLinaN9K4FGgAiyNKbG8KLlxyeV9XK94uyOaWwghp85wNUe94L3+wZqUVRuql9ayhBEqWfq+gI4l6SnbXgA3JIkDq+EXGByLnf04kbpbe7RWtafIQ4ZwVeszjMH/It8ZKVAk8aTy4lqKan2Cx8v2/QOPJ3XkRQEAAA=="
+            }))
+            .unwrap();
+        assert_eq!(
+            hex(legacy_class_hash(&class).unwrap()),
+            "0x7135bb912236c640ca36fa0ec1d78821ecda702ab05b52a53cb6ec16afbde05"
+        );
+    }
+
+    #[test]
+    fn test_casm_class_hash() {
+        let class: CasmContractClass =
+            serde_json::from_value(serde_json::json!({
+                "prime": "0x800000000000011000000000000000000000000000000000000000000000001",
+                "compiler_version": "2.6.3",
+                "bytecode": [
+                    "0xa0680017fff8000",
+                    "0x7",
+                    "0x482680017ffa8000",
+                    "0x100000000000000000000000000000000",
+                    "0x400280007ff97fff",
+                    "0x10780017fff7fff",
+                    "0x2",
+                    "0x208b7fff7fff7ffe"
+                ],
+                "hints": [],
+                "entry_points_by_type": {
+                    "EXTERNAL": [{
+                        "selector": SELECTOR,
+                        "offset": 0,
+                        "builtins": ["range_check"]
+                    }],
+                    "L1_HANDLER": [],
+                    "CONSTRUCTOR": []
+                }
+            }))
+            .unwrap();
+        assert_eq!(
+            hex(casm_class_hash(&class).unwrap()),
+            "0x677d078f24f98e1527d1fa8b6583f944d390d95aaac0b1e0356f14d1bd2efca"
+        );
+    }
+}
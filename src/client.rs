@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
 use eyre::{Context, Result};
 
 use crate::eth::EthereumClient;
@@ -5,6 +8,10 @@ use crate::gen::client::Client as StarknetClient;
 use crate::gen::{BlockId, Felt, Rpc};
 use crate::{config::Config, gen::FunctionCall};
 
+/// Number of L1 blocks whose logs are read at once when looking up settled
+/// states, which keeps the number of logs to verify per request small.
+const SETTLED_STATES_CHUNK: u64 = 64;
+
 #[derive(Debug, Clone)]
 pub struct State {
     pub block_number: u64,
@@ -14,20 +21,29 @@ pub struct State {
 
 pub struct Client {
     starknet: StarknetClient,
-    ethereum: EthereumClient,
+    ethereum: Arc<EthereumClient>,
+    settled_lookback: u64,
 }
 
 impl Client {
     pub async fn new(config: &Config) -> Result<Self> {
         let starknet = StarknetClient::new(&config.starknet_rpc);
-        let ethereum = EthereumClient::new(config).await?;
-        Ok(Self { starknet, ethereum })
+        let ethereum = Arc::new(EthereumClient::new(config).await?);
+        Ok(Self {
+            starknet,
+            ethereum,
+            settled_lookback: config.settled_lookback,
+        })
     }
 
     pub async fn start(&self) -> Result<()> {
         self.ethereum.start().await
     }
 
+    pub fn ethereum(&self) -> Arc<EthereumClient> {
+        self.ethereum.clone()
+    }
+
     pub async fn call_starknet(
         &self,
         request: FunctionCall,
@@ -51,6 +67,60 @@ impl Client {
         })
     }
 
+    /// Return the Starknet states settled on L1 since the given L1 block (or
+    /// within the configured number of L1 blocks back if none is given),
+    /// keyed by the Starknet block number, together with the latest L1 block
+    /// they were read up to. The logs are read newest first, so a backfill
+    /// that fails part way (e.g. on L1 blocks too old to be verified) still
+    /// returns the most recent states.
+    pub async fn get_settled_states(
+        &self,
+        since: Option<u64>,
+    ) -> Result<(u64, BTreeMap<u64, State>)> {
+        let (latest, _) = self.ethereum.latest().await?;
+        let from = match since {
+            Some(since) => since + 1,
+            None => latest.saturating_sub(self.settled_lookback),
+        };
+
+        let mut states = BTreeMap::new();
+        let mut to = latest;
+        while from <= to {
+            let chunk_from =
+                to.saturating_sub(SETTLED_STATES_CHUNK - 1).max(from);
+            let updates = match self
+                .ethereum
+                .state_updates(chunk_from, to)
+                .await
+                .context("beerus: get settled states")
+            {
+                Ok(updates) => updates,
+                Err(e) if since.is_none() && !states.is_empty() => {
+                    tracing::warn!(
+                        error=?e,
+                        l1_block = to,
+                        "settled states backfill stopped"
+                    );
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            for (block_number, block_hash, root) in updates {
+                let state = State {
+                    block_number,
+                    block_hash: as_felt(block_hash.as_bytes())?,
+                    root: as_felt(root.as_bytes())?,
+                };
+                states.insert(block_number, state);
+            }
+            match chunk_from.checked_sub(1) {
+                Some(next) => to = next,
+                None => break,
+            }
+        }
+        Ok((latest, states))
+    }
+
     pub async fn spec_version(&self) -> Result<String> {
         let version = self.starknet.specVersion().await?;
         Ok(version)
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::net::SocketAddr;
@@ -7,11 +8,13 @@ use std::str::FromStr;
 use eyre::{eyre, Context, Result};
 
 use helios::config::networks::Network;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 const DEFAULT_DATA_DIR: &str = "tmp";
+const DEFAULT_CLASS_CACHE_BYTES: u64 = 1024 * 1024 * 1024;
 const DEFAULT_POLL_SECS: u64 = 5;
+const DEFAULT_SETTLED_LOOKBACK: u64 = 7200;
 
 const MAINNET_ETHEREUM_CHAINID: &str = "0x1";
 const SEPOLIA_ETHEREUM_CHAINID: &str = "0xaa36a7";
@@ -19,6 +22,17 @@ const SEPOLIA_ETHEREUM_CHAINID: &str = "0xaa36a7";
 const MAINNET_STARKNET_CHAINID: &str = "0x534e5f4d41494e";
 const SEPOLIA_STARKNET_CHAINID: &str = "0x534e5f5345504f4c4941";
 
+pub const MAINNET_FEEDER_GATEWAY: &str =
+    "https://alpha-mainnet.starknet.io/feeder_gateway";
+const SEPOLIA_FEEDER_GATEWAY: &str =
+    "https://alpha-sepolia.starknet.io/feeder_gateway";
+
+/// Fee tokens deployed at the same addresses on MAINNET and SEPOLIA.
+pub const ETH_FEE_TOKEN_ADDRESS: &str =
+    "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+pub const STRK_FEE_TOKEN_ADDRESS: &str =
+    "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+
 #[derive(Clone, Deserialize, Debug, Validate)]
 pub struct Config {
     pub network: Network,
@@ -28,28 +42,115 @@ pub struct Config {
     pub starknet_rpc: String,
     #[serde(default = "default_data_dir")]
     pub data_dir: PathBuf,
+    /// Upper bound on the total size of the compiled classes cached on disk
+    /// under `data_dir`.
+    #[serde(default = "default_class_cache_bytes")]
+    pub class_cache_bytes: u64,
     #[serde(default = "default_poll_secs")]
     #[validate(range(min = 1, max = 3600))]
     pub poll_secs: u64,
     #[serde(default = "default_rpc_addr")]
     pub rpc_addr: SocketAddr,
+    #[serde(default)]
+    pub verify_events: bool,
+    /// Serve the `beerus_eth*` methods, which read L1 through Helios.
+    #[serde(default)]
+    pub eth_methods: bool,
+    /// Number of L1 blocks back (a day by default) from which the states
+    /// settled on L1 are backfilled at startup.
+    #[serde(default = "default_settled_lookback")]
+    #[validate(range(min = 1))]
+    pub settled_lookback: u64,
+    /// Overrides of the network defaults, required for any network other
+    /// than MAINNET and SEPOLIA (app-chains, forks, local devnets).
+    #[serde(default)]
+    pub core_contract_address: Option<String>,
+    #[serde(default)]
+    #[validate(url)]
+    pub consensus_rpc: Option<String>,
+    #[serde(default)]
+    #[validate(url)]
+    pub fallback_rpc: Option<String>,
+    #[serde(default)]
+    pub checkpoint: Option<String>,
+    #[serde(default)]
+    pub eth_chain_id: Option<String>,
+    /// Beacon chain and fork schedule Helios follows instead of the ones of
+    /// `network`, for an Ethereum network it does not know of.
+    #[serde(default)]
+    pub eth_network: Option<EthNetwork>,
+    #[serde(default)]
+    pub starknet_chain_id: Option<String>,
+    #[serde(default)]
+    #[validate(url)]
+    pub feeder_gateway: Option<String>,
+    #[serde(default)]
+    pub eth_fee_token_address: Option<String>,
+    #[serde(default)]
+    pub strk_fee_token_address: Option<String>,
+}
+
+/// Beacon chain of an Ethereum network (e.g. a local devnet) and its forks.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct EthNetwork {
+    pub chain_id: u64,
+    pub genesis_time: u64,
+    /// Genesis validators root, 32 bytes of hex.
+    pub genesis_root: String,
+    /// Every fork Helios expects (`genesis`, `altair`, `bellatrix`,
+    /// `capella`, `deneb`), by name.
+    pub forks: BTreeMap<String, EthFork>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct EthFork {
+    pub epoch: u64,
+    /// Fork version, 4 bytes of hex.
+    pub fork_version: String,
 }
 
 fn default_data_dir() -> PathBuf {
     PathBuf::from(DEFAULT_DATA_DIR)
 }
 
+fn default_class_cache_bytes() -> u64 {
+    DEFAULT_CLASS_CACHE_BYTES
+}
+
 fn default_poll_secs() -> u64 {
     DEFAULT_POLL_SECS
 }
 
+fn default_settled_lookback() -> u64 {
+    DEFAULT_SETTLED_LOOKBACK
+}
+
 fn default_rpc_addr() -> SocketAddr {
     SocketAddr::from(([0, 0, 0, 0], 3030))
 }
 
+/// Parse the JSON value of `ETH_NETWORK`, which is an error when set but
+/// malformed rather than silently falling back to the built-in network.
+fn parse_eth_network(value: &str) -> Result<EthNetwork> {
+    serde_json::from_str(value).context("Invalid ETH_NETWORK")
+}
+
+/// Read the override from the environment variable, which is an error when
+/// set but malformed, as for `ETH_NETWORK`.
+fn env_override(
+    name: &str,
+    check: impl FnOnce(&str, &str) -> Result<()>,
+) -> Result<Option<String>> {
+    let Ok(value) = std::env::var(name) else {
+        return Ok(None);
+    };
+    check(name, &value).with_context(|| format!("Invalid {name}"))?;
+    Ok(Some(value))
+}
+
 impl Config {
-    pub fn from_env() -> Self {
-        Self {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
             network: Network::from_str(
                 &std::env::var("NETWORK").unwrap_or_default(),
             )
@@ -60,6 +161,10 @@ impl Config {
             data_dir: PathBuf::from(
                 std::env::var("DATA_DIR").unwrap_or_default(),
             ),
+            class_cache_bytes: std::env::var("CLASS_CACHE_BYTES")
+                .ok()
+                .and_then(|bytes| bytes.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_CLASS_CACHE_BYTES),
             poll_secs: u64::from_str(
                 &std::env::var("POLL_SECS").unwrap_or_default(),
             )
@@ -68,7 +173,41 @@ impl Config {
                 .ok()
                 .and_then(|rpc_addr| rpc_addr.parse::<SocketAddr>().ok())
                 .unwrap_or_else(default_rpc_addr),
-        }
+            verify_events: std::env::var("VERIFY_EVENTS")
+                .ok()
+                .and_then(|verify_events| verify_events.parse::<bool>().ok())
+                .unwrap_or_default(),
+            eth_methods: std::env::var("ETH_METHODS")
+                .ok()
+                .and_then(|eth_methods| eth_methods.parse::<bool>().ok())
+                .unwrap_or_default(),
+            settled_lookback: std::env::var("SETTLED_LOOKBACK")
+                .ok()
+                .and_then(|lookback| lookback.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_SETTLED_LOOKBACK),
+            core_contract_address: env_override(
+                "CORE_CONTRACT_ADDRESS",
+                |name, value| check_hex(name, value, 20),
+            )?,
+            consensus_rpc: env_override("CONSENSUS_RPC", check_url)?,
+            fallback_rpc: env_override("FALLBACK_RPC", check_url)?,
+            checkpoint: env_override("CHECKPOINT", |name, value| {
+                check_hex(name, value, 32)
+            })?,
+            eth_chain_id: env_override("ETH_CHAIN_ID", check_chain_id_format)?,
+            eth_network: std::env::var("ETH_NETWORK")
+                .ok()
+                .map(|network| parse_eth_network(&network))
+                .transpose()?,
+            starknet_chain_id: env_override(
+                "STARKNET_CHAIN_ID",
+                check_chain_id_format,
+            )?,
+            feeder_gateway: std::env::var("FEEDER_GATEWAY").ok(),
+            eth_fee_token_address: std::env::var("ETH_FEE_TOKEN_ADDRESS").ok(),
+            strk_fee_token_address: std::env::var("STRK_FEE_TOKEN_ADDRESS")
+                .ok(),
+        })
     }
 
     pub fn from_file(path: &str) -> Result<Self> {
@@ -88,34 +227,17 @@ impl Config {
 
     pub async fn check(&self) -> Result<()> {
         self.validate()?;
+        self.check_overrides()?;
 
-        let expected_chain_id = match self.network {
-            Network::MAINNET => MAINNET_ETHEREUM_CHAINID,
-            Network::SEPOLIA => SEPOLIA_ETHEREUM_CHAINID,
-            _ => {
-                eyre::bail!(
-                    "Ethereum chain id check failed: unsupported network"
-                );
-            }
-        };
         check_chain_id(
-            expected_chain_id,
+            self.expected_eth_chain_id()?,
             &self.eth_execution_rpc,
             "eth_chainId",
         )
         .await?;
 
-        let expected_chain_id = match self.network {
-            Network::MAINNET => MAINNET_STARKNET_CHAINID,
-            Network::SEPOLIA => SEPOLIA_STARKNET_CHAINID,
-            _ => {
-                eyre::bail!(
-                    "Starknet chain id check failed: unsupported network"
-                );
-            }
-        };
         check_chain_id(
-            expected_chain_id,
+            self.expected_starknet_chain_id()?,
             &self.starknet_rpc,
             "starknet_chainId",
         )
@@ -123,6 +245,121 @@ impl Config {
 
         check_data_dir(&self.data_dir)
     }
+
+    /// Check the format of the overrides passed on to Helios as they are.
+    fn check_overrides(&self) -> Result<()> {
+        if let Some(address) = self.core_contract_address.as_deref() {
+            check_hex("core_contract_address", address, 20)?;
+        }
+        if let Some(checkpoint) = self.checkpoint.as_deref() {
+            check_hex("checkpoint", checkpoint, 32)?;
+        }
+        if let Some(chain_id) = self.eth_chain_id.as_deref() {
+            check_chain_id_format("eth_chain_id", chain_id)?;
+        }
+        if let Some(chain_id) = self.starknet_chain_id.as_deref() {
+            check_chain_id_format("starknet_chain_id", chain_id)?;
+        }
+        if let Some(network) = self.eth_network.as_ref() {
+            check_hex("eth_network.genesis_root", &network.genesis_root, 32)?;
+            for (name, fork) in &network.forks {
+                let field = format!("eth_network.forks.{name}.fork_version");
+                check_hex(&field, &fork.fork_version, 4)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Feeder gateway serving the block data that the hashes of blocks
+    /// since 0.13.2 commit to, if known for the network.
+    pub fn feeder_gateway_url(&self) -> Option<&str> {
+        if let Some(url) = self.feeder_gateway.as_deref() {
+            return Some(url);
+        }
+        match self.network {
+            Network::MAINNET => Some(MAINNET_FEEDER_GATEWAY),
+            Network::SEPOLIA => Some(SEPOLIA_FEEDER_GATEWAY),
+            _ => None,
+        }
+    }
+
+    /// Addresses of the ETH and STRK fee tokens, which executed transactions
+    /// are charged in.
+    pub fn fee_token_addresses(&self) -> Result<(&str, &str)> {
+        let defaults = match self.network {
+            Network::MAINNET | Network::SEPOLIA => {
+                Some((ETH_FEE_TOKEN_ADDRESS, STRK_FEE_TOKEN_ADDRESS))
+            }
+            _ => None,
+        };
+        let eth = self
+            .eth_fee_token_address
+            .as_deref()
+            .or(defaults.map(|(eth, _)| eth))
+            .ok_or_else(|| eyre!("ETH fee token address is missing"))?;
+        let strk = self
+            .strk_fee_token_address
+            .as_deref()
+            .or(defaults.map(|(_, strk)| strk))
+            .ok_or_else(|| eyre!("STRK fee token address is missing"))?;
+        Ok((eth, strk))
+    }
+
+    fn expected_eth_chain_id(&self) -> Result<&str> {
+        if let Some(chain_id) = self.eth_chain_id.as_deref() {
+            return Ok(chain_id);
+        }
+        match self.network {
+            Network::MAINNET => Ok(MAINNET_ETHEREUM_CHAINID),
+            Network::SEPOLIA => Ok(SEPOLIA_ETHEREUM_CHAINID),
+            _ => eyre::bail!(
+                "Ethereum chain id check failed: unsupported network"
+            ),
+        }
+    }
+
+    pub fn expected_starknet_chain_id(&self) -> Result<&str> {
+        if let Some(chain_id) = self.starknet_chain_id.as_deref() {
+            return Ok(chain_id);
+        }
+        match self.network {
+            Network::MAINNET => Ok(MAINNET_STARKNET_CHAINID),
+            Network::SEPOLIA => Ok(SEPOLIA_STARKNET_CHAINID),
+            _ => eyre::bail!(
+                "Starknet chain id check failed: unsupported network"
+            ),
+        }
+    }
+}
+
+/// Check that the value is `len` bytes of hex, with or without `0x`.
+fn check_hex(name: &str, value: &str, len: usize) -> Result<()> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    match hex::decode(digits) {
+        Ok(bytes) if bytes.len() == len => Ok(()),
+        _ => eyre::bail!("{name} must be {len} bytes of hex: {value}"),
+    }
+}
+
+/// Check that the value is a number in hex with `0x`, the way
+/// `eth_chainId` and `starknet_chainId` return chain ids.
+fn check_chain_id_format(name: &str, value: &str) -> Result<()> {
+    match value.strip_prefix("0x") {
+        Some(digits)
+            if !digits.is_empty()
+                && digits.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Ok(())
+        }
+        _ => eyre::bail!("{name} must be a number in hex with 0x: {value}"),
+    }
+}
+
+fn check_url(name: &str, value: &str) -> Result<()> {
+    match url::Url::parse(value) {
+        Ok(_) => Ok(()),
+        Err(e) => eyre::bail!("{name} must be a URL: {value} ({e})"),
+    }
 }
 
 fn check_data_dir<P: AsRef<Path>>(path: &P) -> Result<()> {
@@ -186,8 +423,22 @@ mod tests {
             eth_execution_rpc: "foo".to_string(),
             starknet_rpc: "bar".to_string(),
             data_dir: Default::default(),
+            class_cache_bytes: DEFAULT_CLASS_CACHE_BYTES,
             poll_secs: 300,
             rpc_addr: SocketAddr::from(([0, 0, 0, 0], 3030)),
+            verify_events: false,
+            eth_methods: false,
+            settled_lookback: DEFAULT_SETTLED_LOOKBACK,
+            core_contract_address: None,
+            consensus_rpc: None,
+            fallback_rpc: None,
+            checkpoint: None,
+            eth_chain_id: None,
+            eth_network: None,
+            starknet_chain_id: None,
+            feeder_gateway: None,
+            eth_fee_token_address: None,
+            strk_fee_token_address: None,
         };
         let response = config.check().await;
 
@@ -324,8 +575,22 @@ mod tests {
             eth_execution_rpc: "foo".to_string(),
             starknet_rpc: "bar".to_string(),
             data_dir: Default::default(),
+            class_cache_bytes: DEFAULT_CLASS_CACHE_BYTES,
             poll_secs: 9999,
             rpc_addr: SocketAddr::from(([127, 0, 0, 1], 3030)),
+            verify_events: false,
+            eth_methods: false,
+            settled_lookback: DEFAULT_SETTLED_LOOKBACK,
+            core_contract_address: None,
+            consensus_rpc: None,
+            fallback_rpc: None,
+            checkpoint: None,
+            eth_chain_id: None,
+            eth_network: None,
+            starknet_chain_id: None,
+            feeder_gateway: None,
+            eth_fee_token_address: None,
+            strk_fee_token_address: None,
         };
 
         let response = config.check().await;
@@ -333,4 +598,184 @@ mod tests {
         assert!(response.is_err());
         assert!(response.unwrap_err().to_string().contains("poll_secs"));
     }
+
+    #[test]
+    fn chain_ids_of_custom_network() {
+        let mut config = Config {
+            network: Network::GOERLI,
+            eth_execution_rpc: "http://127.0.0.1:8545".to_string(),
+            starknet_rpc: "http://127.0.0.1:5050".to_string(),
+            data_dir: Default::default(),
+            class_cache_bytes: DEFAULT_CLASS_CACHE_BYTES,
+            poll_secs: 5,
+            rpc_addr: SocketAddr::from(([127, 0, 0, 1], 3030)),
+            verify_events: false,
+            eth_methods: false,
+            settled_lookback: DEFAULT_SETTLED_LOOKBACK,
+            core_contract_address: None,
+            consensus_rpc: None,
+            fallback_rpc: None,
+            checkpoint: None,
+            eth_chain_id: None,
+            eth_network: None,
+            starknet_chain_id: None,
+            feeder_gateway: None,
+            eth_fee_token_address: None,
+            strk_fee_token_address: None,
+        };
+        assert!(config.expected_eth_chain_id().is_err());
+        assert!(config.expected_starknet_chain_id().is_err());
+        assert!(config.feeder_gateway_url().is_none());
+        assert!(config.fee_token_addresses().is_err());
+
+        config.eth_chain_id = Some("0x539".to_string());
+        config.starknet_chain_id = Some("0x4b4154414e41".to_string());
+        config.eth_fee_token_address = Some("0xe7".to_string());
+        config.strk_fee_token_address = Some("0x57".to_string());
+        assert_eq!(config.expected_eth_chain_id().unwrap(), "0x539");
+        assert_eq!(
+            config.expected_starknet_chain_id().unwrap(),
+            "0x4b4154414e41"
+        );
+        assert_eq!(config.fee_token_addresses().unwrap(), ("0xe7", "0x57"));
+    }
+
+    #[test]
+    fn custom_network_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            network = "MAINNET"
+            eth_execution_rpc = "http://127.0.0.1:8545"
+            starknet_rpc = "http://127.0.0.1:5050"
+            core_contract_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+            consensus_rpc = "http://127.0.0.1:5052"
+            checkpoint = "0x85e6151a246e8fdba36db27a0c7678a575346272fe978c9281e13a8b26cdfa68"
+            eth_chain_id = "0x539"
+
+            [eth_network]
+            chain_id = 1337
+            genesis_time = 1700000000
+            genesis_root = "0xd8ea171f3c94aea21ebc42a1ed61052acf3f9209c00e4efbaaddac09ed9b8078"
+
+            [eth_network.forks]
+            genesis = { epoch = 0, fork_version = "0x10000038" }
+            deneb = { epoch = 0, fork_version = "0x50000038" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.expected_eth_chain_id().unwrap(), "0x539");
+        assert_eq!(
+            config.expected_starknet_chain_id().unwrap(),
+            MAINNET_STARKNET_CHAINID
+        );
+        assert!(config.fallback_rpc.is_none());
+        assert_eq!(config.feeder_gateway_url(), Some(MAINNET_FEEDER_GATEWAY));
+        assert_eq!(
+            config.fee_token_addresses().unwrap(),
+            (ETH_FEE_TOKEN_ADDRESS, STRK_FEE_TOKEN_ADDRESS)
+        );
+        assert!(config.validate().is_ok());
+        assert!(config.check_overrides().is_ok());
+
+        let network = config.eth_network.as_ref().unwrap();
+        assert_eq!(network.chain_id, 1337);
+        assert_eq!(network.forks["deneb"].fork_version, "0x50000038");
+    }
+
+    #[test]
+    fn malformed_overrides() {
+        let mut config = Config {
+            network: Network::MAINNET,
+            eth_execution_rpc: "http://127.0.0.1:8545".to_string(),
+            starknet_rpc: "http://127.0.0.1:5050".to_string(),
+            data_dir: Default::default(),
+            class_cache_bytes: DEFAULT_CLASS_CACHE_BYTES,
+            poll_secs: DEFAULT_POLL_SECS,
+            rpc_addr: default_rpc_addr(),
+            verify_events: false,
+            eth_methods: false,
+            settled_lookback: DEFAULT_SETTLED_LOOKBACK,
+            core_contract_address: Some(
+                "0x5FbDB2315678afecb367f032d93F642f64180aa3".to_string(),
+            ),
+            consensus_rpc: None,
+            fallback_rpc: None,
+            checkpoint: Some(format!("0x{}", "ab".repeat(32))),
+            eth_chain_id: Some("0x539".to_string()),
+            eth_network: None,
+            starknet_chain_id: None,
+            feeder_gateway: None,
+            eth_fee_token_address: None,
+            strk_fee_token_address: None,
+        };
+        assert!(config.check_overrides().is_ok());
+
+        config.eth_chain_id = Some("1337".to_string());
+        let error = config.check_overrides().unwrap_err().to_string();
+        assert!(error.contains("eth_chain_id"));
+        config.eth_chain_id = None;
+
+        // one byte short
+        config.core_contract_address =
+            Some("0x5FbDB2315678afecb367f032d93F642f64180a".to_string());
+        let error = config.check_overrides().unwrap_err().to_string();
+        assert!(error.contains("core_contract_address"));
+        config.core_contract_address = None;
+
+        config.checkpoint = Some("0xnothex".to_string());
+        let error = config.check_overrides().unwrap_err().to_string();
+        assert!(error.contains("checkpoint"));
+        config.checkpoint = None;
+
+        config.eth_network = Some(EthNetwork {
+            chain_id: 1337,
+            genesis_time: 1700000000,
+            genesis_root: format!("0x{}", "ab".repeat(32)),
+            forks: BTreeMap::from([(
+                "genesis".to_string(),
+                EthFork { epoch: 0, fork_version: "0x1000".to_string() },
+            )]),
+        });
+        let error = config.check_overrides().unwrap_err().to_string();
+        assert!(error.contains("eth_network.forks.genesis.fork_version"));
+    }
+
+    #[test]
+    fn malformed_env_override() {
+        let name = "BEERUS_TEST_MALFORMED_OVERRIDE";
+        let check = |name: &str, value: &str| check_hex(name, value, 20);
+        std::env::remove_var(name);
+        assert!(env_override(name, check).unwrap().is_none());
+
+        std::env::set_var(name, "0x5FbDB2315678afecb367f032d93F642f64180aa3");
+        assert!(env_override(name, check).unwrap().is_some());
+
+        std::env::set_var(name, "0xnothex");
+        let error = env_override(name, check).unwrap_err();
+        assert!(error.to_string().contains(name));
+        std::env::remove_var(name);
+
+        assert!(check_url("consensus_rpc", "http://127.0.0.1:5052").is_ok());
+        assert!(check_url("consensus_rpc", "127.0.0.1:5052").is_err());
+        assert!(check_chain_id_format("eth_chain_id", "0x534e").is_ok());
+        assert!(check_chain_id_format("eth_chain_id", "0x").is_err());
+        assert!(check_chain_id_format("eth_chain_id", "SN_MAIN").is_err());
+    }
+
+    #[test]
+    fn malformed_eth_network() {
+        let json = serde_json::json!({
+            "chain_id": 1337,
+            "genesis_time": 0,
+            "genesis_root": "0x00",
+            "forks": {}
+        });
+        let network = parse_eth_network(&json.to_string()).unwrap();
+        assert_eq!(network.chain_id, 1337);
+
+        let error = parse_eth_network(r#"{"chain_id":1337}"#).unwrap_err();
+        assert!(error.to_string().contains("ETH_NETWORK"));
+        assert!(parse_eth_network("mainnet").is_err());
+    }
 }
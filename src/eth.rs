@@ -1,30 +1,119 @@
 use std::str::FromStr;
 use std::sync::Arc;
 
-use ethers::types::{Address, Bytes, SyncingStatus, H256};
+use ethers::types::{Address, Bytes, Filter, SyncingStatus, H256, U256};
 use eyre::{Context, Result};
 use helios::client::{Client, ClientBuilder};
 use helios::config::checkpoints;
 use helios::config::networks::Network;
+use helios::config::Config as HeliosConfig;
 
 #[cfg(target_arch = "wasm32")]
 use helios::prelude::ConfigDB as DB;
 #[cfg(not(target_arch = "wasm32"))]
 use helios::prelude::FileDB as DB;
-use helios::types::{BlockTag, CallOpts};
+pub use helios::types::{BlockTag, CallOpts};
+use serde::Serialize;
 use tokio::sync::RwLock;
 
-use crate::config::Config;
+use crate::config::{Config, EthNetwork};
+use crate::gen;
 
 const MAINNET_CC_ADDRESS: &str = "c662c410C0ECf747543f5bA90660f6ABeBD9C8c4";
 const MAINNET_CONSENSUS_RPC: &str = "https://www.lightclientdata.org";
 const MAINNET_FALLBACK_RPC: &str = "https://sync-mainnet.beaconcha.in";
 
+/// Oldest checkpoint (two weeks, in seconds) accepted on a custom network,
+/// as on the networks Helios knows of.
+const MAX_CHECKPOINT_AGE: u64 = 1_209_600;
+
 const SEPOLIA_CC_ADDRESS: &str = "E2Bb56ee936fd6433DC0F6e7e3b8365C906AA057";
 const SEPOLIA_CONSENSUS_RPC: &str =
     "http://unstable.sepolia.beacon-api.nimbus.team";
 const SEPOLIA_FALLBACK_RPC: &str = "https://sync-sepolia.beaconcha.in";
 
+const LOG_STATE_UPDATE: &str = "LogStateUpdate(uint256,int256,uint256)";
+const LOG_MESSAGE_TO_L2: &str =
+    "LogMessageToL2(address,uint256,uint256,uint256[],uint256,uint256)";
+
+/// Message sent from L1 to L2 through the core contract, as logged by it.
+#[derive(Clone, Debug)]
+pub struct MessageToL2 {
+    pub from_address: Address,
+    pub to_address: H256,
+    pub selector: H256,
+    pub payload: Vec<H256>,
+    pub nonce: U256,
+    pub fee: U256,
+}
+
+impl MessageToL2 {
+    /// The hash the core contract keys the message by: keccak of the sender,
+    /// the recipient, the nonce, the selector and the length-prefixed payload.
+    pub fn hash(&self) -> H256 {
+        let mut data = Vec::with_capacity(32 * (5 + self.payload.len()));
+        data.extend_from_slice(H256::from(self.from_address).as_bytes());
+        data.extend_from_slice(self.to_address.as_bytes());
+        data.extend_from_slice(&as_bytes(self.nonce));
+        data.extend_from_slice(self.selector.as_bytes());
+        data.extend_from_slice(&as_bytes(self.payload.len().into()));
+        for item in &self.payload {
+            data.extend_from_slice(item.as_bytes());
+        }
+        H256::from(ethers::utils::keccak256(data))
+    }
+
+    /// The message as the L1 handler of the recipient receives it on L2.
+    pub fn msg_from_l1(&self) -> Result<gen::MsgFromL1> {
+        let from_address = format!("{:#x}", self.from_address);
+        Ok(gen::MsgFromL1 {
+            entry_point_selector: as_felt(self.selector)?,
+            from_address: gen::EthAddress::try_new(&from_address)?,
+            payload: self
+                .payload
+                .iter()
+                .copied()
+                .map(as_felt)
+                .collect::<Result<Vec<_>>>()?,
+            to_address: gen::Address(as_felt(self.to_address)?),
+        })
+    }
+
+    /// The nonce of the L1 handler transaction that consumes the message.
+    pub fn l1_handler_nonce(&self) -> Result<gen::Felt> {
+        as_felt(H256(as_bytes(self.nonce)))
+    }
+}
+
+/// The hash the core contract keys an L2->L1 message by: keccak of the
+/// sender, the recipient and the length-prefixed payload.
+pub fn message_to_l1_hash(message: &gen::MsgToL1) -> Result<H256> {
+    let mut data = Vec::with_capacity(32 * (3 + message.payload.len()));
+    data.extend_from_slice(&felt_bytes(&message.from_address)?);
+    data.extend_from_slice(&felt_bytes(&message.to_address)?);
+    data.extend_from_slice(&as_bytes(message.payload.len().into()));
+    for item in &message.payload {
+        data.extend_from_slice(&felt_bytes(item)?);
+    }
+    Ok(H256::from(ethers::utils::keccak256(data)))
+}
+
+/// Status of an L1->L2 message on the core contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MessageToL2Status {
+    /// Sent but not consumed on L2 (nor cancelled) yet.
+    Pending,
+    /// Consumed on L2.
+    Consumed,
+    /// Cancelled by the sender on L1 before being consumed.
+    Cancelled,
+    /// Not waiting to be consumed nor cancelled: the core contract keeps no
+    /// trace of consumed messages, so it was either consumed on L2 or never
+    /// sent at all.
+    Unknown,
+}
+
 pub struct EthereumClient {
     helios: Arc<RwLock<Client<DB>>>,
     starknet_core_contract_address: Address,
@@ -98,6 +187,159 @@ impl EthereumClient {
         Ok((block_number, block_hash, root))
     }
 
+    /// Return the Starknet states settled on L1 within the given range of L1
+    /// blocks, as logged by the core contract: the block number, block hash
+    /// and state root of every update, in the order they were settled.
+    pub async fn state_updates(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(u64, H256, H256)>> {
+        let topic = H256::from(ethers::utils::keccak256(LOG_STATE_UPDATE));
+        let filter = Filter::new()
+            .address(self.starknet_core_contract_address)
+            .topic0(topic)
+            .from_block(from_block)
+            .to_block(to_block);
+
+        let logs = self
+            .helios
+            .read()
+            .await
+            .get_logs(&filter)
+            .await
+            .context("helios: get logs")?;
+
+        logs.into_iter().map(|log| parse_state_update(&log.data)).collect()
+    }
+
+    /// Return the L1->L2 messages sent by the L1 transaction with the given
+    /// hash, read from the logs of its (verified) receipt.
+    pub async fn messages_to_l2(
+        &self,
+        transaction_hash: H256,
+    ) -> Result<Vec<MessageToL2>> {
+        let receipt = self
+            .helios
+            .read()
+            .await
+            .get_transaction_receipt(&transaction_hash)
+            .await
+            .context("helios: get transaction receipt")?
+            .ok_or_else(|| eyre::eyre!("Transaction not found"))?;
+
+        let topic = H256::from(ethers::utils::keccak256(LOG_MESSAGE_TO_L2));
+        receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == self.starknet_core_contract_address)
+            .filter(|log| log.topics.first() == Some(&topic))
+            .map(|log| parse_message_to_l2(&log.topics, &log.data))
+            .collect()
+    }
+
+    /// Return the status of the L1->L2 message with the given hash, as
+    /// recorded by the core contract at the latest L1 block.
+    pub async fn message_to_l2_status(
+        &self,
+        message_hash: H256,
+    ) -> Result<MessageToL2Status> {
+        let (number, _) = self.latest().await?;
+        let tag = BlockTag::Number(number);
+
+        // fee + 1 while the message is waiting to be consumed, 0 otherwise
+        let data = [
+            &ethers::utils::id("l1ToL2Messages(bytes32)")[..],
+            message_hash.as_bytes(),
+        ]
+        .concat();
+        let pending: H256 =
+            self.call(&data, tag).await.context("helios: l1ToL2Messages")?;
+        if !pending.is_zero() {
+            return Ok(MessageToL2Status::Pending);
+        }
+
+        // the time the cancellation was requested, kept once it is done
+        let data = [
+            &ethers::utils::id("l1ToL2MessageCancellations(bytes32)")[..],
+            message_hash.as_bytes(),
+        ]
+        .concat();
+        let cancelled: H256 = self
+            .call(&data, tag)
+            .await
+            .context("helios: l1ToL2MessageCancellations")?;
+        if !cancelled.is_zero() {
+            return Ok(MessageToL2Status::Cancelled);
+        }
+
+        Ok(MessageToL2Status::Unknown)
+    }
+
+    /// Return how many times the L2->L1 message with the given hash can be
+    /// consumed on L1, as recorded by the core contract at the latest L1
+    /// block. The count only goes up once the L2 block that sent the message
+    /// is settled on L1, and down when the message is consumed.
+    pub async fn message_to_l1_count(&self, message_hash: H256) -> Result<u64> {
+        let (number, _) = self.latest().await?;
+        let tag = BlockTag::Number(number);
+
+        let data = [
+            &ethers::utils::id("l2ToL1Messages(bytes32)")[..],
+            message_hash.as_bytes(),
+        ]
+        .concat();
+        let count: H256 =
+            self.call(&data, tag).await.context("helios: l2ToL1Messages")?;
+        let count = U256::from_big_endian(count.as_bytes());
+        if count > U256::from(u64::MAX) {
+            eyre::bail!("Unexpected message count: {count}");
+        }
+        Ok(count.low_u64())
+    }
+
+    /// Execute a read-only call against the L1 state at the given block.
+    pub async fn eth_call(
+        &self,
+        opts: &CallOpts,
+        tag: BlockTag,
+    ) -> Result<Bytes> {
+        let ret = self
+            .helios
+            .read()
+            .await
+            .call(opts, tag)
+            .await
+            .context("helios: call")?;
+        Ok(Bytes::from(ret))
+    }
+
+    pub async fn eth_get_balance(
+        &self,
+        address: Address,
+        tag: BlockTag,
+    ) -> Result<U256> {
+        let balance = self
+            .helios
+            .read()
+            .await
+            .get_balance(&address, tag)
+            .await
+            .context("helios: get balance")?;
+        Ok(balance)
+    }
+
+    pub async fn eth_block_number(&self) -> Result<u64> {
+        let block_number = self
+            .helios
+            .read()
+            .await
+            .get_block_number()
+            .await
+            .context("helios: get block number")?;
+        Ok(block_number.as_u64())
+    }
+
     async fn call<const N: usize, T: From<[u8; N]>>(
         &self,
         data: &[u8],
@@ -128,20 +370,117 @@ impl EthereumClient {
     }
 }
 
+/// Parse the data of `LogStateUpdate(globalRoot, blockNumber, blockHash)`.
+fn parse_state_update(data: &[u8]) -> Result<(u64, H256, H256)> {
+    if data.len() != 3 * 32 {
+        eyre::bail!("Expected {} bytes but got {}!", 3 * 32, data.len());
+    }
+    let root = H256::from_slice(&data[0..32]);
+    // An int256: anything negative or beyond u64 is not a block number.
+    if data[32..56].iter().any(|byte| *byte != 0) {
+        eyre::bail!(
+            "Block number out of range: 0x{}",
+            hex::encode(&data[32..64])
+        );
+    }
+    let block_number: [u8; 8] = data[56..64].try_into().unwrap();
+    let block_number = u64::from_be_bytes(block_number);
+    let block_hash = H256::from_slice(&data[64..96]);
+    Ok((block_number, block_hash, root))
+}
+
+/// Parse `LogMessageToL2(fromAddress, toAddress, selector, payload, nonce,
+/// fee)`, where the first three are indexed (topics) and the rest is data.
+fn parse_message_to_l2(topics: &[H256], data: &[u8]) -> Result<MessageToL2> {
+    let [_, from_address, to_address, selector] = topics else {
+        eyre::bail!("Expected 4 topics but got {}!", topics.len());
+    };
+    let word = |index: usize| -> Result<U256> {
+        let end = index
+            .checked_add(1)
+            .and_then(|next| next.checked_mul(32))
+            .ok_or_else(|| eyre::eyre!("Message data offset overflow"))?;
+        let bytes = data
+            .get(end - 32..end)
+            .ok_or_else(|| eyre::eyre!("Message data is too short"))?;
+        Ok(U256::from_big_endian(bytes))
+    };
+    let usize_word = |index: usize| -> Result<usize> {
+        let value = word(index)?;
+        if value > U256::from(usize::MAX) {
+            eyre::bail!("Message data word {value} is out of range");
+        }
+        Ok(value.as_usize())
+    };
+
+    let offset = usize_word(0)?;
+    if offset % 32 != 0 {
+        eyre::bail!("Message payload offset {offset} is not word-aligned");
+    }
+    let offset = offset / 32;
+    let nonce = word(1)?;
+    let fee = word(2)?;
+    let len = usize_word(offset)?;
+    let payload = (0..len)
+        .map(|index| {
+            let index = offset
+                .checked_add(1)
+                .and_then(|start| start.checked_add(index))
+                .ok_or_else(|| eyre::eyre!("Message data offset overflow"))?;
+            word(index).map(|item| H256(as_bytes(item)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(MessageToL2 {
+        from_address: Address::from(*from_address),
+        to_address: *to_address,
+        selector: *selector,
+        payload,
+        nonce,
+        fee,
+    })
+}
+
+fn as_felt(value: H256) -> Result<gen::Felt> {
+    let value = U256::from_big_endian(value.as_bytes());
+    Ok(gen::Felt::try_new(&format!("{value:#x}"))?)
+}
+
+fn felt_bytes(value: &gen::Felt) -> Result<[u8; 32]> {
+    let value = value.as_ref().trim_start_matches("0x");
+    Ok(as_bytes(U256::from_str_radix(value, 16)?))
+}
+
+fn as_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
 async fn get_client(config: &Config) -> Result<Client<DB>> {
     let consensus_rpc =
         get_consensus_rpc(config).context("consensus rpc url")?;
-    let fallback_rpc =
-        get_fallback_address(config).context("fallback rpc url")?;
+    let fallback_rpc = get_fallback_address(config);
     let checkpoint = get_checkpoint(config).await.context("checkpoint")?;
 
-    let builder = ClientBuilder::new()
-        .network(config.network)
+    // A custom network comes with its own chain and fork schedule.
+    let builder = match config.eth_network.as_ref() {
+        Some(network) => ClientBuilder::new().config(helios_config(network)?),
+        None => ClientBuilder::new().network(config.network),
+    };
+    let builder = builder
         .consensus_rpc(consensus_rpc)
         .execution_rpc(&config.eth_execution_rpc)
-        .checkpoint(&checkpoint)
-        .load_external_fallback()
-        .fallback(fallback_rpc);
+        .checkpoint(&checkpoint);
+
+    // Without a known fallback (e.g. a local devnet), the configured
+    // checkpoint is the only one used.
+    let builder = match fallback_rpc {
+        Some(fallback_rpc) => {
+            builder.load_external_fallback().fallback(fallback_rpc)
+        }
+        None => builder,
+    };
 
     #[cfg(not(target_arch = "wasm32"))]
     let builder = builder.data_dir(config.data_dir.clone());
@@ -149,7 +488,27 @@ async fn get_client(config: &Config) -> Result<Client<DB>> {
     builder.build()
 }
 
+/// Helios config carrying the chain and forks of a custom network, all the
+/// other settings being set on the builder.
+fn helios_config(network: &EthNetwork) -> Result<HeliosConfig> {
+    let chain = serde_json::json!({
+        "chain_id": network.chain_id,
+        "genesis_time": network.genesis_time,
+        "genesis_root": network.genesis_root,
+    });
+    Ok(HeliosConfig {
+        chain: serde_json::from_value(chain).context("chain")?,
+        forks: serde_json::from_value(serde_json::to_value(&network.forks)?)
+            .context("forks")?,
+        max_checkpoint_age: MAX_CHECKPOINT_AGE,
+        ..Default::default()
+    })
+}
+
 fn get_core_contract_address(config: &Config) -> Result<Address> {
+    if let Some(address) = config.core_contract_address.as_deref() {
+        return Ok(Address::from_str(address)?);
+    }
     match config.network {
         Network::MAINNET => Ok(Address::from_str(MAINNET_CC_ADDRESS)?),
         Network::SEPOLIA => Ok(Address::from_str(SEPOLIA_CC_ADDRESS)?),
@@ -158,6 +517,9 @@ fn get_core_contract_address(config: &Config) -> Result<Address> {
 }
 
 fn get_consensus_rpc(config: &Config) -> Result<&str> {
+    if let Some(consensus_rpc) = config.consensus_rpc.as_deref() {
+        return Ok(consensus_rpc);
+    }
     match config.network {
         Network::MAINNET => Ok(MAINNET_CONSENSUS_RPC),
         Network::SEPOLIA => Ok(SEPOLIA_CONSENSUS_RPC),
@@ -165,15 +527,21 @@ fn get_consensus_rpc(config: &Config) -> Result<&str> {
     }
 }
 
-fn get_fallback_address(config: &Config) -> Result<&str> {
+fn get_fallback_address(config: &Config) -> Option<&str> {
+    if let Some(fallback_rpc) = config.fallback_rpc.as_deref() {
+        return Some(fallback_rpc);
+    }
     match config.network {
-        Network::MAINNET => Ok(MAINNET_FALLBACK_RPC),
-        Network::SEPOLIA => Ok(SEPOLIA_FALLBACK_RPC),
-        network => eyre::bail!("unsupported network: {network:?}"),
+        Network::MAINNET => Some(MAINNET_FALLBACK_RPC),
+        Network::SEPOLIA => Some(SEPOLIA_FALLBACK_RPC),
+        _ => None,
     }
 }
 
 async fn get_checkpoint(config: &Config) -> Result<String> {
+    if let Some(checkpoint) = config.checkpoint.as_deref() {
+        return Ok(checkpoint.trim_start_matches("0x").to_owned());
+    }
     if !matches!(config.network, Network::MAINNET | Network::SEPOLIA) {
         eyre::bail!("unsupported network: {:?}", config.network);
     }
@@ -182,3 +550,153 @@ async fn get_checkpoint(config: &Config) -> Result<String> {
     let checkpoint = cf.fetch_latest_checkpoint(&config.network).await?;
     Ok(format!("{checkpoint:x}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_update(block_number: [u8; 32]) -> Vec<u8> {
+        [[0x11; 32], block_number, [0x22; 32]].concat()
+    }
+
+    #[test]
+    fn test_parse_state_update() {
+        let mut block_number = [0u8; 32];
+        block_number[24..].copy_from_slice(&652076u64.to_be_bytes());
+
+        let (number, hash, root) =
+            parse_state_update(&state_update(block_number)).unwrap();
+
+        assert_eq!(number, 652076);
+        assert_eq!(hash, H256::repeat_byte(0x22));
+        assert_eq!(root, H256::repeat_byte(0x11));
+    }
+
+    #[test]
+    fn test_parse_state_update_out_of_range() {
+        // -1 as an int256
+        assert!(parse_state_update(&state_update([0xff; 32])).is_err());
+
+        let mut block_number = [0u8; 32];
+        block_number[23] = 1;
+        assert!(parse_state_update(&state_update(block_number)).is_err());
+
+        assert!(parse_state_update(&[0u8; 64]).is_err());
+    }
+
+    fn word(value: u64) -> [u8; 32] {
+        as_bytes(value.into())
+    }
+
+    fn message_to_l2(payload: Vec<H256>) -> MessageToL2 {
+        MessageToL2 {
+            from_address: "0xae0Ee0A63A2cE6BaeEFFE56e7714FB4EFE48D419"
+                .parse()
+                .unwrap(),
+            to_address: "0x073314940630fd6dcda0d772d4c972c4e0a9946bef9dabf4ef84eda8ef542b82"
+                .parse()
+                .unwrap(),
+            selector: "0x02d757788a8d8d6f21d1cd40bce38a8222d70654214e96ff95d8086e684fbee5"
+                .parse()
+                .unwrap(),
+            payload,
+            nonce: 1234.into(),
+            fee: 10000.into(),
+        }
+    }
+
+    #[test]
+    fn test_message_to_l2_hash() {
+        let payload = [0xdead, 0x10, 0].map(|item| H256(word(item))).to_vec();
+        assert_eq!(
+            message_to_l2(payload).hash(),
+            "0xdd5b1f3f42b171a2f6057d82c3b3f33e20905cbd9a6bead696aac2cc92ad7c2a"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            message_to_l2(vec![]).hash(),
+            "0x1b9f13edbacd9ce968d3ad293e78b671d5d6b9320a28d5d67161cde8c4676634"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_message_to_l2() {
+        let expected =
+            message_to_l2(vec![H256(word(0xdead)), H256(word(0x10))]);
+        let topics = [
+            H256::from_slice(&ethers::utils::keccak256(LOG_MESSAGE_TO_L2)),
+            H256::from(expected.from_address),
+            expected.to_address,
+            expected.selector,
+        ];
+        // (payload offset, nonce, fee) then the length-prefixed payload
+        let data = [word(0x60), word(1234), word(10000), word(2)]
+            .into_iter()
+            .chain(expected.payload.iter().map(|item| item.0))
+            .collect::<Vec<_>>()
+            .concat();
+
+        let message = parse_message_to_l2(&topics, &data).unwrap();
+
+        assert_eq!(message.from_address, expected.from_address);
+        assert_eq!(message.to_address, expected.to_address);
+        assert_eq!(message.selector, expected.selector);
+        assert_eq!(message.payload, expected.payload);
+        assert_eq!(message.nonce, expected.nonce);
+        assert_eq!(message.fee, expected.fee);
+        assert_eq!(message.hash(), expected.hash());
+
+        assert!(parse_message_to_l2(&topics[..3], &data).is_err());
+        let short = &data[..data.len() - 32];
+        assert!(parse_message_to_l2(&topics, short).is_err());
+
+        let mut huge = data.clone();
+        huge[..32].copy_from_slice(&word(u64::MAX));
+        assert!(parse_message_to_l2(&topics, &huge).is_err());
+
+        // offsets and lengths are neither truncated nor rounded down
+        let mut unaligned = data.clone();
+        unaligned[..32].copy_from_slice(&word(0x61));
+        assert!(parse_message_to_l2(&topics, &unaligned).is_err());
+        let mut wide = data.clone();
+        wide[..32].copy_from_slice(&as_bytes(
+            (U256::one() << 128) + U256::from(0x60),
+        ));
+        assert!(parse_message_to_l2(&topics, &wide).is_err());
+        let mut long = data.clone();
+        long[96..128]
+            .copy_from_slice(&as_bytes((U256::one() << 128) + U256::from(2)));
+        assert!(parse_message_to_l2(&topics, &long).is_err());
+    }
+
+    fn message_to_l1(payload: &[&str]) -> gen::MsgToL1 {
+        let felt = |value: &str| gen::Felt::try_new(value).unwrap();
+        gen::MsgToL1 {
+            from_address: felt(
+                "0x73314940630fd6dcda0d772d4c972c4e0a9946bef9dabf4ef84eda8ef542b82",
+            ),
+            to_address: felt("0xae0ee0a63a2ce6baeeffe56e7714fb4efe48d419"),
+            payload: payload.iter().copied().map(felt).collect(),
+        }
+    }
+
+    #[test]
+    fn test_message_to_l1_hash() {
+        assert_eq!(
+            message_to_l1_hash(&message_to_l1(&["0xdead", "0x10", "0x0"]))
+                .unwrap(),
+            "0xcb0b2e6ddbd55ad0e13a15e1f0bdc75419b633d9240bf644e15d2db3ebd25fdc"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            message_to_l1_hash(&message_to_l1(&[])).unwrap(),
+            "0x52c37fef06655248e0aa690bcf89fcafc2d2f91da19956edec48a817135d7a44"
+                .parse()
+                .unwrap()
+        );
+    }
+}
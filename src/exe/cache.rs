@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use once_cell::sync::OnceCell;
+
+use super::*;
+
+const CLASSES_DIR: &str = "classes";
+
+static CLASSES: OnceCell<ClassCache> = OnceCell::new();
+
+/// Keep the compiled classes in the `classes` directory under the data
+/// directory, so that they are neither downloaded nor compiled again, even
+/// after a restart, up to `max_bytes` of them. Only the first call takes
+/// effect. The records of declared classes only hold for one network, hence
+/// a directory per chain id.
+pub fn init<P: AsRef<Path>>(
+    data_dir: P,
+    chain_id: &str,
+    max_bytes: u64,
+) -> Result<(), Error> {
+    let dir = data_dir.as_ref().join(CLASSES_DIR).join(chain_id);
+    let cache = ClassCache::new(dir, max_bytes)?;
+    let _ = CLASSES.set(cache);
+    Ok(())
+}
+
+pub(crate) fn classes() -> Option<&'static ClassCache> {
+    CLASSES.get()
+}
+
+/// Remember that the Cairo 0 class is declared at the block, so that it is
+/// taken to be declared at every later block without being fetched again.
+pub fn set_declared_legacy_class(
+    class_hash: &gen::Felt,
+    block_number: u64,
+) -> Result<(), Error> {
+    let Some(disk) = classes() else {
+        return Ok(());
+    };
+    let class_hash = ClassHash(StarkFelt::try_from(class_hash.clone())?);
+    disk.put_declared_at(&class_hash, block_number)
+}
+
+/// The class in the form it is executed from: the program of a Cairo 0
+/// class (as the JSON blockifier loads it from) or the CASM of a Sierra one.
+pub(crate) enum CompiledClass {
+    Legacy(String),
+    Sierra(CasmContractClass),
+}
+
+/// Compiled classes stored on disk, one file per class named after the
+/// class hash. A class never changes once declared, so an entry never needs
+/// to be invalidated: the least recently used ones are only evicted when
+/// the total size of the cache goes over the limit.
+pub(crate) struct ClassCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ClassCache {
+    fn new(dir: PathBuf, max_bytes: u64) -> Result<Self, Error> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    pub(crate) fn get(&self, class_hash: &ClassHash) -> Option<CompiledClass> {
+        let (path, legacy) = [
+            (self.path(class_hash, Kind::Sierra), false),
+            (self.path(class_hash, Kind::Legacy), true),
+        ]
+        .into_iter()
+        .find(|(path, _)| path.exists())?;
+
+        let json = fs::read_to_string(&path).ok()?;
+        let class = if legacy {
+            CompiledClass::Legacy(json)
+        } else {
+            match serde_json::from_str(&json) {
+                Ok(casm_contract_class) => {
+                    CompiledClass::Sierra(casm_contract_class)
+                }
+                Err(e) => {
+                    tracing::warn!(?path, error=?e, "corrupted cached class");
+                    let _ = fs::remove_file(&path);
+                    return None;
+                }
+            }
+        };
+
+        touch(&path);
+        Some(class)
+    }
+
+    pub(crate) fn put(
+        &self,
+        class_hash: &ClassHash,
+        class: &CompiledClass,
+    ) -> Result<(), Error> {
+        let (kind, json) = match class {
+            CompiledClass::Legacy(json) => (Kind::Legacy, json.clone()),
+            CompiledClass::Sierra(casm_contract_class) => {
+                (Kind::Sierra, serde_json::to_string(casm_contract_class)?)
+            }
+        };
+
+        self.write(&self.path(class_hash, kind), json)?;
+        self.evict()
+    }
+
+    /// The lowest block the Cairo 0 class is known to be declared at.
+    pub(crate) fn declared_at(&self, class_hash: &ClassHash) -> Option<u64> {
+        let path = self.declared_path(class_hash);
+        let block_number =
+            fs::read_to_string(&path).ok()?.trim().parse().ok()?;
+        touch(&path);
+        Some(block_number)
+    }
+
+    /// Record that the Cairo 0 class is declared at the block, and so at
+    /// every later one. Cairo 0 classes have no proof of being declared, so
+    /// this saves downloading the class again only to check it.
+    pub(crate) fn put_declared_at(
+        &self,
+        class_hash: &ClassHash,
+        block_number: u64,
+    ) -> Result<(), Error> {
+        let known = self.declared_at(class_hash);
+        if known.is_some_and(|declared| declared <= block_number) {
+            return Ok(());
+        }
+        self.write(&self.declared_path(class_hash), block_number.to_string())?;
+        self.evict()
+    }
+
+    /// Write to a temporary file first, so that a concurrent reader never
+    /// sees a partially written file.
+    fn write(&self, path: &Path, contents: String) -> Result<(), Error> {
+        static SEQ: AtomicU64 = AtomicU64::new(0);
+        let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+        let tmp = self.dir.join(format!("{}.{seq}.tmp", std::process::id()));
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Remove the least recently used classes and declaration records until
+    /// the cache fits the limit. A class takes its record along with it.
+    fn evict(&self) -> Result<(), Error> {
+        let mut entries = fs::read_dir(&self.dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let meta = entry.metadata().ok()?;
+                let path = entry.path();
+                let is_entry =
+                    matches!(path.extension()?.to_str()?, "json" | "declared");
+                (meta.is_file() && is_entry).then_some((
+                    meta.modified().ok()?,
+                    meta.len(),
+                    path,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        let mut sizes = entries
+            .iter()
+            .map(|(_, len, path)| (path.clone(), *len))
+            .collect::<HashMap<_, _>>();
+        entries.sort();
+        for (_, _, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            let record = path.with_extension("declared");
+            let evicted =
+                if path == record { vec![path] } else { vec![path, record] };
+            for path in evicted {
+                // Already gone with its class, or never there.
+                let Some(len) = sizes.remove(&path) else {
+                    continue;
+                };
+                tracing::debug!(?path, "evicting cache entry");
+                remove_file(&path)?;
+                total -= len;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, class_hash: &ClassHash, kind: Kind) -> PathBuf {
+        let suffix = match kind {
+            Kind::Legacy => "v0",
+            Kind::Sierra => "v1",
+        };
+        self.dir.join(format!("{}.{suffix}.json", class_hash.0))
+    }
+
+    fn declared_path(&self, class_hash: &ClassHash) -> PathBuf {
+        self.path(class_hash, Kind::Legacy).with_extension("declared")
+    }
+}
+
+/// Set the modification time of the file, which is the last time the entry
+/// was used.
+fn touch(path: &Path) {
+    let _ = fs::File::options()
+        .append(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+/// Remove the file, unless it is already gone: evicted by a concurrent
+/// insert or another process.
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Legacy,
+    Sierra,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn class_cache(name: &str, max_bytes: u64) -> ClassCache {
+        let dir = std::env::temp_dir()
+            .join(format!("beerus-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ClassCache::new(dir, max_bytes).unwrap()
+    }
+
+    fn class_hash(value: u64) -> ClassHash {
+        ClassHash(StarkFelt::from(value))
+    }
+
+    fn set_modified(cache: &ClassCache, value: u64, time: SystemTime) {
+        let path = cache.path(&class_hash(value), Kind::Legacy);
+        let file = fs::File::options().append(true).open(path).unwrap();
+        file.set_modified(time).unwrap();
+    }
+
+    fn legacy(class: Option<CompiledClass>) -> Option<String> {
+        match class? {
+            CompiledClass::Legacy(json) => Some(json),
+            CompiledClass::Sierra(_) => None,
+        }
+    }
+
+    #[test]
+    fn cached_class_is_returned() {
+        let cache = class_cache("cached", 1024);
+        let json = r#"{"program":{}}"#.to_owned();
+        cache
+            .put(&class_hash(1), &CompiledClass::Legacy(json.clone()))
+            .unwrap();
+
+        assert_eq!(legacy(cache.get(&class_hash(1))), Some(json));
+        assert!(cache.get(&class_hash(2)).is_none());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn least_recently_used_class_is_evicted() {
+        let json = "x".repeat(100);
+        let cache = class_cache("evicted", 250);
+        // the second class is written after the first, a minute ago
+        let now = SystemTime::now();
+        for value in 1..=2 {
+            let class = CompiledClass::Legacy(json.clone());
+            cache.put(&class_hash(value), &class).unwrap();
+            let written = now - Duration::from_secs(180 - value * 60);
+            set_modified(&cache, value, written);
+        }
+        // use the first class, so that the second one is evicted instead
+        assert!(cache.get(&class_hash(1)).is_some());
+        cache.put(&class_hash(3), &CompiledClass::Legacy(json)).unwrap();
+
+        assert!(cache.get(&class_hash(1)).is_some());
+        assert!(cache.get(&class_hash(2)).is_none());
+        assert!(cache.get(&class_hash(3)).is_some());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn lowest_declared_block_is_kept() {
+        let json = "x".repeat(100);
+        let cache = class_cache("declared", 150);
+        assert_eq!(cache.declared_at(&class_hash(1)), None);
+        cache
+            .put(&class_hash(1), &CompiledClass::Legacy(json.clone()))
+            .unwrap();
+        cache.put_declared_at(&class_hash(1), 20).unwrap();
+        cache.put_declared_at(&class_hash(1), 30).unwrap();
+        assert_eq!(cache.declared_at(&class_hash(1)), Some(20));
+        cache.put_declared_at(&class_hash(1), 10).unwrap();
+        assert_eq!(cache.declared_at(&class_hash(1)), Some(10));
+
+        // the record goes away with the class
+        cache.put(&class_hash(2), &CompiledClass::Legacy(json)).unwrap();
+        assert!(cache.get(&class_hash(1)).is_none());
+        assert_eq!(cache.declared_at(&class_hash(1)), None);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn declared_records_count_towards_the_limit() {
+        let cache = class_cache("records", 12);
+        // records of classes that are not cached
+        for value in 1..=3 {
+            cache.put_declared_at(&class_hash(value), 1000 + value).unwrap();
+            let path = cache.declared_path(&class_hash(value));
+            let file = fs::File::options().append(true).open(path).unwrap();
+            let written =
+                SystemTime::now() - Duration::from_secs(180 - value * 60);
+            file.set_modified(written).unwrap();
+        }
+        cache.put_declared_at(&class_hash(4), 1004).unwrap();
+
+        assert_eq!(cache.declared_at(&class_hash(1)), None);
+        assert_eq!(cache.declared_at(&class_hash(2)), Some(1002));
+        assert_eq!(cache.declared_at(&class_hash(3)), Some(1003));
+        assert_eq!(cache.declared_at(&class_hash(4)), Some(1004));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
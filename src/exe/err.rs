@@ -27,6 +27,10 @@ pub enum Error {
     Program(#[from] cairo_vm::types::errors::program_errors::ProgramError),
     #[error("sierra compilation error: {0:?}")]
     SierraCompilation(#[from] StarknetSierraCompilationError),
+    #[error("proof error: {0:?}")]
+    Proof(#[from] crate::proof::ProofError),
+    #[error("transaction {index} failed: {error}")]
+    Execution { index: usize, error: String },
     #[error("{0}")]
     Custom(&'static str),
 }
@@ -43,6 +47,9 @@ impl From<Error> for iamgroot::jsonrpc::Error {
     fn from(error: Error) -> Self {
         match error {
             Error::IamGroot(e) => e,
+            e @ Error::Execution { .. } => {
+                iamgroot::jsonrpc::Error { code: 41, message: e.to_string() }
+            }
             e => iamgroot::jsonrpc::Error { code: 500, message: e.to_string() },
         }
     }
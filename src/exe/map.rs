@@ -1,8 +1,19 @@
-use blockifier::execution::contract_class::ContractClassV0;
+use std::collections::BTreeMap;
+
+use blockifier::execution::contract_class::{ClassInfo, ContractClassV0};
+use blockifier::transaction::transactions::{
+    DeclareTransaction, DeployAccountTransaction, InvokeTransaction,
+    L1HandlerTransaction,
+};
 use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
 use cairo_lang_starknet_classes::contract_class::ContractClass as CairoContractClass;
+use serde::Serialize;
+use starknet_api::core::calculate_contract_address;
+use starknet_api::data_availability::DataAvailabilityMode;
+use starknet_crypto::FieldElement;
 
 use self::gen::DeprecatedContractClass;
+use crate::class::{legacy_class_hash, sierra_class_hash};
 
 use super::*;
 
@@ -43,20 +54,8 @@ impl TryFrom<gen::GetClassResult> for ContractClass {
 
     fn try_from(value: gen::GetClassResult) -> Result<Self, Self::Error> {
         Ok(match value {
-            gen::GetClassResult::ContractClass(ref class) => {
-                let mut json = serde_json::to_value(&value)?;
-                if let Some(abi) = class.abi.as_ref() {
-                    let abi: serde_json::Value = serde_json::from_str(abi)?;
-                    json["abi"] = abi;
-                }
-                let contract_class: CairoContractClass =
-                    serde_json::from_value(json)?;
-                let casm_contract_class =
-                    CasmContractClass::from_contract_class(
-                        contract_class,
-                        /*add_pythonic_hints=*/ false,
-                        /*max_bytecode_size=*/ u16::MAX as usize,
-                    )?;
+            gen::GetClassResult::ContractClass(class) => {
+                let casm_contract_class = compile_sierra_class(&class)?;
                 let class = casm_contract_class.try_into()?;
 
                 ContractClass::V1(class)
@@ -69,20 +68,49 @@ impl TryFrom<gen::GetClassResult> for ContractClass {
     }
 }
 
-fn build_contract_class(
+pub(crate) fn compile_sierra_class(
+    class: &gen::ContractClass,
+) -> Result<CasmContractClass, Error> {
+    let mut json = serde_json::to_value(class)?;
+    if let Some(abi) = class.abi.as_ref() {
+        let abi: serde_json::Value = serde_json::from_str(abi)?;
+        json["abi"] = abi;
+    }
+    let contract_class: CairoContractClass = serde_json::from_value(json)?;
+    let casm_contract_class = CasmContractClass::from_contract_class(
+        contract_class,
+        /*add_pythonic_hints=*/ false,
+        /*max_bytecode_size=*/ u16::MAX as usize,
+    )?;
+    Ok(casm_contract_class)
+}
+
+pub(crate) fn build_contract_class(
     class: DeprecatedContractClass,
 ) -> Result<ContractClassV0, Error> {
+    let json = legacy_class_json(class)?;
+    legacy_contract_class(&json)
+}
+
+/// The class with its program decoded, as blockifier loads it from JSON.
+pub(crate) fn legacy_class_json(
+    class: DeprecatedContractClass,
+) -> Result<String, Error> {
     let program = decode_program(class.program.as_ref())?;
 
     let mut class = serde_json::to_value(class)?;
     class["program"] = serde_json::from_str(&program)?;
-    let json = serde_json::to_string(&class)?;
+    Ok(serde_json::to_string(&class)?)
+}
 
-    let class = ContractClassV0::try_from_json_string(&json)?;
+pub(crate) fn legacy_contract_class(
+    json: &str,
+) -> Result<ContractClassV0, Error> {
+    let class = ContractClassV0::try_from_json_string(json)?;
     Ok(class)
 }
 
-fn decode_program(program: &str) -> Result<String, Error> {
+pub(crate) fn decode_program(program: &str) -> Result<String, Error> {
     let program = decode_base64(program)?;
     let program = decompress(&program)?;
     Ok(program)
@@ -102,3 +130,410 @@ fn decompress(input: &[u8]) -> Result<String, Error> {
     gz.read_to_string(&mut result)?;
     Ok(result)
 }
+
+/// Convert the broadcasted transaction into an account transaction that
+/// blockifier can execute, computing its hash for the given chain.
+pub(crate) fn account_transaction(
+    transaction: gen::BroadcastedTxn,
+    chain_id: &gen::Felt,
+) -> Result<AccountTransaction, Error> {
+    Ok(match transaction {
+        gen::BroadcastedTxn::BroadcastedInvokeTxn(
+            gen::BroadcastedInvokeTxn(tx),
+        ) => {
+            let tx_hash =
+                transaction_hash(&gen::Txn::InvokeTxn(tx.clone()), chain_id)?;
+            AccountTransaction::Invoke(invoke_transaction(tx, tx_hash)?)
+        }
+        gen::BroadcastedTxn::BroadcastedDeployAccountTxn(
+            gen::BroadcastedDeployAccountTxn(tx),
+        ) => {
+            let tx_hash = transaction_hash(
+                &gen::Txn::DeployAccountTxn(tx.clone()),
+                chain_id,
+            )?;
+            AccountTransaction::DeployAccount(deploy_account_transaction(
+                tx, tx_hash,
+            )?)
+        }
+        gen::BroadcastedTxn::BroadcastedDeclareTxn(tx) => {
+            let (tx, class) = match tx {
+                gen::BroadcastedDeclareTxn::BroadcastedDeclareTxnV1(tx) => {
+                    let class_hash =
+                        as_felt(legacy_class_hash(&tx.contract_class)?)?;
+                    let class = gen::GetClassResult::DeprecatedContractClass(
+                        tx.contract_class.clone(),
+                    );
+                    (declare_txn(&tx, &class_hash)?, class)
+                }
+                gen::BroadcastedDeclareTxn::BroadcastedDeclareTxnV2(tx) => {
+                    let class_hash =
+                        as_felt(sierra_class_hash(&tx.contract_class)?)?;
+                    let class = gen::GetClassResult::ContractClass(
+                        tx.contract_class.clone(),
+                    );
+                    (declare_txn(&tx, &class_hash)?, class)
+                }
+                gen::BroadcastedDeclareTxn::BroadcastedDeclareTxnV3(tx) => {
+                    let class_hash =
+                        as_felt(sierra_class_hash(&tx.contract_class)?)?;
+                    let class = gen::GetClassResult::ContractClass(
+                        tx.contract_class.clone(),
+                    );
+                    (declare_txn(&tx, &class_hash)?, class)
+                }
+            };
+            let tx_hash =
+                transaction_hash(&gen::Txn::DeclareTxn(tx.clone()), chain_id)?;
+            AccountTransaction::Declare(declare_transaction(
+                tx, tx_hash, class,
+            )?)
+        }
+    })
+}
+
+/// Convert the transaction included in a block into a transaction that
+/// blockifier can execute. Declare transactions need the declared class.
+pub(crate) fn block_transaction(
+    transaction: gen::BlockTransaction,
+    class: Option<gen::GetClassResult>,
+) -> Result<Transaction, Error> {
+    let tx_hash = TransactionHash(transaction.transaction_hash.0.try_into()?);
+    Ok(match transaction.txn {
+        gen::Txn::InvokeTxn(tx) => Transaction::AccountTransaction(
+            AccountTransaction::Invoke(invoke_transaction(tx, tx_hash)?),
+        ),
+        gen::Txn::DeployAccountTxn(tx) => {
+            Transaction::AccountTransaction(AccountTransaction::DeployAccount(
+                deploy_account_transaction(tx, tx_hash)?,
+            ))
+        }
+        gen::Txn::DeclareTxn(tx) => {
+            let class = class.ok_or(Error::Custom("missing declared class"))?;
+            Transaction::AccountTransaction(AccountTransaction::Declare(
+                declare_transaction(tx, tx_hash, class)?,
+            ))
+        }
+        gen::Txn::L1HandlerTxn(tx) => {
+            Transaction::L1HandlerTransaction(L1HandlerTransaction {
+                tx: api::L1HandlerTransaction {
+                    version: TransactionVersion(tx.version.try_into()?),
+                    nonce: Nonce(StarkFelt::try_from(
+                        tx.nonce.as_ref().as_str(),
+                    )?),
+                    contract_address: contract_address(
+                        tx.function_call.contract_address,
+                    )?,
+                    entry_point_selector: EntryPointSelector(
+                        tx.function_call.entry_point_selector.try_into()?,
+                    ),
+                    calldata: calldata(tx.function_call.calldata)?,
+                },
+                tx_hash,
+                // The fee paid on L1 is not known on L2: it only has to
+                // cover the execution, which the sequencer already checked.
+                paid_fee_on_l1: Fee(u128::MAX),
+            })
+        }
+        gen::Txn::DeployTxn(_) => {
+            return Err(Error::Custom("unsupported transaction type"));
+        }
+    })
+}
+
+/// The hash of the class the declare transaction declares.
+pub(crate) fn declared_class_hash(tx: &gen::DeclareTxn) -> &gen::Felt {
+    match tx {
+        gen::DeclareTxn::DeclareTxnV0(tx) => &tx.class_hash,
+        gen::DeclareTxn::DeclareTxnV1(tx) => &tx.class_hash,
+        gen::DeclareTxn::DeclareTxnV2(tx) => &tx.class_hash,
+        gen::DeclareTxn::DeclareTxnV3(tx) => &tx.class_hash,
+    }
+}
+
+fn invoke_transaction(
+    tx: gen::InvokeTxn,
+    tx_hash: TransactionHash,
+) -> Result<InvokeTransaction, Error> {
+    let (tx, only_query) = match tx {
+        gen::InvokeTxn::InvokeTxnV0(tx) => (
+            api::InvokeTransaction::V0(api::InvokeTransactionV0 {
+                max_fee: fee(&tx.max_fee)?,
+                signature: signature(&tx.signature)?,
+                contract_address: contract_address(tx.contract_address)?,
+                entry_point_selector: EntryPointSelector(
+                    tx.entry_point_selector.try_into()?,
+                ),
+                calldata: calldata(tx.calldata)?,
+            }),
+            is_query(&tx.version)?,
+        ),
+        gen::InvokeTxn::InvokeTxnV1(tx) => (
+            api::InvokeTransaction::V1(api::InvokeTransactionV1 {
+                max_fee: fee(&tx.max_fee)?,
+                signature: signature(&tx.signature)?,
+                nonce: Nonce(tx.nonce.try_into()?),
+                sender_address: contract_address(tx.sender_address)?,
+                calldata: calldata(tx.calldata)?,
+            }),
+            is_query(&tx.version)?,
+        ),
+        gen::InvokeTxn::InvokeTxnV3(tx) => (
+            api::InvokeTransaction::V3(api::InvokeTransactionV3 {
+                resource_bounds: resource_bounds(&tx.resource_bounds)?,
+                tip: api::Tip(parse_u64(tx.tip.as_ref())?),
+                signature: signature(&tx.signature)?,
+                nonce: Nonce(tx.nonce.try_into()?),
+                sender_address: contract_address(tx.sender_address)?,
+                calldata: calldata(tx.calldata)?,
+                nonce_data_availability_mode: da_mode(
+                    &tx.nonce_data_availability_mode,
+                ),
+                fee_data_availability_mode: da_mode(
+                    &tx.fee_data_availability_mode,
+                ),
+                paymaster_data: api::PaymasterData(felts(tx.paymaster_data)?),
+                account_deployment_data: api::AccountDeploymentData(felts(
+                    tx.account_deployment_data,
+                )?),
+            }),
+            is_query(&tx.version)?,
+        ),
+    };
+    Ok(InvokeTransaction { tx, tx_hash, only_query })
+}
+
+fn deploy_account_transaction(
+    tx: gen::DeployAccountTxn,
+    tx_hash: TransactionHash,
+) -> Result<DeployAccountTransaction, Error> {
+    let (tx, only_query) = match tx {
+        gen::DeployAccountTxn::DeployAccountTxnV1(tx) => (
+            api::DeployAccountTransaction::V1(
+                api::DeployAccountTransactionV1 {
+                    max_fee: fee(&tx.max_fee)?,
+                    signature: signature(&tx.signature)?,
+                    nonce: Nonce(tx.nonce.try_into()?),
+                    class_hash: ClassHash(tx.class_hash.try_into()?),
+                    contract_address_salt: api::ContractAddressSalt(
+                        tx.contract_address_salt.try_into()?,
+                    ),
+                    constructor_calldata: calldata(tx.constructor_calldata)?,
+                },
+            ),
+            is_query(&tx.version)?,
+        ),
+        gen::DeployAccountTxn::DeployAccountTxnV3(tx) => (
+            api::DeployAccountTransaction::V3(
+                api::DeployAccountTransactionV3 {
+                    resource_bounds: resource_bounds(&tx.resource_bounds)?,
+                    tip: api::Tip(parse_u64(tx.tip.as_ref())?),
+                    signature: signature(&tx.signature)?,
+                    nonce: Nonce(tx.nonce.try_into()?),
+                    class_hash: ClassHash(tx.class_hash.try_into()?),
+                    contract_address_salt: api::ContractAddressSalt(
+                        tx.contract_address_salt.try_into()?,
+                    ),
+                    constructor_calldata: calldata(tx.constructor_calldata)?,
+                    nonce_data_availability_mode: da_mode(
+                        &tx.nonce_data_availability_mode,
+                    ),
+                    fee_data_availability_mode: da_mode(
+                        &tx.fee_data_availability_mode,
+                    ),
+                    paymaster_data: api::PaymasterData(felts(
+                        tx.paymaster_data,
+                    )?),
+                },
+            ),
+            is_query(&tx.version)?,
+        ),
+    };
+    let contract_address = calculate_contract_address(
+        tx.contract_address_salt(),
+        tx.class_hash(),
+        &tx.constructor_calldata(),
+        ContractAddress::default(),
+    )?;
+    Ok(DeployAccountTransaction { tx, tx_hash, contract_address, only_query })
+}
+
+fn declare_transaction(
+    tx: gen::DeclareTxn,
+    tx_hash: TransactionHash,
+    class: gen::GetClassResult,
+) -> Result<DeclareTransaction, Error> {
+    let (tx, only_query) = match tx {
+        gen::DeclareTxn::DeclareTxnV0(tx) => (
+            api::DeclareTransaction::V0(api::DeclareTransactionV0V1 {
+                max_fee: fee(&tx.max_fee)?,
+                signature: signature(&tx.signature)?,
+                nonce: Nonce::default(),
+                class_hash: ClassHash(tx.class_hash.try_into()?),
+                sender_address: contract_address(tx.sender_address)?,
+            }),
+            is_query(&tx.version)?,
+        ),
+        gen::DeclareTxn::DeclareTxnV1(tx) => (
+            api::DeclareTransaction::V1(api::DeclareTransactionV0V1 {
+                max_fee: fee(&tx.max_fee)?,
+                signature: signature(&tx.signature)?,
+                nonce: Nonce(tx.nonce.try_into()?),
+                class_hash: ClassHash(tx.class_hash.try_into()?),
+                sender_address: contract_address(tx.sender_address)?,
+            }),
+            is_query(&tx.version)?,
+        ),
+        gen::DeclareTxn::DeclareTxnV2(tx) => (
+            api::DeclareTransaction::V2(api::DeclareTransactionV2 {
+                max_fee: fee(&tx.max_fee)?,
+                signature: signature(&tx.signature)?,
+                nonce: Nonce(tx.nonce.try_into()?),
+                class_hash: ClassHash(tx.class_hash.try_into()?),
+                compiled_class_hash: CompiledClassHash(
+                    tx.compiled_class_hash.try_into()?,
+                ),
+                sender_address: contract_address(tx.sender_address)?,
+            }),
+            is_query(&tx.version)?,
+        ),
+        gen::DeclareTxn::DeclareTxnV3(tx) => (
+            api::DeclareTransaction::V3(api::DeclareTransactionV3 {
+                resource_bounds: resource_bounds(&tx.resource_bounds)?,
+                tip: api::Tip(parse_u64(tx.tip.as_ref())?),
+                signature: signature(&tx.signature)?,
+                nonce: Nonce(tx.nonce.try_into()?),
+                class_hash: ClassHash(tx.class_hash.try_into()?),
+                compiled_class_hash: CompiledClassHash(
+                    tx.compiled_class_hash.try_into()?,
+                ),
+                sender_address: contract_address(tx.sender_address)?,
+                nonce_data_availability_mode: da_mode(
+                    &tx.nonce_data_availability_mode,
+                ),
+                fee_data_availability_mode: da_mode(
+                    &tx.fee_data_availability_mode,
+                ),
+                paymaster_data: api::PaymasterData(felts(tx.paymaster_data)?),
+                account_deployment_data: api::AccountDeploymentData(felts(
+                    tx.account_deployment_data,
+                )?),
+            }),
+            is_query(&tx.version)?,
+        ),
+    };
+    let class_info = class_info(class)?;
+    let declare = if only_query {
+        DeclareTransaction::new_for_query(tx, tx_hash, class_info)?
+    } else {
+        DeclareTransaction::new(tx, tx_hash, class_info)?
+    };
+    Ok(declare)
+}
+
+/// The declare transaction as it is hashed: the same fields as broadcasted,
+/// with the class replaced by its hash.
+fn declare_txn<T: Serialize>(
+    tx: &T,
+    class_hash: &gen::Felt,
+) -> Result<gen::DeclareTxn, Error> {
+    let mut json = serde_json::to_value(tx)?;
+    if let Some(fields) = json.as_object_mut() {
+        fields.remove("contract_class");
+        fields
+            .insert("class_hash".to_owned(), serde_json::to_value(class_hash)?);
+    }
+    Ok(serde_json::from_value(json)?)
+}
+
+fn class_info(class: gen::GetClassResult) -> Result<ClassInfo, Error> {
+    let (sierra_program_length, abi_length) = match &class {
+        gen::GetClassResult::ContractClass(class) => (
+            class.sierra_program.len(),
+            class.abi.as_ref().map(String::len).unwrap_or_default(),
+        ),
+        gen::GetClassResult::DeprecatedContractClass(_) => (0, 0),
+    };
+    let class = ContractClass::try_from(class)?;
+    ClassInfo::new(&class, sierra_program_length, abi_length)
+        .map_err(|_| Error::Custom("invalid class info"))
+}
+
+fn transaction_hash(
+    txn: &gen::Txn,
+    chain_id: &gen::Felt,
+) -> Result<TransactionHash, Error> {
+    let hash = crate::transaction::transaction_hash(txn, chain_id)?;
+    Ok(TransactionHash(hash.try_into()?))
+}
+
+/// Query versions have the 2^128 bit set, and are never accepted on chain.
+fn is_query<T: Serialize>(version: &T) -> Result<bool, Error> {
+    let version = serde_json::to_value(version)?;
+    let version = version.as_str().unwrap_or_default();
+    let version = FieldElement::from_hex_be(version)
+        .map_err(|_| Error::Custom("invalid transaction version"))?;
+    Ok(version.to_bytes_be() >= query_version_base().to_bytes_be())
+}
+
+/// 2^128, added to the version of query transactions.
+fn query_version_base() -> FieldElement {
+    FieldElement::from(u128::MAX) + FieldElement::ONE
+}
+
+fn resource_bounds(
+    resource_bounds: &gen::ResourceBoundsMapping,
+) -> Result<api::ResourceBoundsMapping, Error> {
+    let bounds = |bounds: &gen::ResourceBounds| -> Result<_, Error> {
+        Ok(api::ResourceBounds {
+            max_amount: parse_u64(bounds.max_amount.as_ref())?,
+            max_price_per_unit: parse_int(bounds.max_price_per_unit.as_ref())?,
+        })
+    };
+    Ok(api::ResourceBoundsMapping(BTreeMap::from([
+        (api::Resource::L1Gas, bounds(&resource_bounds.l1_gas)?),
+        (api::Resource::L2Gas, bounds(&resource_bounds.l2_gas)?),
+    ])))
+}
+
+fn da_mode(mode: &gen::DaMode) -> DataAvailabilityMode {
+    match mode {
+        gen::DaMode::L1 => DataAvailabilityMode::L1,
+        gen::DaMode::L2 => DataAvailabilityMode::L2,
+    }
+}
+
+fn fee(max_fee: &gen::Felt) -> Result<Fee, Error> {
+    Ok(Fee(parse_int(max_fee.as_ref())?))
+}
+
+fn signature(signature: &[gen::Felt]) -> Result<TransactionSignature, Error> {
+    Ok(TransactionSignature(felts(signature.to_vec())?))
+}
+
+fn calldata(calldata: Vec<gen::Felt>) -> Result<Calldata, Error> {
+    Ok(Calldata(Arc::new(felts(calldata)?)))
+}
+
+fn contract_address(address: gen::Address) -> Result<ContractAddress, Error> {
+    let felt: StarkFelt = address.0.try_into()?;
+    Ok(ContractAddress(felt.try_into()?))
+}
+
+fn felts(felts: Vec<gen::Felt>) -> Result<Vec<StarkFelt>, Error> {
+    felts.into_iter().map(TryInto::try_into).collect()
+}
+
+fn as_felt(felt: FieldElement) -> Result<gen::Felt, Error> {
+    Ok(gen::Felt::try_new(&format!("0x{felt:x}"))?)
+}
+
+pub(crate) fn parse_int(hex: &str) -> Result<u128, Error> {
+    u128::from_str_radix(hex.trim_start_matches("0x"), 16)
+        .map_err(|_| Error::Custom("invalid integer"))
+}
+
+fn parse_u64(hex: &str) -> Result<u64, Error> {
+    u64::try_from(parse_int(hex)?)
+        .map_err(|_| Error::Custom("integer out of range"))
+}
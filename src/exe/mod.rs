@@ -1,6 +1,11 @@
-use std::{collections::HashSet, num::NonZeroU128, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU128,
+    sync::Arc,
+};
 
 use blockifier::{
+    abi::constants as abi_constants,
     block::{BlockInfo, GasPrices},
     context::{BlockContext, ChainInfo, FeeTokenAddresses, TransactionContext},
     execution::{
@@ -10,16 +15,24 @@ use blockifier::{
         entry_point::{CallEntryPoint, CallType, EntryPointExecutionContext},
     },
     state::{
-        cached_state::CommitmentStateDiff,
+        cached_state::{CachedState, CommitmentStateDiff},
         errors::StateError,
         state_api::{State as BlockifierState, StateReader, StateResult},
     },
-    transaction::objects::{
-        CommonAccountFields, DeprecatedTransactionInfo, TransactionInfo,
+    transaction::{
+        account_transaction::AccountTransaction,
+        objects::{
+            CommonAccountFields, DeprecatedTransactionInfo,
+            TransactionExecutionInfo, TransactionInfo,
+        },
+        transaction_execution::Transaction,
+        transactions::ExecutableTransaction,
     },
     versioned_constants::VersionedConstants,
 };
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
 use cairo_vm::vm::runners::cairo_runner::ExecutionResources;
+use once_cell::sync::OnceCell;
 use starknet_api::{
     block::{BlockNumber as StarknetBlockNumber, BlockTimestamp},
     core::{
@@ -30,22 +43,31 @@ use starknet_api::{
     hash::{StarkFelt, StarkHash},
     state::StorageKey as StarknetStorageKey,
     transaction::{
-        Calldata, Fee, TransactionHash, TransactionSignature,
+        self as api, Calldata, Fee, TransactionHash, TransactionSignature,
         TransactionVersion,
     },
 };
+use starknet_crypto::FieldElement;
 
+use crate::block::{GasPrice, L1DataAvailabilityMode};
 use crate::gen::{self, blocking::Rpc};
 
+pub mod cache;
 pub mod err;
 pub mod map;
+pub mod trace;
 
+use cache::CompiledClass;
 use err::Error;
 
+/// Call the function on the state after the (verified) block, where every
+/// read is verified against the state root of the block, in the context of
+/// the block on the chain with the given id.
 pub fn call(
     client: &gen::client::blocking::Client,
     function_call: gen::FunctionCall,
-    state_root: gen::Felt,
+    block_header: &gen::BlockHeader,
+    chain: &Chain,
 ) -> Result<CallInfo, Error> {
     let gen::FunctionCall { calldata, contract_address, entry_point_selector } =
         function_call;
@@ -59,36 +81,25 @@ pub fn call(
 
     let mut resources = ExecutionResources::default();
 
-    let one = NonZeroU128::new(1)
-        .ok_or_else(|| Error::Custom("NonZeroU128 is zero"))?;
-    let block_info = BlockInfo {
-        block_number: StarknetBlockNumber::default(),
-        block_timestamp: BlockTimestamp::default(),
-        sequencer_address: ContractAddress::default(),
-        gas_prices: GasPrices {
-            eth_l1_gas_price: one,
-            strk_l1_gas_price: one,
-            eth_l1_data_gas_price: one,
-            strk_l1_data_gas_price: one,
-        },
-        use_kzg_da: false,
-    };
-
-    let chain_info = ChainInfo {
-        chain_id: BlockifierChainId("00".to_owned()),
-        fee_token_addresses: FeeTokenAddresses {
-            strk_fee_token_address: ContractAddress::default(),
-            eth_fee_token_address: ContractAddress::default(),
+    // A call charges no fee, so the price of data gas does not matter.
+    let data_gas = DataGas {
+        price: GasPrice {
+            price_in_wei: felt(&block_header.l1_gas_price.price_in_wei)?,
+            price_in_fri: felt(&block_header.l1_gas_price.price_in_fri)?,
         },
+        mode: L1DataAvailabilityMode::Calldata,
     };
-
-    let versioned_constants = VersionedConstants::latest_constants();
-
-    let block_context = BlockContext::new_unchecked(
-        &block_info,
-        &chain_info,
-        versioned_constants,
-    );
+    // Nor does it run out of gas, so blocks newer than the bundled constants
+    // can be called with the newest ones.
+    let version = crate::block::parse_version(&block_header.starknet_version)
+        .ok_or(Error::Custom("invalid starknet version"))?
+        .min(CONSTANTS_13_1_1_VERSION.to_vec());
+    let block_context = block_context(
+        block_header,
+        chain,
+        Some(&data_gas),
+        versioned_constants(&version)?,
+    )?;
 
     let tx_info = TransactionInfo::Deprecated(DeprecatedTransactionInfo {
         common_fields: CommonAccountFields {
@@ -125,13 +136,7 @@ pub fn call(
         initial_gas: u64::MAX,
     };
 
-    let diff = CommitmentStateDiff {
-        storage_updates: Default::default(),
-        address_to_nonce: Default::default(),
-        address_to_class_hash: Default::default(),
-        class_hash_to_compiled_class_hash: Default::default(),
-    };
-    let mut proxy = StateProxy { client: client.to_owned(), diff, state_root };
+    let mut proxy = StateProxy::new(client, pinned_state(block_header));
 
     let call_info =
         call_entry_point.execute(&mut proxy, &mut resources, &mut context)?;
@@ -140,46 +145,596 @@ pub fn call(
     Ok(call_info)
 }
 
+/// Execute the transactions one after another on top of the verified state
+/// of the block, and estimate the fee of each from the fee its execution
+/// would be charged. A reverted transaction fails the whole estimate.
+pub fn estimate_fee(
+    client: &gen::client::blocking::Client,
+    block_header: &gen::BlockHeader,
+    chain: &Chain,
+    data_gas: Option<&DataGas>,
+    transactions: Vec<gen::BroadcastedTxn>,
+    validate: bool,
+) -> Result<Vec<gen::FeeEstimate>, Error> {
+    let transactions = account_transactions(transactions, &chain.chain_id)?;
+    let (executions, _) = execute(
+        client,
+        &block_context(
+            block_header,
+            chain,
+            data_gas,
+            block_constants(block_header)?,
+        )?,
+        pinned_state(block_header),
+        transactions,
+        validate,
+        /*charge_fee=*/ false,
+    )?;
+
+    let estimates = executions
+        .iter()
+        .enumerate()
+        .map(|(index, execution)| {
+            if let Some(error) = execution.info.revert_error.clone() {
+                return Err(Error::Execution { index, error });
+            }
+            fee_estimate(block_header, execution)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    tracing::debug!(?estimates, "fee estimated");
+    Ok(estimates)
+}
+
+/// Execute the transactions one after another on top of the verified state
+/// of the block, and return the trace and the fee estimate of each.
+/// Reverted transactions are not an error: the revert reason is traced.
+pub fn simulate(
+    client: &gen::client::blocking::Client,
+    block_header: &gen::BlockHeader,
+    chain: &Chain,
+    data_gas: Option<&DataGas>,
+    transactions: Vec<gen::BroadcastedTxn>,
+    validate: bool,
+    charge_fee: bool,
+) -> Result<Vec<gen::SimulatedTransaction>, Error> {
+    let transactions = account_transactions(transactions, &chain.chain_id)?;
+    let (executions, _) = execute(
+        client,
+        &block_context(
+            block_header,
+            chain,
+            data_gas,
+            block_constants(block_header)?,
+        )?,
+        pinned_state(block_header),
+        transactions,
+        validate,
+        charge_fee,
+    )?;
+
+    executions
+        .iter()
+        .map(|execution| {
+            Ok(gen::SimulatedTransaction {
+                fee_estimation: Some(fee_estimate(block_header, execution)?),
+                transaction_trace: Some(trace::transaction_trace(
+                    &execution.transaction,
+                    &execution.info,
+                    &execution.state_diff,
+                )?),
+            })
+        })
+        .collect()
+}
+
+/// Re-execute the transactions of the block, in order, on top of the
+/// verified state of its parent block (with the given root), and return the
+/// trace of each. Declared classes are fetched from the block itself. The
+/// changes made by the whole block must match its verified state diff.
+pub fn trace_block(
+    client: &gen::client::blocking::Client,
+    block_header: &gen::BlockHeader,
+    parent_root: gen::Felt,
+    chain: &Chain,
+    data_gas: Option<&DataGas>,
+    transactions: Vec<gen::BlockTransaction>,
+    state_diff: &gen::StateDiff,
+) -> Result<Vec<gen::TransactionTrace>, Error> {
+    let parent_number = block_header
+        .block_number
+        .as_ref()
+        .checked_sub(1)
+        .filter(|number| *number >= 0)
+        .ok_or(Error::Custom("the genesis block has no parent state"))?;
+    let parent_id = gen::BlockId::BlockNumber {
+        block_number: gen::BlockNumber::try_new(parent_number)?,
+    };
+    let block_id = gen::BlockId::BlockNumber {
+        block_number: block_header.block_number.clone(),
+    };
+
+    let transactions = transactions
+        .into_iter()
+        .map(|transaction| {
+            let class = match &transaction.txn {
+                gen::Txn::DeclareTxn(tx) => {
+                    let class_hash = map::declared_class_hash(tx);
+                    Some(get_class(client, &block_id, class_hash)?)
+                }
+                _ => None,
+            };
+            map::block_transaction(transaction, class)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let (executions, block_diff) = execute(
+        client,
+        &block_context(
+            block_header,
+            chain,
+            data_gas,
+            block_constants(block_header)?,
+        )?,
+        (parent_id, parent_root),
+        transactions,
+        /*validate=*/ true,
+        /*charge_fee=*/ true,
+    )?;
+    trace::verify_state_diff(&block_diff, state_diff)?;
+
+    executions
+        .iter()
+        .map(|execution| {
+            trace::transaction_trace(
+                &execution.transaction,
+                &execution.info,
+                &execution.state_diff,
+            )
+        })
+        .collect()
+}
+
+/// Fetch the class and check that it hashes to the requested class hash.
+fn get_class(
+    client: &gen::client::blocking::Client,
+    block_id: &gen::BlockId,
+    class_hash: &gen::Felt,
+) -> Result<gen::GetClassResult, Error> {
+    let class = client.getClass(block_id.clone(), class_hash.clone())?;
+    let computed = crate::class::class_hash(&class)?;
+    if computed != crate::proof::as_field_element(class_hash)? {
+        return Err(crate::proof::ProofError::ValueMismatch {
+            name: "class hash",
+            provided: class_hash.as_ref().clone(),
+            proven: format!("0x{:x}", computed),
+        }
+        .into());
+    }
+    Ok(class)
+}
+
+/// The state after the block: the block id and its verified state root.
+fn pinned_state(block_header: &gen::BlockHeader) -> (gen::BlockId, gen::Felt) {
+    let block_id = gen::BlockId::BlockNumber {
+        block_number: block_header.block_number.clone(),
+    };
+    (block_id, block_header.new_root.clone())
+}
+
+fn account_transactions(
+    transactions: Vec<gen::BroadcastedTxn>,
+    chain_id: &gen::Felt,
+) -> Result<Vec<Transaction>, Error> {
+    transactions
+        .into_iter()
+        .map(|transaction| {
+            map::account_transaction(transaction, chain_id)
+                .map(Transaction::AccountTransaction)
+        })
+        .collect()
+}
+
+/// The result of executing a transaction, with the changes it made to the
+/// state it was executed on.
+struct Execution {
+    transaction: Transaction,
+    info: TransactionExecutionInfo,
+    state_diff: CommitmentStateDiff,
+}
+
+/// Execute the transactions one after another on top of the verified state
+/// of the block with the given id and root, each seeing the changes made by
+/// the previous ones. The changes made by all of them are returned as well.
+fn execute(
+    client: &gen::client::blocking::Client,
+    block_context: &BlockContext,
+    (block_id, state_root): (gen::BlockId, gen::Felt),
+    transactions: Vec<Transaction>,
+    validate: bool,
+    charge_fee: bool,
+) -> Result<(Vec<Execution>, CommitmentStateDiff), Error> {
+    let proxy = StateProxy::new(client, (block_id, state_root));
+    let mut state = CachedState::from(proxy);
+
+    let mut executions = Vec::with_capacity(transactions.len());
+    for (index, transaction) in transactions.into_iter().enumerate() {
+        let mut tx_state = CachedState::create_transactional(&mut state);
+        let info = match transaction.execute_raw(
+            &mut tx_state,
+            block_context,
+            charge_fee,
+            validate,
+        ) {
+            Ok(info) => info,
+            Err(e) => {
+                tx_state.abort();
+                return Err(Error::Execution { index, error: e.to_string() });
+            }
+        };
+        let state_diff = tx_state.to_state_diff();
+        tx_state.commit();
+        executions.push(Execution { transaction, info, state_diff });
+    }
+    Ok((executions, state.to_state_diff()))
+}
+
+fn fee_estimate(
+    block_header: &gen::BlockHeader,
+    execution: &Execution,
+) -> Result<gen::FeeEstimate, Error> {
+    let (unit, gas_price) = match fee_unit(&execution.transaction) {
+        gen::PriceUnit::Wei => {
+            (gen::PriceUnit::Wei, &block_header.l1_gas_price.price_in_wei)
+        }
+        gen::PriceUnit::Fri => {
+            (gen::PriceUnit::Fri, &block_header.l1_gas_price.price_in_fri)
+        }
+    };
+    let overall_fee = execution.info.actual_fee.0;
+    // Only the L1 gas: since 0.13.1 the fee also pays for L1 data gas.
+    let gas_consumed = execution
+        .info
+        .actual_resources
+        .0
+        .get(abi_constants::L1_GAS_USAGE)
+        .copied()
+        .unwrap_or_default();
+    Ok(gen::FeeEstimate {
+        gas_consumed: as_felt(gas_consumed as u128)?,
+        gas_price: gas_price.clone(),
+        overall_fee: as_felt(overall_fee)?,
+        unit,
+    })
+}
+
+/// Version 3 transactions pay fees in STRK (FRI), older ones in ETH (WEI).
+fn fee_unit(transaction: &Transaction) -> gen::PriceUnit {
+    let v3 = match transaction {
+        Transaction::AccountTransaction(AccountTransaction::Invoke(tx)) => {
+            matches!(tx.tx, api::InvokeTransaction::V3(_))
+        }
+        Transaction::AccountTransaction(AccountTransaction::Declare(tx)) => {
+            matches!(tx.tx(), api::DeclareTransaction::V3(_))
+        }
+        Transaction::AccountTransaction(AccountTransaction::DeployAccount(
+            tx,
+        )) => matches!(tx.tx, api::DeployAccountTransaction::V3(_)),
+        Transaction::L1HandlerTransaction(_) => false,
+    };
+    if v3 {
+        gen::PriceUnit::Fri
+    } else {
+        gen::PriceUnit::Wei
+    }
+}
+
+/// JSON-RPC error code of the provider for a class that is not declared.
+const CLASS_HASH_NOT_FOUND: i64 = 28;
+
+/// The first version that charges for L1 data gas (and posts state diffs
+/// to blobs), and whose constants Blockifier ships.
+pub(crate) const DATA_GAS_VERSION: [u64; 3] = [0, 13, 1];
+
+/// The first version with the constants of 0.13.1.1, which lowered the gas
+/// cost of declared code.
+const CONSTANTS_13_1_1_VERSION: [u64; 4] = [0, 13, 1, 1];
+
+/// The first version whose constants are not bundled: since 0.13.2 the OS
+/// resources of a transaction depend on its version, which Blockifier 0.5
+/// cannot express.
+const UNSUPPORTED_CONSTANTS_VERSION: [u64; 3] = [0, 13, 2];
+
+const VERSIONED_CONSTANTS_13_0: &str =
+    include_str!("resources/versioned_constants_13_0.json");
+
+const VERSIONED_CONSTANTS_13_1_1: &str =
+    include_str!("resources/versioned_constants_13_1_1.json");
+
+/// The chain the transactions are executed on: its id, and the tokens fees
+/// are charged in.
+#[derive(Clone, Debug)]
+pub struct Chain {
+    pub chain_id: gen::Felt,
+    pub eth_fee_token_address: gen::Felt,
+    pub strk_fee_token_address: gen::Felt,
+}
+
+/// The L1 data gas price of the block and the DA mode it was posted in,
+/// neither of which the block header carries. Only blocks since 0.13.1 have
+/// them.
+#[derive(Clone, Debug)]
+pub struct DataGas {
+    pub price: GasPrice,
+    pub mode: L1DataAvailabilityMode,
+}
+
+/// Build the context of the block as the sequencer had it: its number,
+/// timestamp, sequencer and gas prices, on the chain with the given id.
+fn block_context(
+    block_header: &gen::BlockHeader,
+    chain: &Chain,
+    data_gas: Option<&DataGas>,
+    constants: &VersionedConstants,
+) -> Result<BlockContext, Error> {
+    let version = crate::block::parse_version(&block_header.starknet_version)
+        .ok_or(Error::Custom("invalid starknet version"))?;
+    let eth_l1_gas_price = gas_price(&block_header.l1_gas_price.price_in_wei)?;
+    let strk_l1_gas_price = gas_price(&block_header.l1_gas_price.price_in_fri)?;
+    // Blocks before 0.13.1 have no data gas: there is nothing to charge for
+    // it, so the price only has to be non-zero.
+    let (eth_l1_data_gas_price, strk_l1_data_gas_price, use_kzg_da) =
+        match data_gas {
+            Some(data_gas) => (
+                data_gas_price(&data_gas.price.price_in_wei)?,
+                data_gas_price(&data_gas.price.price_in_fri)?,
+                data_gas.mode == L1DataAvailabilityMode::Blob,
+            ),
+            None if version.as_slice() < &DATA_GAS_VERSION[..] => {
+                (eth_l1_gas_price, strk_l1_gas_price, false)
+            }
+            None => return Err(Error::Custom("missing L1 data gas price")),
+        };
+    let sequencer_address: StarkFelt =
+        block_header.sequencer_address.clone().try_into()?;
+    let block_info = BlockInfo {
+        block_number: StarknetBlockNumber(
+            *block_header.block_number.as_ref() as u64
+        ),
+        block_timestamp: BlockTimestamp(*block_header.timestamp.as_ref() as u64),
+        sequencer_address: ContractAddress(sequencer_address.try_into()?),
+        gas_prices: GasPrices {
+            eth_l1_gas_price,
+            strk_l1_gas_price,
+            eth_l1_data_gas_price,
+            strk_l1_data_gas_price,
+        },
+        use_kzg_da,
+    };
+
+    let chain_info = ChainInfo {
+        chain_id: BlockifierChainId(chain_name(&chain.chain_id)?),
+        fee_token_addresses: FeeTokenAddresses {
+            strk_fee_token_address: ContractAddress(
+                StarkFelt::try_from(chain.strk_fee_token_address.clone())?
+                    .try_into()?,
+            ),
+            eth_fee_token_address: ContractAddress(
+                StarkFelt::try_from(chain.eth_fee_token_address.clone())?
+                    .try_into()?,
+            ),
+        },
+    };
+
+    Ok(BlockContext::new_unchecked(&block_info, &chain_info, constants))
+}
+
+/// The constants (OS resources, fee weights, limits) the block was executed
+/// with. Blockifier only ships the constants of 0.13.1, the ones of 0.13.0
+/// (which apply to every older block as well) and of 0.13.1.1 are bundled
+/// with this crate. Newer blocks are rejected rather than executed with the
+/// wrong constants.
+fn versioned_constants(
+    version: &[u64],
+) -> Result<&'static VersionedConstants, Error> {
+    static CONSTANTS_13_0: OnceCell<VersionedConstants> = OnceCell::new();
+    static CONSTANTS_13_1_1: OnceCell<VersionedConstants> = OnceCell::new();
+    let parse = |json: &str| {
+        serde_json::from_str(json)
+            .map_err(|_| Error::Custom("invalid versioned constants"))
+    };
+    if version >= &UNSUPPORTED_CONSTANTS_VERSION[..] {
+        Err(Error::Custom("no versioned constants for the starknet version"))
+    } else if version >= &CONSTANTS_13_1_1_VERSION[..] {
+        CONSTANTS_13_1_1.get_or_try_init(|| parse(VERSIONED_CONSTANTS_13_1_1))
+    } else if version >= &DATA_GAS_VERSION[..] {
+        Ok(VersionedConstants::latest_constants())
+    } else {
+        CONSTANTS_13_0.get_or_try_init(|| parse(VERSIONED_CONSTANTS_13_0))
+    }
+}
+
+/// Like [`versioned_constants`] for the version of the block.
+fn block_constants(
+    block_header: &gen::BlockHeader,
+) -> Result<&'static VersionedConstants, Error> {
+    let version = crate::block::parse_version(&block_header.starknet_version)
+        .ok_or(Error::Custom("invalid starknet version"))?;
+    versioned_constants(&version)
+}
+
+/// Decode the chain id from the short string it encodes, e.g. "SN_MAIN".
+fn chain_name(chain_id: &gen::Felt) -> Result<String, Error> {
+    let hex = chain_id.as_ref().trim_start_matches("0x");
+    let hex =
+        if hex.len() % 2 == 1 { format!("0{hex}") } else { hex.to_owned() };
+    hex::decode(hex)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or(Error::Custom("invalid chain id"))
+}
+
+/// Blockifier requires non-zero gas prices: blocks that did not set the
+/// price (e.g. STRK prices before v0.13) get the smallest possible one.
+fn gas_price(price: &gen::Felt) -> Result<NonZeroU128, Error> {
+    let price = map::parse_int(price.as_ref())?;
+    NonZeroU128::new(price)
+        .or(NonZeroU128::new(1))
+        .ok_or(Error::Custom("NonZeroU128 is zero"))
+}
+
+fn felt(value: &gen::Felt) -> Result<FieldElement, Error> {
+    FieldElement::from_hex_be(value.as_ref())
+        .map_err(|_| Error::Custom("invalid felt"))
+}
+
+/// Like [`gas_price`], for a price read from the (verified) block data.
+fn data_gas_price(price: &FieldElement) -> Result<NonZeroU128, Error> {
+    let bytes = price.to_bytes_be();
+    let (high, low) = bytes.split_at(16);
+    if high.iter().any(|byte| *byte != 0) {
+        return Err(Error::Custom("L1 data gas price exceeds u128"));
+    }
+    let price = u128::from_be_bytes(low.try_into().unwrap_or_default());
+    NonZeroU128::new(price)
+        .or(NonZeroU128::new(1))
+        .ok_or(Error::Custom("NonZeroU128 is zero"))
+}
+
+fn as_felt(value: u128) -> Result<gen::Felt, Error> {
+    Ok(gen::Felt::try_new(&format!("{value:#x}"))?)
+}
+
 struct StateProxy {
     client: gen::client::blocking::Client,
     diff: CommitmentStateDiff,
+    block_id: gen::BlockId,
     state_root: gen::Felt,
+    /// Classes declared during the execution, which the remote state of the
+    /// block knows nothing about.
+    classes: HashMap<ClassHash, ContractClass>,
+    cache: StateCache,
 }
 
-impl StateReader for StateProxy {
-    fn get_storage_at(
+/// Values already read and verified from the state of the block, so that
+/// reading them again during the execution costs no more round-trips. The
+/// proxy only ever reads the state of a single block, so the cache lives
+/// (and dies) with it and the block is implicitly part of every key.
+#[derive(Default)]
+struct StateCache {
+    storage: HashMap<(ContractAddress, StarknetStorageKey), StarkFelt>,
+    contracts: HashMap<ContractAddress, (gen::Felt, gen::Felt)>,
+    classes: HashMap<ClassHash, ContractClass>,
+    compiled_class_hashes: HashMap<ClassHash, CompiledClassHash>,
+    class_commitment: Option<gen::Felt>,
+    /// Cairo 0 classes known to be declared at the block.
+    declared_legacy_classes: HashSet<ClassHash>,
+}
+
+impl StateProxy {
+    fn new(
+        client: &gen::client::blocking::Client,
+        (block_id, state_root): (gen::BlockId, gen::Felt),
+    ) -> Self {
+        Self {
+            client: client.to_owned(),
+            diff: CommitmentStateDiff {
+                storage_updates: Default::default(),
+                address_to_nonce: Default::default(),
+                address_to_class_hash: Default::default(),
+                class_hash_to_compiled_class_hash: Default::default(),
+            },
+            block_id,
+            state_root,
+            classes: HashMap::new(),
+            cache: StateCache::default(),
+        }
+    }
+
+    /// Return the class hash and nonce of the contract, proven against the
+    /// state root. Both are zero for a contract that is not deployed.
+    fn get_contract_state(
+        &mut self,
+        contract_address: ContractAddress,
+    ) -> StateResult<(gen::Felt, gen::Felt)> {
+        if let Some(contract_state) =
+            self.cache.contracts.get(&contract_address)
+        {
+            return Ok(contract_state.clone());
+        }
+        let felt: gen::Felt = contract_address.0.key().try_into()?;
+        let contract_state = self.fetch_contract_state(gen::Address(felt))?;
+        self.cache.contracts.insert(contract_address, contract_state.clone());
+        Ok(contract_state)
+    }
+
+    fn fetch_contract_state(
+        &self,
+        contract_address: gen::Address,
+    ) -> StateResult<(gen::Felt, gen::Felt)> {
+        let proof = self
+            .client
+            .getProof(self.block_id.clone(), contract_address.clone(), vec![])
+            .map_err(Into::<Error>::into)?;
+
+        // TODO: find more elegant way for this
+        // workaround to skip proof validation for testing
+        #[cfg(feature = "skip-zero-root-validation")]
+        if self.state_root.as_ref() == "0x0" {
+            let zero =
+                gen::Felt::try_new("0x0").map_err(Into::<Error>::into)?;
+            return Ok(proof
+                .contract_data
+                .map(|data| (data.class_hash, data.nonce))
+                .unwrap_or((zero.clone(), zero)));
+        }
+
+        let contract_state = proof
+            .verify_contract_state(self.state_root.clone(), contract_address)
+            .map_err(|e| {
+                StateError::StateReadError(format!("Invalid merkle proof: {e}"))
+            })?;
+        tracing::info!("get_contract_state: proof verified");
+        Ok(contract_state)
+    }
+
+    fn fetch_storage_at(
         &mut self,
         contract_address: ContractAddress,
         key: StarknetStorageKey,
     ) -> StateResult<StarkFelt> {
-        tracing::info!(?contract_address, ?key, "get_storage_at");
-
+        let contract = contract_address;
         let felt: gen::Felt = contract_address.0.key().try_into()?;
         let contract_address = gen::Address(felt);
 
         let key = gen::StorageKey::try_new(&key.0.to_string())
             .map_err(Into::<Error>::into)?;
 
-        let block_id = gen::BlockId::BlockTag(gen::BlockTag::Latest);
-
-        let ret = self
+        let proof = self
             .client
-            .getStorageAt(
+            .getProof(
+                self.block_id.clone(),
                 contract_address.clone(),
-                key.clone(),
-                block_id.clone(),
+                vec![key.clone()],
             )
             .map_err(Into::<Error>::into)?;
+        tracing::info!(?proof, "fetch_storage_at: proof received");
 
-        let proof = self
+        if proof.contract_data.is_none() {
+            // The contract is not deployed (yet), so all of its storage is
+            // zero once the proof of its absence is verified.
+            self.get_contract_state(contract)?;
+            return Ok(StarkFelt::ZERO);
+        }
+
+        let ret = self
             .client
-            .getProof(
-                block_id.clone(),
+            .getStorageAt(
                 contract_address.clone(),
-                vec![key.clone()],
+                key.clone(),
+                self.block_id.clone(),
             )
             .map_err(Into::<Error>::into)?;
-        tracing::info!(?proof, "get_storage_at: proof received");
 
         // TODO: find more elegant way for this
         // workaround to skip proof validation for testing
@@ -191,30 +746,224 @@ impl StateReader for StateProxy {
         let global_root = self.state_root.clone();
         let value = ret.clone();
         proof.verify(global_root, contract_address, key, value).map_err(
-            |_| StateError::StateReadError("Invalid merkle proof".to_owned()),
+            |e| {
+                StateError::StateReadError(format!("Invalid merkle proof: {e}"))
+            },
         )?;
-        tracing::info!("get_storage_at: proof verified");
+        tracing::info!("fetch_storage_at: proof verified");
 
         Ok(ret.try_into()?)
     }
 
-    fn get_nonce_at(
+    /// Return the class declared with the given hash, after checking that
+    /// it does hash to it.
+    fn get_declared_class(
+        &self,
+        class_hash: ClassHash,
+    ) -> StateResult<gen::GetClassResult> {
+        let undeclared = StateError::UndeclaredClassHash(class_hash);
+        let class_hash: gen::Felt = class_hash.0.try_into()?;
+
+        let ret = match self
+            .client
+            .getClass(self.block_id.clone(), class_hash.clone())
+        {
+            Ok(ret) => ret,
+            Err(e) if e.code == CLASS_HASH_NOT_FOUND => return Err(undeclared),
+            Err(e) => return Err(Error::from(e).into()),
+        };
+
+        let computed_class_hash = crate::class::class_hash(&ret)?;
+        if computed_class_hash
+            != crate::proof::as_field_element(&class_hash)
+                .map_err(Error::from)?
+        {
+            return Err(StateError::StateReadError(
+                "Invalid class hash".to_owned(),
+            ));
+        }
+        Ok(ret)
+    }
+
+    /// Return the compiled class from the cache on disk, or else download
+    /// the class (checking its hash), compile it and store it there. The
+    /// cache only saves compiling the class: it says nothing about the class
+    /// being declared at the block, which is checked by the callers.
+    fn get_compiled_class(
         &mut self,
-        contract_address: ContractAddress,
-    ) -> StateResult<Nonce> {
-        tracing::info!(?contract_address, "get_nonce_at");
+        class_hash: ClassHash,
+    ) -> StateResult<CompiledClass> {
+        let disk = cache::classes();
+        if let Some(class) = disk.and_then(|disk| disk.get(&class_hash)) {
+            tracing::debug!(?class_hash, "compiled class found on disk");
+            return Ok(class);
+        }
+
+        let class = match self.get_declared_class(class_hash)? {
+            gen::GetClassResult::ContractClass(class) => {
+                CompiledClass::Sierra(map::compile_sierra_class(&class)?)
+            }
+            gen::GetClassResult::DeprecatedContractClass(class) => {
+                CompiledClass::Legacy(map::legacy_class_json(class)?)
+            }
+        };
+        if let Some(disk) = disk {
+            if let Err(e) = disk.put(&class_hash, &class) {
+                tracing::warn!(?class_hash, error=?e, "class not cached");
+            }
+        }
+        if matches!(class, CompiledClass::Legacy(_)) {
+            self.set_declared_legacy_class(class_hash);
+        }
+        Ok(class)
+    }
 
-        let block_id = gen::BlockId::BlockTag(gen::BlockTag::Latest);
+    /// Check that the Cairo 0 class is declared at the block. There is no
+    /// proof for a Cairo 0 class: it is declared if it was already found
+    /// declared at this block or an earlier one, if a contract read (and
+    /// proven) from the state has it as its class, or else if the provider
+    /// serves it at the block.
+    fn verify_declared_legacy_class(
+        &mut self,
+        class_hash: ClassHash,
+    ) -> StateResult<()> {
+        if self.cache.declared_legacy_classes.contains(&class_hash) {
+            return Ok(());
+        }
+        let declared_before = cache::classes()
+            .and_then(|disk| disk.declared_at(&class_hash))
+            .zip(self.block_number())
+            .is_some_and(|(declared, block_number)| declared <= block_number);
+        let deployed = self.cache.contracts.values().any(|(hash, _)| {
+            StarkFelt::try_from(hash.clone())
+                .is_ok_and(|hash| hash == class_hash.0)
+        });
+        if !declared_before && !deployed {
+            self.get_declared_class(class_hash)?;
+        }
+        self.set_declared_legacy_class(class_hash);
+        Ok(())
+    }
 
-        let felt: gen::Felt = contract_address.0.key().try_into()?;
-        let contract_address = gen::Address(felt);
+    /// Remember that the Cairo 0 class is declared at the block, for the
+    /// rest of the execution and, on disk, for later ones.
+    fn set_declared_legacy_class(&mut self, class_hash: ClassHash) {
+        self.cache.declared_legacy_classes.insert(class_hash);
+        let Some(block_number) = self.block_number() else {
+            return;
+        };
+        if let Some(disk) = cache::classes() {
+            if let Err(e) = disk.put_declared_at(&class_hash, block_number) {
+                tracing::warn!(?class_hash, error=?e, "declaration not cached");
+            }
+        }
+    }
 
-        let ret = self
+    /// The number of the block the state is read from.
+    fn block_number(&self) -> Option<u64> {
+        match &self.block_id {
+            gen::BlockId::BlockNumber { block_number } => {
+                Some(*block_number.as_ref() as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the class commitment, proven against the state root.
+    fn get_class_commitment(&mut self) -> StateResult<gen::Felt> {
+        if let Some(class_commitment) = self.cache.class_commitment.as_ref() {
+            return Ok(class_commitment.clone());
+        }
+
+        let class_commitment =
+            gen::GetProofResult::fetch_class_commitment_blocking(
+                &self.client,
+                self.block_id.clone(),
+                self.state_root.clone(),
+            )
+            .map_err(Into::<Error>::into)?;
+        self.cache.class_commitment = Some(class_commitment.clone());
+        Ok(class_commitment)
+    }
+
+    /// Check that the Sierra class is declared, i.e. that the class
+    /// commitment of the state root has a leaf for the class hash, and
+    /// return the leaf. The leaf commits to the compiled class hash of the
+    /// compiler the class was declared with, which need not be the one used
+    /// for execution here.
+    fn verify_declared_class(
+        &mut self,
+        class_hash: ClassHash,
+    ) -> StateResult<StarkFelt> {
+        // TODO: find more elegant way for this
+        // workaround to skip proof validation for testing
+        #[cfg(feature = "skip-zero-root-validation")]
+        if self.state_root.as_ref() == "0x0" {
+            return Ok(StarkFelt::ZERO);
+        }
+
+        let undeclared = StateError::UndeclaredClassHash(class_hash);
+        let class_hash: gen::Felt = class_hash.0.try_into()?;
+        let class_commitment = self.get_class_commitment()?;
+        let class_proof = self
             .client
-            .getNonce(block_id, contract_address)
+            .getClassProof(self.block_id.clone(), class_hash.clone())
             .map_err(Into::<Error>::into)?;
+        let leaf =
+            match class_proof.verify_declared(&class_commitment, class_hash) {
+                Ok(leaf) => leaf,
+                // Blockifier only declares a class reported as undeclared.
+                Err(crate::proof::ProofError::UndeclaredClass(_)) => {
+                    return Err(undeclared)
+                }
+                Err(e) => {
+                    return Err(StateError::StateReadError(format!(
+                        "Invalid class proof: {e}"
+                    )))
+                }
+            };
+        tracing::info!("verify_declared_class: proof verified");
+        Ok(leaf.try_into()?)
+    }
+}
+
+impl StateReader for StateProxy {
+    fn get_storage_at(
+        &mut self,
+        contract_address: ContractAddress,
+        key: StarknetStorageKey,
+    ) -> StateResult<StarkFelt> {
+        tracing::info!(?contract_address, ?key, "get_storage_at");
+
+        let written = self
+            .diff
+            .storage_updates
+            .get(&contract_address)
+            .and_then(|storage| storage.get(&key));
+        if let Some(value) = written {
+            return Ok(*value);
+        }
+        if let Some(value) = self.cache.storage.get(&(contract_address, key)) {
+            return Ok(*value);
+        }
+
+        let value = self.fetch_storage_at(contract_address, key)?;
+        self.cache.storage.insert((contract_address, key), value);
+        Ok(value)
+    }
+
+    fn get_nonce_at(
+        &mut self,
+        contract_address: ContractAddress,
+    ) -> StateResult<Nonce> {
+        tracing::info!(?contract_address, "get_nonce_at");
+
+        if let Some(nonce) = self.diff.address_to_nonce.get(&contract_address) {
+            return Ok(*nonce);
+        }
+        let (_, nonce) = self.get_contract_state(contract_address)?;
 
-        Ok(Nonce(ret.try_into()?))
+        Ok(Nonce(nonce.try_into()?))
     }
 
     fn get_class_hash_at(
@@ -223,17 +972,14 @@ impl StateReader for StateProxy {
     ) -> StateResult<ClassHash> {
         tracing::info!(?contract_address, "get_class_hash_at");
 
-        let block_id = gen::BlockId::BlockTag(gen::BlockTag::Latest);
-
-        let felt: gen::Felt = contract_address.0.key().try_into()?;
-        let contract_address = gen::Address(felt);
-
-        let ret = self
-            .client
-            .getClassHashAt(block_id, contract_address)
-            .map_err(Into::<Error>::into)?;
+        if let Some(class_hash) =
+            self.diff.address_to_class_hash.get(&contract_address)
+        {
+            return Ok(*class_hash);
+        }
+        let (class_hash, _) = self.get_contract_state(contract_address)?;
 
-        Ok(ClassHash(ret.try_into()?))
+        Ok(ClassHash(class_hash.try_into()?))
     }
 
     fn get_compiled_contract_class(
@@ -242,16 +988,26 @@ impl StateReader for StateProxy {
     ) -> StateResult<ContractClass> {
         tracing::info!(?class_hash, "get_compiled_contract_class");
 
-        let block_id = gen::BlockId::BlockTag(gen::BlockTag::Latest);
-
-        let class_hash: gen::Felt = class_hash.0.try_into()?;
-
-        let ret = self
-            .client
-            .getClass(block_id, class_hash)
-            .map_err(Into::<Error>::into)?;
+        if let Some(class) = self
+            .classes
+            .get(&class_hash)
+            .or_else(|| self.cache.classes.get(&class_hash))
+        {
+            return Ok(class.clone());
+        }
 
-        Ok(ret.try_into()?)
+        let class = match self.get_compiled_class(class_hash)? {
+            CompiledClass::Legacy(json) => {
+                self.verify_declared_legacy_class(class_hash)?;
+                ContractClass::V0(map::legacy_contract_class(&json)?)
+            }
+            CompiledClass::Sierra(casm_contract_class) => {
+                self.verify_declared_class(class_hash)?;
+                ContractClass::V1(casm_contract_class.try_into()?)
+            }
+        };
+        self.cache.classes.insert(class_hash, class.clone());
+        Ok(class)
     }
 
     fn get_compiled_class_hash(
@@ -259,7 +1015,34 @@ impl StateReader for StateProxy {
         class_hash: ClassHash,
     ) -> StateResult<CompiledClassHash> {
         tracing::info!(?class_hash, "get_compiled_class_hash");
-        Err(StateError::UndeclaredClassHash(class_hash))
+
+        if let Some(hash) = self
+            .diff
+            .class_hash_to_compiled_class_hash
+            .get(&class_hash)
+            .or_else(|| self.cache.compiled_class_hashes.get(&class_hash))
+        {
+            return Ok(*hash);
+        }
+
+        let compiled_class_hash = match self.get_compiled_class(class_hash)? {
+            // The class commitment tree only commits to the compiled class
+            // hash through the leaf of the class, which stands for it: the
+            // class compiled here need not hash the same.
+            CompiledClass::Sierra(_) => {
+                CompiledClassHash(self.verify_declared_class(class_hash)?)
+            }
+            // Cairo 0 classes are not compiled, so they are not in the class
+            // commitment tree and their compiled class hash is zero.
+            CompiledClass::Legacy(_) => {
+                self.verify_declared_legacy_class(class_hash)?;
+                CompiledClassHash::default()
+            }
+        };
+        self.cache
+            .compiled_class_hashes
+            .insert(class_hash, compiled_class_hash);
+        Ok(compiled_class_hash)
     }
 }
 
@@ -275,8 +1058,7 @@ impl BlockifierState for StateProxy {
             .storage_updates
             .entry(contract_address)
             .or_default()
-            .entry(key)
-            .or_insert(value);
+            .insert(key, value);
         Ok(())
     }
 
@@ -285,10 +1067,8 @@ impl BlockifierState for StateProxy {
         contract_address: ContractAddress,
     ) -> StateResult<()> {
         tracing::info!(?contract_address, "increment_nonce");
-        let nonce: &mut Nonce =
-            self.diff.address_to_nonce.entry(contract_address).or_default();
-        let value = *nonce;
-        *nonce = value.try_increment()?;
+        let nonce = self.get_nonce_at(contract_address)?.try_increment()?;
+        self.diff.address_to_nonce.insert(contract_address, nonce);
         Ok(())
     }
 
@@ -298,8 +1078,7 @@ impl BlockifierState for StateProxy {
         class_hash: ClassHash,
     ) -> StateResult<()> {
         tracing::info!(?contract_address, ?class_hash, "set_class_hash_at");
-        *self.diff.address_to_class_hash.entry(contract_address).or_default() =
-            class_hash;
+        self.diff.address_to_class_hash.insert(contract_address, class_hash);
         Ok(())
     }
 
@@ -309,6 +1088,7 @@ impl BlockifierState for StateProxy {
         contract_class: ContractClass,
     ) -> StateResult<()> {
         tracing::info!(?class_hash, ?contract_class, "set_contract_class");
+        self.classes.insert(class_hash, contract_class);
         Ok(())
     }
 
@@ -322,11 +1102,9 @@ impl BlockifierState for StateProxy {
             ?compiled_class_hash,
             "set_compiled_class_hash"
         );
-        *self
-            .diff
+        self.diff
             .class_hash_to_compiled_class_hash
-            .entry(class_hash)
-            .or_default() = compiled_class_hash;
+            .insert(class_hash, compiled_class_hash);
         Ok(())
     }
 
@@ -339,3 +1117,94 @@ impl BlockifierState for StateProxy {
         tracing::info!(?class_hash, pcs.len = pcs.len(), "add_visited_pcs");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use starknet_crypto::poseidon_hash_many;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    // A class commitment tree holding only the class 0xabc.
+    const CLASS_COMMITMENT: &str =
+        "0x4a97062d30e38519de81e24c084fca7272f1de4fcc86c33dd4be26dab15c611";
+    const CLASS_LEAF: &str =
+        "0x7b80c4d5d2b3bbf805caad0d76e9f0c1cc40ba6b5a2458894a532f2533b8a41";
+
+    fn state_root() -> gen::Felt {
+        let root = poseidon_hash_many(&[
+            FieldElement::from_byte_slice_be(b"STARKNET_STATE_V0").unwrap(),
+            FieldElement::ZERO,
+            FieldElement::from_hex_be(CLASS_COMMITMENT).unwrap(),
+        ]);
+        gen::Felt::try_new(&format!("0x{:x}", root)).unwrap()
+    }
+
+    fn respond(request: &wiremock::Request) -> ResponseTemplate {
+        let request: Value = serde_json::from_slice(&request.body).unwrap();
+        let result = match request["method"].as_str().unwrap() {
+            "pathfinder_getProof" => json!({
+                "class_commitment": CLASS_COMMITMENT,
+                "contract_proof": [],
+                "state_commitment": state_root(),
+            }),
+            "pathfinder_getClassProof" => json!({
+                "class_proof": [{
+                    "edge": {
+                        "child": CLASS_LEAF,
+                        "path": {"len": 251, "value": "0xabc"}
+                    }
+                }]
+            }),
+            method => panic!("unexpected method {method}"),
+        };
+        ResponseTemplate::new(200)
+            .set_body_json(json!({"jsonrpc": "2.0", "id": 1, "result": result}))
+    }
+
+    #[tokio::test]
+    async fn cached_class_proven_undeclared_can_be_declared() {
+        let server = MockServer::start().await;
+        Mock::given(any()).respond_with(respond).mount(&server).await;
+
+        let dir = std::env::temp_dir()
+            .join(format!("beerus-undeclared-{}", std::process::id()));
+        cache::init(&dir, "0x534e5f4d41494e", 1024 * 1024).unwrap();
+        let casm_contract_class = serde_json::from_value(json!({
+            "prime": "0x800000000000011000000000000000000000000000000000000000000000001",
+            "compiler_version": "2.6.4",
+            "bytecode": [],
+            "hints": [],
+            "entry_points_by_type": {
+                "EXTERNAL": [],
+                "L1_HANDLER": [],
+                "CONSTRUCTOR": []
+            }
+        }))
+        .unwrap();
+        let class_hash = ClassHash(StarkFelt::from(0xabdu64));
+        cache::classes()
+            .unwrap()
+            .put(&class_hash, &CompiledClass::Sierra(casm_contract_class))
+            .unwrap();
+
+        let url = server.uri();
+        let result = tokio::task::spawn_blocking(move || {
+            let client = gen::client::blocking::Client::new(&url);
+            let block_id = gen::BlockId::BlockNumber {
+                block_number: gen::BlockNumber::try_new(1).unwrap(),
+            };
+            let mut proxy = StateProxy::new(&client, (block_id, state_root()));
+            proxy.get_compiled_contract_class(class_hash).map(|_| ())
+        })
+        .await
+        .unwrap();
+
+        // blockifier declares the class only on this very error
+        assert!(matches!(
+            result,
+            Err(StateError::UndeclaredClassHash(hash)) if hash == class_hash
+        ));
+    }
+}
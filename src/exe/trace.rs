@@ -0,0 +1,480 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::*;
+
+/// Build the trace of the executed transaction: the invocations of its
+/// phases (validation, execution, fee transfer) and the state diff it left.
+pub(crate) fn transaction_trace(
+    transaction: &Transaction,
+    execution_info: &TransactionExecutionInfo,
+    diff: &CommitmentStateDiff,
+) -> Result<gen::TransactionTrace, Error> {
+    let invocation = |call_info: &Option<CallInfo>| {
+        call_info.as_ref().map(function_invocation).transpose()
+    };
+    let validate_invocation = invocation(&execution_info.validate_call_info)?;
+    let fee_transfer_invocation =
+        invocation(&execution_info.fee_transfer_call_info)?;
+    let execute_invocation = invocation(&execution_info.execute_call_info)?;
+
+    let deployed = execution_info
+        .validate_call_info
+        .iter()
+        .chain(execution_info.execute_call_info.iter())
+        .flat_map(constructed_contracts)
+        .collect::<HashSet<_>>();
+    let state_diff = Some(state_diff(diff, &deployed, transaction)?);
+
+    Ok(match transaction {
+        Transaction::AccountTransaction(AccountTransaction::Invoke(_)) => {
+            let execute_invocation = match &execution_info.revert_error {
+                Some(revert_reason) => {
+                    gen::InvokeTxnTraceExecuteInvocation::RevertReason {
+                        revert_reason: Some(revert_reason.clone()),
+                    }
+                }
+                None => {
+                    gen::InvokeTxnTraceExecuteInvocation::FunctionInvocation(
+                        execute_invocation.ok_or(Error::Custom(
+                            "missing execute invocation",
+                        ))?,
+                    )
+                }
+            };
+            gen::TransactionTrace::InvokeTxnTrace(gen::InvokeTxnTrace {
+                execute_invocation,
+                fee_transfer_invocation,
+                r#type: gen::InvokeTxnTraceType::Invoke,
+                state_diff,
+                validate_invocation,
+            })
+        }
+        Transaction::AccountTransaction(AccountTransaction::Declare(_)) => {
+            gen::TransactionTrace::DeclareTxnTrace(gen::DeclareTxnTrace {
+                fee_transfer_invocation,
+                r#type: gen::DeclareTxnTraceType::Declare,
+                state_diff,
+                validate_invocation,
+            })
+        }
+        Transaction::AccountTransaction(AccountTransaction::DeployAccount(
+            _,
+        )) => gen::TransactionTrace::DeployAccountTxnTrace(
+            gen::DeployAccountTxnTrace {
+                constructor_invocation: execute_invocation
+                    .ok_or(Error::Custom("missing constructor invocation"))?,
+                fee_transfer_invocation,
+                r#type: gen::DeployAccountTxnTraceType::DeployAccount,
+                state_diff,
+                validate_invocation,
+            },
+        ),
+        Transaction::L1HandlerTransaction(_) => {
+            gen::TransactionTrace::L1HandlerTxnTrace(gen::L1HandlerTxnTrace {
+                function_invocation: execute_invocation
+                    .ok_or(Error::Custom("missing function invocation"))?,
+                r#type: gen::L1HandlerTxnTraceType::L1Handler,
+                state_diff,
+            })
+        }
+    })
+}
+
+fn function_invocation(
+    call_info: &CallInfo,
+) -> Result<gen::FunctionInvocation, Error> {
+    let call = &call_info.call;
+    let execution = &call_info.execution;
+
+    let calls = call_info
+        .inner_calls
+        .iter()
+        .map(|call_info| function_invocation(call_info).map(gen::NestedCall))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let events = execution
+        .events
+        .iter()
+        .map(|ordered| {
+            Ok(gen::OrderedEvent {
+                order: Some(ordered.order as i64),
+                event_content: gen::EventContent {
+                    data: felts(&ordered.event.data.0)?,
+                    keys: ordered
+                        .event
+                        .keys
+                        .iter()
+                        .map(|key| key.0.try_into())
+                        .collect::<Result<Vec<_>, Error>>()?,
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let from_address: gen::Felt = call.storage_address.0.key().try_into()?;
+    let messages = execution
+        .l2_to_l1_messages
+        .iter()
+        .map(|ordered| {
+            Ok(gen::OrderedMessage {
+                order: Some(ordered.order as i64),
+                msg_to_l1: gen::MsgToL1 {
+                    from_address: from_address.clone(),
+                    payload: felts(&ordered.message.payload.0)?,
+                    to_address: StarkFelt::from(ordered.message.to_address)
+                        .try_into()?,
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(gen::FunctionInvocation {
+        function_call: gen::FunctionCall {
+            calldata: felts(&call.calldata.0)?,
+            contract_address: gen::Address(from_address),
+            entry_point_selector: call.entry_point_selector.0.try_into()?,
+        },
+        call_type: match call.call_type {
+            CallType::Call => gen::CallType::Call,
+            CallType::Delegate => gen::CallType::LibraryCall,
+        },
+        caller_address: call.caller_address.0.key().try_into()?,
+        calls,
+        class_hash: call.class_hash.unwrap_or_default().0.try_into()?,
+        entry_point_type: match call.entry_point_type {
+            EntryPointType::Constructor => gen::EntryPointType::Constructor,
+            EntryPointType::External => gen::EntryPointType::External,
+            EntryPointType::L1Handler => gen::EntryPointType::L1Handler,
+        },
+        events,
+        execution_resources: execution_resources(&call_info.resources),
+        messages,
+        result: felts(&execution.retdata.0)?,
+    })
+}
+
+fn execution_resources(
+    resources: &ExecutionResources,
+) -> gen::ExecutionResources {
+    let builtin = |name: &str| {
+        resources
+            .builtin_instance_counter
+            .get(name)
+            .map(|count| *count as i64)
+            .filter(|count| *count > 0)
+    };
+    gen::ExecutionResources {
+        bitwise_builtin_applications: builtin("bitwise_builtin"),
+        ec_op_builtin_applications: builtin("ec_op_builtin"),
+        ecdsa_builtin_applications: builtin("ecdsa_builtin"),
+        keccak_builtin_applications: builtin("keccak_builtin"),
+        memory_holes: Some(resources.n_memory_holes as i64)
+            .filter(|holes| *holes > 0),
+        pedersen_builtin_applications: builtin("pedersen_builtin"),
+        poseidon_builtin_applications: builtin("poseidon_builtin"),
+        range_check_builtin_applications: builtin("range_check_builtin"),
+        segment_arena_builtin: builtin("segment_arena_builtin"),
+        steps: resources.n_steps as i64,
+    }
+}
+
+/// Contracts deployed by the call or its inner calls: a deployment always
+/// runs the constructor of the class, even if the class does not define one.
+fn constructed_contracts(call_info: &CallInfo) -> Vec<ContractAddress> {
+    let mut contracts = call_info
+        .inner_calls
+        .iter()
+        .flat_map(constructed_contracts)
+        .collect::<Vec<_>>();
+    if matches!(call_info.call.entry_point_type, EntryPointType::Constructor) {
+        contracts.push(call_info.call.storage_address);
+    }
+    contracts
+}
+
+fn state_diff(
+    diff: &CommitmentStateDiff,
+    deployed: &HashSet<ContractAddress>,
+    transaction: &Transaction,
+) -> Result<gen::StateDiff, Error> {
+    let address = |address: &ContractAddress| -> Result<gen::Felt, Error> {
+        address.0.key().try_into()
+    };
+
+    let storage_diffs = diff
+        .storage_updates
+        .iter()
+        .map(|(contract_address, storage)| {
+            Ok(gen::ContractStorageDiffItem {
+                address: address(contract_address)?,
+                storage_entries: storage
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok(gen::StorageDiffItem {
+                            key: Some(key.0.key().try_into()?),
+                            value: Some(value.try_into()?),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let nonces = diff
+        .address_to_nonce
+        .iter()
+        .map(|(contract_address, nonce)| {
+            Ok(gen::NonceUpdate {
+                contract_address: Some(gen::Address(address(
+                    contract_address,
+                )?)),
+                nonce: Some(nonce.0.try_into()?),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut deployed_contracts = Vec::new();
+    let mut replaced_classes = Vec::new();
+    for (contract_address, class_hash) in &diff.address_to_class_hash {
+        let class_hash: gen::Felt = class_hash.0.try_into()?;
+        if deployed.contains(contract_address) {
+            deployed_contracts.push(gen::DeployedContractItem {
+                address: address(contract_address)?,
+                class_hash,
+            });
+        } else {
+            replaced_classes.push(gen::ReplacedClass {
+                class_hash: Some(class_hash),
+                contract_address: Some(gen::Address(address(
+                    contract_address,
+                )?)),
+            });
+        }
+    }
+
+    let declared_classes = diff
+        .class_hash_to_compiled_class_hash
+        .iter()
+        .map(|(class_hash, compiled_class_hash)| {
+            Ok(gen::DeclaredClass {
+                class_hash: Some(class_hash.0.try_into()?),
+                compiled_class_hash: Some(compiled_class_hash.0.try_into()?),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // Cairo 0 classes have no compiled class hash, so they are only known
+    // from the declare transaction itself.
+    let deprecated_declared_classes = match transaction {
+        Transaction::AccountTransaction(AccountTransaction::Declare(tx))
+            if matches!(
+                tx.tx(),
+                api::DeclareTransaction::V0(_) | api::DeclareTransaction::V1(_)
+            ) =>
+        {
+            vec![tx.class_hash().0.try_into()?]
+        }
+        _ => vec![],
+    };
+
+    Ok(gen::StateDiff {
+        declared_classes,
+        deployed_contracts,
+        deprecated_declared_classes,
+        nonces,
+        replaced_classes,
+        storage_diffs,
+    })
+}
+
+fn felts(felts: &[StarkFelt]) -> Result<Vec<gen::Felt>, Error> {
+    felts.iter().map(TryInto::try_into).collect()
+}
+
+/// Check the changes made by the re-executed block against the verified
+/// state diff of the block. Cairo 0 declarations are not compared, as they
+/// are not an effect of the execution.
+pub(crate) fn verify_state_diff(
+    diff: &CommitmentStateDiff,
+    verified: &gen::StateDiff,
+) -> Result<(), Error> {
+    let executed = executed_entries(diff)?;
+    let verified = verified_entries(verified)?;
+    let keys = executed.keys().chain(verified.keys()).collect::<BTreeSet<_>>();
+    for key in keys {
+        let (provided, proven) = (executed.get(key), verified.get(key));
+        if provided != proven {
+            let entry = |value: Option<&String>| {
+                format!("{key} = {}", value.map_or("none", String::as_str))
+            };
+            return Err(crate::proof::ProofError::ValueMismatch {
+                name: "traced state diff",
+                provided: entry(provided),
+                proven: entry(proven),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// The entries of a state diff, keyed and valued by canonical hex strings,
+/// so that diffs compare whatever the order of their items.
+type DiffEntries = BTreeMap<String, String>;
+
+fn executed_entries(diff: &CommitmentStateDiff) -> Result<DiffEntries, Error> {
+    let hex = |felt: &StarkFelt| hex(&gen::Felt::try_from(felt)?);
+
+    let mut entries = DiffEntries::new();
+    for (address, storage) in &diff.storage_updates {
+        let address = hex(address.0.key())?;
+        for (key, value) in storage {
+            let key = hex(key.0.key())?;
+            entries.insert(format!("storage {address}/{key}"), hex(value)?);
+        }
+    }
+    for (address, nonce) in &diff.address_to_nonce {
+        entries
+            .insert(format!("nonce {}", hex(address.0.key())?), hex(&nonce.0)?);
+    }
+    for (address, class_hash) in &diff.address_to_class_hash {
+        let address = hex(address.0.key())?;
+        entries.insert(format!("class hash {address}"), hex(&class_hash.0)?);
+    }
+    for (class_hash, compiled_class_hash) in
+        &diff.class_hash_to_compiled_class_hash
+    {
+        entries.insert(
+            format!("compiled class hash {}", hex(&class_hash.0)?),
+            hex(&compiled_class_hash.0)?,
+        );
+    }
+    Ok(entries)
+}
+
+fn verified_entries(diff: &gen::StateDiff) -> Result<DiffEntries, Error> {
+    let some_hex = |felt: Option<&gen::Felt>| {
+        hex(felt.ok_or(Error::Custom("incomplete state diff"))?)
+    };
+
+    let mut entries = DiffEntries::new();
+    for item in &diff.storage_diffs {
+        let address = hex(&item.address)?;
+        for entry in &item.storage_entries {
+            let key = some_hex(entry.key.as_ref())?;
+            entries.insert(
+                format!("storage {address}/{key}"),
+                some_hex(entry.value.as_ref())?,
+            );
+        }
+    }
+    for item in &diff.nonces {
+        let address = some_hex(item.contract_address.as_ref().map(|a| &a.0))?;
+        entries
+            .insert(format!("nonce {address}"), some_hex(item.nonce.as_ref())?);
+    }
+    for item in &diff.deployed_contracts {
+        entries.insert(
+            format!("class hash {}", hex(&item.address)?),
+            hex(&item.class_hash)?,
+        );
+    }
+    for item in &diff.replaced_classes {
+        let address = some_hex(item.contract_address.as_ref().map(|a| &a.0))?;
+        entries.insert(
+            format!("class hash {address}"),
+            some_hex(item.class_hash.as_ref())?,
+        );
+    }
+    for item in &diff.declared_classes {
+        entries.insert(
+            format!(
+                "compiled class hash {}",
+                some_hex(item.class_hash.as_ref())?
+            ),
+            some_hex(item.compiled_class_hash.as_ref())?,
+        );
+    }
+    Ok(entries)
+}
+
+fn hex(felt: &gen::Felt) -> Result<String, Error> {
+    Ok(format!("0x{:x}", crate::proof::as_field_element(felt)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn felt(value: u64) -> StarkFelt {
+        StarkFelt::from(value)
+    }
+
+    fn address(value: u64) -> ContractAddress {
+        ContractAddress(felt(value).try_into().unwrap())
+    }
+
+    fn executed() -> CommitmentStateDiff {
+        let storage =
+            [(StarknetStorageKey(felt(5).try_into().unwrap()), felt(7))];
+        CommitmentStateDiff {
+            address_to_class_hash: [(address(1), ClassHash(felt(0xa)))]
+                .into_iter()
+                .collect(),
+            address_to_nonce: [(address(2), Nonce(felt(1)))]
+                .into_iter()
+                .collect(),
+            storage_updates: [(address(1), storage.into_iter().collect())]
+                .into_iter()
+                .collect(),
+            class_hash_to_compiled_class_hash: [(
+                ClassHash(felt(0xb)),
+                CompiledClassHash(felt(0xc)),
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn verified(storage_value: &str) -> gen::StateDiff {
+        serde_json::from_value(json!({
+            "declared_classes": [
+                {"class_hash": "0xB", "compiled_class_hash": "0xc"}
+            ],
+            "deployed_contracts": [{"address": "0x1", "class_hash": "0xa"}],
+            "deprecated_declared_classes": ["0xd"],
+            "nonces": [{"contract_address": "0x2", "nonce": "0x1"}],
+            "replaced_classes": [],
+            "storage_diffs": [
+                {"address": "0x2", "storage_entries": []},
+                {
+                    "address": "0x1",
+                    "storage_entries": [{"key": "0x5", "value": storage_value}]
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_state_diff() {
+        verify_state_diff(&executed(), &verified("0x7")).unwrap();
+    }
+
+    #[test]
+    fn test_verify_state_diff_value_mismatch() {
+        let error =
+            verify_state_diff(&executed(), &verified("0x8")).unwrap_err();
+        assert!(error.to_string().contains("storage 0x1/0x5"));
+    }
+
+    #[test]
+    fn test_verify_state_diff_missing_entry() {
+        let mut diff = executed();
+        diff.address_to_nonce.clear();
+        let error = verify_state_diff(&diff, &verified("0x7")).unwrap_err();
+        assert!(error.to_string().contains("nonce 0x2"));
+    }
+}
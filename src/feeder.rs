@@ -0,0 +1,332 @@
+//! Client of the feeder gateway, the source of the parts of a block which
+//! its hash commits to since 0.13.2 but which are not served over RPC (the
+//! L1 data gas price, the DA mode and the gas consumed by each transaction).
+//! Nothing read from the gateway is trusted: it only goes into recomputing
+//! the block hash, which fails unless every part is the committed one.
+
+use iamgroot::jsonrpc;
+use serde::Deserialize;
+use starknet_crypto::FieldElement;
+
+use crate::block::{
+    BlockData, GasPrice, L1DataAvailabilityMode, MessageToL1, Receipt,
+};
+use crate::gen::StateDiff;
+
+#[derive(Clone, Debug)]
+pub struct FeederGateway {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl FeederGateway {
+    pub fn new(url: &str, client: reqwest::Client) -> Self {
+        Self { url: url.trim_end_matches('/').to_owned(), client }
+    }
+
+    pub async fn get_block(
+        &self,
+        block_number: u64,
+    ) -> Result<GatewayBlock, jsonrpc::Error> {
+        let url = format!("{}/get_block?blockNumber={block_number}", self.url);
+        let block: Block = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(gateway_error)?
+            .json()
+            .await
+            .map_err(gateway_error)?;
+        if block.block_number != block_number {
+            return Err(jsonrpc::Error::new(
+                -1,
+                format!(
+                    "Feeder gateway returned block {} instead of {block_number}",
+                    block.block_number
+                ),
+            ));
+        }
+        block.try_into()
+    }
+}
+
+/// Parts of the block served by the gateway that go into [`BlockData`].
+#[derive(Clone, Debug)]
+pub struct GatewayBlock {
+    pub l1_da_mode: L1DataAvailabilityMode,
+    pub l1_data_gas_price: GasPrice,
+    pub l2_gas_price: GasPrice,
+    pub receipts: Vec<Receipt>,
+}
+
+impl GatewayBlock {
+    pub fn with_state_diff(self, state_diff: StateDiff) -> BlockData {
+        BlockData {
+            state_diff,
+            l1_da_mode: self.l1_da_mode,
+            l1_data_gas_price: self.l1_data_gas_price,
+            l2_gas_price: self.l2_gas_price,
+            receipts: self.receipts,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Block {
+    block_number: u64,
+    #[serde(default)]
+    l1_da_mode: Option<DataAvailabilityMode>,
+    #[serde(default)]
+    l1_data_gas_price: Option<Price>,
+    #[serde(default)]
+    l2_gas_price: Option<Price>,
+    transaction_receipts: Vec<TransactionReceipt>,
+}
+
+#[derive(Deserialize)]
+enum DataAvailabilityMode {
+    #[serde(rename = "CALLDATA")]
+    Calldata,
+    #[serde(rename = "BLOB")]
+    Blob,
+}
+
+#[derive(Deserialize)]
+struct Price {
+    price_in_wei: String,
+    price_in_fri: String,
+}
+
+#[derive(Deserialize)]
+struct TransactionReceipt {
+    transaction_index: u64,
+    transaction_hash: String,
+    #[serde(default)]
+    actual_fee: Option<String>,
+    #[serde(default)]
+    l2_to_l1_messages: Vec<Message>,
+    #[serde(default)]
+    execution_status: Option<String>,
+    #[serde(default)]
+    revert_error: Option<String>,
+    #[serde(default)]
+    execution_resources: Option<ExecutionResources>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    from_address: String,
+    to_address: String,
+    payload: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ExecutionResources {
+    #[serde(default)]
+    total_gas_consumed: Option<GasConsumed>,
+}
+
+#[derive(Deserialize)]
+struct GasConsumed {
+    #[serde(default)]
+    l1_gas: u128,
+    #[serde(default)]
+    l1_data_gas: u128,
+    #[serde(default)]
+    l2_gas: u128,
+}
+
+impl TryFrom<Block> for GatewayBlock {
+    type Error = jsonrpc::Error;
+
+    fn try_from(block: Block) -> Result<Self, Self::Error> {
+        let l1_da_mode = match block.l1_da_mode {
+            Some(DataAvailabilityMode::Calldata) => {
+                L1DataAvailabilityMode::Calldata
+            }
+            Some(DataAvailabilityMode::Blob) => L1DataAvailabilityMode::Blob,
+            None => return Err(incomplete_block("l1_da_mode")),
+        };
+        let l1_data_gas_price = block
+            .l1_data_gas_price
+            .ok_or_else(|| incomplete_block("l1_data_gas_price"))?
+            .try_into()?;
+        // Only served (and committed to) since 0.13.4.
+        let l2_gas_price = match block.l2_gas_price {
+            Some(price) => price.try_into()?,
+            None => GasPrice::default(),
+        };
+
+        let mut receipts = block.transaction_receipts;
+        receipts.sort_by_key(|receipt| receipt.transaction_index);
+        let receipts = receipts
+            .into_iter()
+            .map(Receipt::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { l1_da_mode, l1_data_gas_price, l2_gas_price, receipts })
+    }
+}
+
+impl TryFrom<Price> for GasPrice {
+    type Error = jsonrpc::Error;
+
+    fn try_from(price: Price) -> Result<Self, Self::Error> {
+        Ok(Self {
+            price_in_wei: felt(&price.price_in_wei)?,
+            price_in_fri: felt(&price.price_in_fri)?,
+        })
+    }
+}
+
+impl TryFrom<TransactionReceipt> for Receipt {
+    type Error = jsonrpc::Error;
+
+    fn try_from(receipt: TransactionReceipt) -> Result<Self, Self::Error> {
+        let actual_fee =
+            receipt.actual_fee.ok_or_else(|| incomplete_block("actual_fee"))?;
+        let gas = receipt
+            .execution_resources
+            .and_then(|resources| resources.total_gas_consumed)
+            .ok_or_else(|| incomplete_block("total_gas_consumed"))?;
+        let revert_reason = match receipt.execution_status.as_deref() {
+            Some("REVERTED") => Some(receipt.revert_error.unwrap_or_default()),
+            _ => None,
+        };
+        let messages_sent = receipt
+            .l2_to_l1_messages
+            .into_iter()
+            .map(|message| {
+                Ok(MessageToL1 {
+                    from_address: felt(&message.from_address)?,
+                    to_address: felt(&message.to_address)?,
+                    payload: message
+                        .payload
+                        .iter()
+                        .map(|value| felt(value))
+                        .collect::<Result<Vec<_>, _>>()?,
+                })
+            })
+            .collect::<Result<Vec<_>, jsonrpc::Error>>()?;
+        Ok(Self {
+            transaction_hash: felt(&receipt.transaction_hash)?,
+            actual_fee: felt(&actual_fee)?,
+            messages_sent,
+            revert_reason,
+            l1_gas: gas.l1_gas,
+            l1_data_gas: gas.l1_data_gas,
+            l2_gas: gas.l2_gas,
+        })
+    }
+}
+
+fn felt(value: &str) -> Result<FieldElement, jsonrpc::Error> {
+    FieldElement::from_hex_be(value).map_err(|_| {
+        jsonrpc::Error::new(
+            -1,
+            format!("Invalid felt received from feeder gateway: {value}"),
+        )
+    })
+}
+
+fn incomplete_block(field: &str) -> jsonrpc::Error {
+    jsonrpc::Error::new(
+        -1,
+        format!("Feeder gateway block is missing '{field}'"),
+    )
+}
+
+fn gateway_error(error: reqwest::Error) -> jsonrpc::Error {
+    jsonrpc::Error::new(-1, format!("Feeder gateway request failed: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block() -> serde_json::Value {
+        serde_json::json!({
+            "block_number": 7,
+            "starknet_version": "0.13.2",
+            "l1_da_mode": "BLOB",
+            "l1_data_gas_price": {
+                "price_in_wei": "0x1",
+                "price_in_fri": "0x2"
+            },
+            "transaction_receipts": [{
+                "transaction_index": 1,
+                "transaction_hash": "0x0b",
+                "actual_fee": "0xc",
+                "l2_to_l1_messages": [],
+                "execution_status": "REVERTED",
+                "revert_error": "out of gas",
+                "execution_resources": {
+                    "total_gas_consumed": {
+                        "l1_gas": 13,
+                        "l1_data_gas": 14
+                    }
+                }
+            }, {
+                "transaction_index": 0,
+                "transaction_hash": "0xa",
+                "actual_fee": "0xd",
+                "l2_to_l1_messages": [{
+                    "from_address": "0xe",
+                    "to_address": "0xAe0Ee0A63A2cE6BaeEFFE56e7714FB4EFE48D419",
+                    "payload": ["0xf"]
+                }],
+                "execution_status": "SUCCEEDED",
+                "execution_resources": {
+                    "total_gas_consumed": {
+                        "l1_gas": 0,
+                        "l1_data_gas": 128
+                    }
+                }
+            }]
+        })
+    }
+
+    fn parse(json: serde_json::Value) -> Result<GatewayBlock, jsonrpc::Error> {
+        serde_json::from_value::<Block>(json).unwrap().try_into()
+    }
+
+    #[test]
+    fn test_gateway_block() {
+        let block = parse(block()).unwrap();
+        assert_eq!(block.l1_da_mode, L1DataAvailabilityMode::Blob);
+        assert_eq!(block.l1_data_gas_price.price_in_fri, FieldElement::TWO);
+        assert_eq!(block.l2_gas_price.price_in_wei, FieldElement::ZERO);
+
+        // Receipts are ordered by transaction index.
+        let [first, second] = &block.receipts[..] else {
+            panic!("expected two receipts");
+        };
+        assert_eq!(first.transaction_hash, FieldElement::from(0xau64));
+        assert_eq!(first.revert_reason, None);
+        assert_eq!(first.l1_data_gas, 128);
+        assert_eq!(
+            first.messages_sent[0].to_address,
+            FieldElement::from_hex_be(
+                "0xae0ee0a63a2ce6baeeffe56e7714fb4efe48d419"
+            )
+            .unwrap()
+        );
+        assert_eq!(second.transaction_hash, FieldElement::from(0xbu64));
+        assert_eq!(second.revert_reason.as_deref(), Some("out of gas"));
+        assert_eq!((second.l1_gas, second.l2_gas), (13, 0));
+    }
+
+    #[test]
+    fn test_incomplete_gateway_block() {
+        let mut json = block();
+        json["l1_da_mode"] = serde_json::Value::Null;
+        assert!(parse(json).is_err());
+
+        let mut json = block();
+        json["transaction_receipts"][0]["execution_resources"] =
+            serde_json::json!({});
+        assert!(parse(json).is_err());
+    }
+}
@@ -2162,6 +2162,14 @@ pub mod gen {
         pub state_commitment: Option<Felt>,
     }
 
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct GetClassProofResult {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        pub class_commitment: Option<Felt>,
+        pub class_proof: Proof,
+    }
+
     pub mod error {
         pub const BLOCK_NOT_FOUND: Error = Error(24, "Block not found");
         pub const CLASS_ALREADY_DECLARED: Error =
@@ -2226,6 +2234,13 @@ pub mod gen {
 
     #[async_trait::async_trait]
     pub trait Rpc {
+        /// Returns merkle proofs of a class in the class commitment tree
+        async fn getClassProof(
+            &self,
+            block_id: BlockId,
+            class_hash: Felt,
+        ) -> std::result::Result<GetClassProofResult, jsonrpc::Error>;
+
         /// Returns merkle proofs of a contract's storage state
         async fn getProof(
             &self,
@@ -2425,6 +2440,51 @@ pub mod gen {
         ) -> std::result::Result<TransactionTrace, jsonrpc::Error>;
     }
 
+    async fn handle_getClassProof<RPC: Rpc>(
+        rpc: &RPC,
+        params: &Value,
+    ) -> jsonrpc::Response {
+        #[derive(Deserialize, Serialize)]
+        struct ArgByPos(BlockId, Felt);
+
+        #[derive(Deserialize, Serialize)]
+        struct ArgByName {
+            block_id: BlockId,
+            class_hash: Felt,
+        }
+
+        let args =
+            serde_json::from_value::<ArgByName>(params.clone()).or_else(|_| {
+                serde_json::from_value::<ArgByPos>(params.clone()).map(
+                    |args_by_pos| {
+                        let ArgByPos(block_id, class_hash) = args_by_pos;
+                        ArgByName { block_id, class_hash }
+                    },
+                )
+            });
+
+        let args: ArgByName = match args {
+            Ok(args) => args,
+            Err(error) => {
+                tracing::debug!(?error, "failed to parse request params");
+                return jsonrpc::Response::error(-32602, "Invalid params");
+            }
+        };
+
+        let ArgByName { block_id, class_hash } = args;
+
+        match rpc.getClassProof(block_id, class_hash).await {
+            Ok(ret) => match serde_json::to_value(ret) {
+                Ok(ret) => jsonrpc::Response::result(ret),
+                Err(error) => {
+                    tracing::debug!(?error, "failed to parse response object");
+                    jsonrpc::Response::error(-32603, "Internal error")
+                }
+            },
+            Err(e) => jsonrpc::Response::error(e.code, &e.message),
+        }
+    }
+
     async fn handle_getProof<RPC: Rpc>(
         rpc: &RPC,
         params: &Value,
@@ -3637,6 +3697,9 @@ pub mod gen {
         let params = &req.params.clone().unwrap_or_default();
 
         let response = match req.method.as_str() {
+            "pathfinder_getClassProof" => {
+                handle_getClassProof(rpc, params).await
+            }
             "pathfinder_getProof" => handle_getProof(rpc, params).await,
             "pathfinder_getTxStatus" => handle_getTxStatus(rpc, params).await,
             "pathfinder_version" => handle_version(rpc, params).await,
@@ -3715,6 +3778,13 @@ pub mod gen {
     pub mod blocking {
         use super::*;
         pub trait Rpc {
+            /// Returns merkle proofs of a class in the class commitment tree
+            fn getClassProof(
+                &self,
+                block_id: BlockId,
+                class_hash: Felt,
+            ) -> std::result::Result<GetClassProofResult, jsonrpc::Error>;
+
             /// Returns merkle proofs of a contract's storage state
             fn getProof(
                 &self,
@@ -3916,6 +3986,54 @@ pub mod gen {
             ) -> std::result::Result<TransactionTrace, jsonrpc::Error>;
         }
 
+        fn handle_getClassProof<RPC: Rpc>(
+            rpc: &RPC,
+            params: &Value,
+        ) -> jsonrpc::Response {
+            #[derive(Deserialize, Serialize)]
+            struct ArgByPos(BlockId, Felt);
+
+            #[derive(Deserialize, Serialize)]
+            struct ArgByName {
+                block_id: BlockId,
+                class_hash: Felt,
+            }
+
+            let args = serde_json::from_value::<ArgByName>(params.clone())
+                .or_else(|_| {
+                    serde_json::from_value::<ArgByPos>(params.clone()).map(
+                        |args_by_pos| {
+                            let ArgByPos(block_id, class_hash) = args_by_pos;
+                            ArgByName { block_id, class_hash }
+                        },
+                    )
+                });
+
+            let args: ArgByName = match args {
+                Ok(args) => args,
+                Err(error) => {
+                    tracing::debug!(?error, "failed to parse request params");
+                    return jsonrpc::Response::error(-32602, "Invalid params");
+                }
+            };
+
+            let ArgByName { block_id, class_hash } = args;
+
+            match rpc.getClassProof(block_id, class_hash) {
+                Ok(ret) => match serde_json::to_value(ret) {
+                    Ok(ret) => jsonrpc::Response::result(ret),
+                    Err(error) => {
+                        tracing::debug!(
+                            ?error,
+                            "failed to parse response object"
+                        );
+                        jsonrpc::Response::error(-32603, "Internal error")
+                    }
+                },
+                Err(e) => jsonrpc::Response::error(e.code, &e.message),
+            }
+        }
+
         fn handle_getProof<RPC: Rpc>(
             rpc: &RPC,
             params: &Value,
@@ -5214,6 +5332,7 @@ pub mod gen {
             let params = &req.params.clone().unwrap_or_default();
 
             let response = match req.method.as_str() {
+                "pathfinder_getClassProof" => handle_getClassProof(rpc, params),
                 "pathfinder_getProof" => handle_getProof(rpc, params),
                 "pathfinder_getTxStatus" => handle_getTxStatus(rpc, params),
                 "pathfinder_version" => handle_version(rpc, params),
@@ -5305,6 +5424,78 @@ pub mod gen {
 
         #[async_trait::async_trait]
         impl super::Rpc for Client {
+            async fn getClassProof(
+                &self,
+                block_id: BlockId,
+                class_hash: Felt,
+            ) -> std::result::Result<GetClassProofResult, jsonrpc::Error>
+            {
+                let args = (block_id, class_hash);
+
+                let params: serde_json::Value = serde_json::to_value(args)
+                    .map_err(|e| {
+                        jsonrpc::Error::new(
+                            4001,
+                            format!("Invalid params: {e}."),
+                        )
+                    })?;
+                let req = jsonrpc::Request::new(
+                    "pathfinder_getClassProof".to_string(),
+                    params,
+                )
+                .with_id(jsonrpc::Id::Number(1));
+
+                tracing::debug!(request=?req, "processing");
+
+                let mut res: jsonrpc::Response = self
+                    .client
+                    .post(&self.url)
+                    .json(&req)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        jsonrpc::Error::new(
+                            4002,
+                            format!("Request failed: {e}."),
+                        )
+                    })?
+                    .json()
+                    .await
+                    .map_err(|e| {
+                        jsonrpc::Error::new(
+                            5001,
+                            format!("Invalid response JSON: {e}."),
+                        )
+                    })?;
+
+                tracing::debug!(response=?res, "processing");
+
+                if let Some(err) = res.error.take() {
+                    tracing::error!(error=?err, "failed");
+                    return Err(err);
+                }
+
+                if let Some(value) = res.result.take() {
+                    let ret: GetClassProofResult =
+                        serde_json::from_value(value).map_err(|e| {
+                            jsonrpc::Error::new(
+                                5002,
+                                format!("Invalid response object: {e}."),
+                            )
+                        })?;
+
+                    tracing::debug!(result=?ret, "ready");
+
+                    Ok(ret)
+                } else {
+                    tracing::error!("both error and result are missing");
+                    Err(jsonrpc::Error::new(
+                        5003,
+                        "Response missing".to_string(),
+                    ))
+                }
+            }
+
             async fn getProof(
                 &self,
                 block_id: BlockId,
@@ -7471,6 +7662,73 @@ pub mod gen {
             }
 
             impl super::super::blocking::Rpc for Client {
+                fn getClassProof(
+                    &self,
+                    block_id: BlockId,
+                    class_hash: Felt,
+                ) -> std::result::Result<GetClassProofResult, jsonrpc::Error>
+                {
+                    let args = (block_id, class_hash);
+
+                    let params: serde_json::Value = serde_json::to_value(args)
+                        .map_err(|e| {
+                            jsonrpc::Error::new(
+                                4001,
+                                format!("Invalid params: {e}."),
+                            )
+                        })?;
+                    let req = jsonrpc::Request::new(
+                        "pathfinder_getClassProof".to_string(),
+                        params,
+                    )
+                    .with_id(jsonrpc::Id::Number(1));
+
+                    tracing::debug!(request=?req, "processing");
+
+                    let mut res: jsonrpc::Response = ureq::post(&self.url)
+                        .send_json(&req)
+                        .map_err(|e| {
+                            jsonrpc::Error::new(
+                                4002,
+                                format!("Request failed: {e}."),
+                            )
+                        })?
+                        .into_json()
+                        .map_err(|e| {
+                            jsonrpc::Error::new(
+                                5001,
+                                format!("Invalid response JSON: {e}."),
+                            )
+                        })?;
+
+                    tracing::debug!(response=?res, "processing");
+
+                    if let Some(err) = res.error.take() {
+                        tracing::error!(error=?err, "failed");
+                        return Err(err);
+                    }
+
+                    if let Some(value) = res.result.take() {
+                        let ret: GetClassProofResult =
+                            serde_json::from_value(value).map_err(|e| {
+                                jsonrpc::Error::new(
+                                    5002,
+                                    format!("Invalid response object: {e}."),
+                                )
+                            })?;
+
+                        tracing::debug!(result=?ret, "ready");
+
+                        Ok(ret)
+                    } else {
+                        tracing::error!("both error and result are missing");
+                        Err(jsonrpc::Error::new(
+                            5003,
+                            "Response missing".to_string(),
+                        ))
+                    }
+                }
+
                 fn getProof(
                     &self,
                     block_id: BlockId,
@@ -1,11 +1,15 @@
+pub mod block;
+pub mod class;
 pub mod client;
 pub mod config;
 pub mod eth;
 pub mod exe;
+pub mod feeder;
 pub mod gen;
 pub mod proof;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod rpc;
 
+pub mod transaction;
 pub mod util;
@@ -1,12 +1,17 @@
+use bitvec::prelude::{BitSlice, Msb0};
 use iamgroot::jsonrpc;
-use starknet_crypto::{pedersen_hash, poseidon_hash_many, FieldElement};
+use starknet_crypto::{
+    pedersen_hash, poseidon_hash, poseidon_hash_many, FieldElement,
+};
+use thiserror::Error as ThisError;
 
 use crate::gen::{
-    Address, BinaryNode, BinaryNodeBinary, ContractData, EdgeNode,
-    EdgeNodeEdge, Felt, GetProofResult, Node, StorageKey,
+    blocking, Address, BinaryNode, BinaryNodeBinary, BlockId, ContractData,
+    EdgeNode, EdgeNodeEdge, Felt, GetClassProofResult, GetProofResult, Node,
+    Rpc, StorageKey,
 };
 
-use crate::util::{felt_from_bits, felt_to_bits};
+use crate::util::felt_to_bits;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
@@ -24,6 +29,62 @@ impl From<bool> for Direction {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, ThisError)]
+pub enum ProofError {
+    #[error("no contract data found")]
+    MissingContractData,
+    #[error("no storage proof found")]
+    MissingStorageProof,
+    #[error("no class commitment found")]
+    MissingClassCommitment,
+    #[error("no state commitment found")]
+    MissingStateCommitment,
+    #[error("storage proof count mismatch: {keys} keys, {proofs} proofs")]
+    ProofCountMismatch { keys: usize, proofs: usize },
+    #[error("path length mismatch: expected {expected}, actual {actual}")]
+    PathLengthMismatch { expected: i64, actual: i64 },
+    #[error(
+        "hash mismatch at depth {depth}: expected {expected}, computed {computed}"
+    )]
+    HashMismatch { depth: i64, expected: String, computed: String },
+    #[error("root mismatch: expected {expected}, computed {computed}")]
+    RootMismatch { expected: String, computed: String },
+    #[error("{name} mismatch: provided {provided}, proven {proven}")]
+    ValueMismatch { name: &'static str, provided: String, proven: String },
+    #[error(
+        "edge path mismatch at depth {depth}: expected {expected}, actual {actual}"
+    )]
+    EdgePathMismatch { depth: i64, expected: String, actual: String },
+    #[error("class {0} is not declared")]
+    UndeclaredClass(String),
+    #[error("invalid felt: {0}")]
+    InvalidFelt(String),
+}
+
+impl ProofError {
+    /// True if the proof is well-formed but does not verify, i.e. the
+    /// provider returned data that contradicts the trusted root. False if
+    /// the proof could not be checked because it is incomplete or malformed.
+    pub fn is_invalid(&self) -> bool {
+        matches!(
+            self,
+            Self::PathLengthMismatch { .. }
+                | Self::HashMismatch { .. }
+                | Self::EdgePathMismatch { .. }
+                | Self::RootMismatch { .. }
+                | Self::ValueMismatch { .. }
+                | Self::UndeclaredClass(_)
+        )
+    }
+}
+
+impl From<ProofError> for jsonrpc::Error {
+    fn from(error: ProofError) -> Self {
+        let code = if error.is_invalid() { -32700 } else { -32701 };
+        jsonrpc::Error::new(code, error.to_string())
+    }
+}
+
 impl GetProofResult {
     pub fn verify(
         &self,
@@ -31,43 +92,162 @@ impl GetProofResult {
         contract_address: Address,
         key: StorageKey,
         value: Felt,
-    ) -> Result<(), jsonrpc::Error> {
-        let contract_data = self.contract_data.as_ref().ok_or(
-            jsonrpc::Error::new(-32700, "No contract data found".to_string()),
-        )?;
-        self.verify_storage_proofs(contract_data, key, value)?;
+    ) -> Result<(), ProofError> {
+        self.verify_batch(global_root, contract_address, &[(key, value)])
+    }
+
+    /// Verify the values of many storage keys of the same contract, given in
+    /// the same order as the keys were passed to `pathfinder_getProof`. The
+    /// contract proof is verified only once for the whole batch.
+    pub fn verify_batch(
+        &self,
+        global_root: Felt,
+        contract_address: Address,
+        entries: &[(StorageKey, Felt)],
+    ) -> Result<(), ProofError> {
+        let contract_data = self
+            .contract_data
+            .as_ref()
+            .ok_or(ProofError::MissingContractData)?;
+        self.verify_storage_proofs(contract_data, entries)?;
         self.verify_contract_proof(contract_data, global_root, contract_address)
     }
 
+    pub fn verify_nonce(
+        &self,
+        global_root: Felt,
+        contract_address: Address,
+        nonce: Felt,
+    ) -> Result<(), ProofError> {
+        let contract_data =
+            self.verify_contract_data(global_root, contract_address)?;
+        check_value("nonce", &nonce, &contract_data.nonce)
+    }
+
+    pub fn verify_class_hash(
+        &self,
+        global_root: Felt,
+        contract_address: Address,
+        class_hash: Felt,
+    ) -> Result<(), ProofError> {
+        let contract_data =
+            self.verify_contract_data(global_root, contract_address)?;
+        check_value("class hash", &class_hash, &contract_data.class_hash)
+    }
+
+    /// Verify the contract proof against the global root and return the class
+    /// hash and nonce of the contract. Without contract data, the proof must
+    /// show that the contract is not deployed, and both are zero.
+    pub fn verify_contract_state(
+        &self,
+        global_root: Felt,
+        contract_address: Address,
+    ) -> Result<(Felt, Felt), ProofError> {
+        let Some(contract_data) = self.contract_data.as_ref() else {
+            let zero = as_felt(FieldElement::ZERO)?;
+            let storage_commitment = Self::parse_proof(
+                contract_address.0.as_ref(),
+                zero.clone(),
+                &self.contract_proof,
+            )?;
+            self.verify_global_root(global_root, storage_commitment)?;
+            return Ok((zero.clone(), zero));
+        };
+        self.verify_contract_proof(
+            contract_data,
+            global_root,
+            contract_address,
+        )?;
+        Ok((contract_data.class_hash.clone(), contract_data.nonce.clone()))
+    }
+
+    /// Verify that the class commitment of the proof is the one the global
+    /// root commits to and return it. The storage commitment is taken from
+    /// the top node of the contract proof, so any contract address will do.
+    pub fn verify_class_commitment(
+        &self,
+        global_root: Felt,
+    ) -> Result<Felt, ProofError> {
+        let storage_commitment = match self.contract_proof.first() {
+            Some(node) => node_hash(node, pedersen)?,
+            None => FieldElement::ZERO,
+        };
+        self.verify_global_root(global_root, as_felt(storage_commitment)?)?;
+        self.class_commitment.clone().ok_or(ProofError::MissingClassCommitment)
+    }
+
+    /// Contract address to prove when only the class commitment is needed
+    /// from the proof: the class commitment is the same for the proof of
+    /// any contract address.
+    fn class_commitment_address() -> Result<Address, jsonrpc::Error> {
+        Ok(Address(Felt::try_new("0x1")?))
+    }
+
+    /// Fetch a proof of the state at the block and return the class
+    /// commitment, verified against the global root.
+    pub async fn fetch_class_commitment(
+        client: &(impl Rpc + Sync),
+        block_id: BlockId,
+        global_root: Felt,
+    ) -> Result<Felt, jsonrpc::Error> {
+        let address = Self::class_commitment_address()?;
+        let proof = client.getProof(block_id, address, vec![]).await?;
+        Ok(proof.verify_class_commitment(global_root)?)
+    }
+
+    /// Blocking version of `fetch_class_commitment`.
+    pub fn fetch_class_commitment_blocking(
+        client: &impl blocking::Rpc,
+        block_id: BlockId,
+        global_root: Felt,
+    ) -> Result<Felt, jsonrpc::Error> {
+        let address = Self::class_commitment_address()?;
+        let proof = client.getProof(block_id, address, vec![])?;
+        Ok(proof.verify_class_commitment(global_root)?)
+    }
+
+    /// Verify the contract proof against the global root and return the
+    /// contract data (class hash, nonce, storage root) it commits to.
+    fn verify_contract_data(
+        &self,
+        global_root: Felt,
+        contract_address: Address,
+    ) -> Result<&ContractData, ProofError> {
+        let contract_data = self
+            .contract_data
+            .as_ref()
+            .ok_or(ProofError::MissingContractData)?;
+        self.verify_contract_proof(
+            contract_data,
+            global_root,
+            contract_address,
+        )?;
+        Ok(contract_data)
+    }
+
     fn verify_storage_proofs(
         &self,
         contract_data: &ContractData,
-        key: StorageKey,
-        value: Felt,
-    ) -> Result<(), jsonrpc::Error> {
-        let root = &contract_data.root;
-        let storage_proofs = &contract_data.storage_proofs.as_ref().ok_or(
-            jsonrpc::Error::new(-32700, "No storage proof found".to_string()),
-        )?[0];
-
-        match Self::parse_proof(key.as_ref(), value, storage_proofs)? {
-            Some(computed_root) if computed_root.as_ref() == root.as_ref() => {
-                Ok(())
-            }
-            Some(computed_root)  => {
-                Err(jsonrpc::Error::new(
-                    -32700,
-                    format!(
-                        "Proof invalid:\nprovided-root -> {}\ncomputed-root -> {}\n",
-                        root.as_ref(), computed_root.as_ref()
-                    ),
-                ))
-            },
-            None => Err(jsonrpc::Error::new(
-                -32700,
-                format!("Proof invalid for root -> {}\n", root.as_ref()),
-            )),
+        entries: &[(StorageKey, Felt)],
+    ) -> Result<(), ProofError> {
+        let storage_proofs = contract_data
+            .storage_proofs
+            .as_ref()
+            .ok_or(ProofError::MissingStorageProof)?;
+        if storage_proofs.len() != entries.len() {
+            return Err(ProofError::ProofCountMismatch {
+                keys: entries.len(),
+                proofs: storage_proofs.len(),
+            });
         }
+
+        for ((key, value), storage_proof) in entries.iter().zip(storage_proofs)
+        {
+            let computed_root =
+                Self::parse_proof(key.as_ref(), value.clone(), storage_proof)?;
+            check_root(&contract_data.root, &computed_root)?;
+        }
+        Ok(())
     }
 
     fn verify_contract_proof(
@@ -75,208 +255,172 @@ impl GetProofResult {
         contract_data: &ContractData,
         global_root: Felt,
         contract_address: Address,
-    ) -> Result<(), jsonrpc::Error> {
+    ) -> Result<(), ProofError> {
         let state_hash = Self::calculate_contract_state_hash(contract_data)?;
-
-        match Self::parse_proof(
+        let storage_commitment = Self::parse_proof(
             contract_address.0.as_ref(),
             state_hash,
             &self.contract_proof,
-        )? {
-            Some(storage_commitment) => {
-                let class_commitment = self.class_commitment.as_ref().ok_or(
-                    jsonrpc::Error::new(
-                        -32700,
-                        "No class commitment".to_string(),
-                    ),
-                )?;
-                let parsed_global_root = Self::calculate_global_root(
-                    class_commitment,
-                    storage_commitment,
-                )
-                .map_err(|_| {
-                    jsonrpc::Error::new(
-                        -32700,
-                        "Failed to calculate global root".to_string(),
-                    )
-                })?;
-                let state_commitment = self.state_commitment.as_ref().ok_or(
-                    jsonrpc::Error::new(
-                        -32700,
-                        "No state commitment".to_string(),
-                    ),
-                )?;
-                if state_commitment.as_ref() == parsed_global_root.as_ref()
-                    && global_root.as_ref() == parsed_global_root.as_ref()
-                {
-                    Ok(())
-                } else {
-                    Err(jsonrpc::Error::new(
-                        -32700,
-                        format!("Proof invalid:\nstate commitment -> {}\nparsed global root -> {}\n global root -> {}", 
-                        state_commitment.as_ref(), parsed_global_root.as_ref(), global_root.as_ref())
-                    ))
-                }
-            }
-            None => Err(jsonrpc::Error::new(
-                -32700,
-                format!(
-                    "Could not parse global root for root: {}",
-                    global_root.as_ref()
-                ),
-            )),
-        }
+        )?;
+        self.verify_global_root(global_root, storage_commitment)
+    }
+
+    fn verify_global_root(
+        &self,
+        global_root: Felt,
+        storage_commitment: Felt,
+    ) -> Result<(), ProofError> {
+        let class_commitment = self
+            .class_commitment
+            .as_ref()
+            .ok_or(ProofError::MissingClassCommitment)?;
+        let parsed_global_root =
+            Self::calculate_global_root(class_commitment, storage_commitment)?;
+        let state_commitment = self
+            .state_commitment
+            .as_ref()
+            .ok_or(ProofError::MissingStateCommitment)?;
+        check_root(state_commitment, &parsed_global_root)?;
+        check_root(&global_root, &parsed_global_root)
     }
 
     fn calculate_contract_state_hash(
         contract_data: &ContractData,
-    ) -> Result<Felt, jsonrpc::Error> {
+    ) -> Result<Felt, ProofError> {
         // The contract state hash is defined as H(H(H(hash, root), nonce), CONTRACT_STATE_HASH_VERSION)
         const CONTRACT_STATE_HASH_VERSION: FieldElement = FieldElement::ZERO;
         let hash = pedersen_hash(
-            &FieldElement::from_hex_be(contract_data.class_hash.as_ref())
-                .map_err(|_| {
-                    jsonrpc::Error::new(
-                        -32701,
-                        "Failed to create Field Element".to_string(),
-                    )
-                })?,
-            &FieldElement::from_hex_be(contract_data.root.as_ref()).map_err(
-                |_| {
-                    jsonrpc::Error::new(
-                        -32701,
-                        "Failed to create Field Element".to_string(),
-                    )
-                },
-            )?,
-        );
-        let hash = pedersen_hash(
-            &hash,
-            &FieldElement::from_hex_be(contract_data.nonce.as_ref()).map_err(
-                |_| {
-                    jsonrpc::Error::new(
-                        -32701,
-                        "Failed to create Field Element".to_string(),
-                    )
-                },
-            )?,
+            &as_field_element(&contract_data.class_hash)?,
+            &as_field_element(&contract_data.root)?,
         );
+        let hash =
+            pedersen_hash(&hash, &as_field_element(&contract_data.nonce)?);
         let hash = pedersen_hash(&hash, &CONTRACT_STATE_HASH_VERSION);
-        Felt::try_new(&format!("0x{:x}", hash)).map_err(|_| {
-            jsonrpc::Error::new(
-                -32701,
-                "Failed to create Field Element".to_string(),
-            )
-        })
+        as_felt(hash)
     }
 
     fn calculate_global_root(
         class_commitment: &Felt,
         storage_commitment: Felt,
-    ) -> Result<Felt, jsonrpc::Error> {
+    ) -> Result<Felt, ProofError> {
         let global_state_ver = FieldElement::from_byte_slice_be(
             b"STARKNET_STATE_V0",
         )
-        .map_err(|_| {
-            jsonrpc::Error::new(
-                -32701,
-                "Failed to create Field Element".to_string(),
-            )
-        })?;
+        .map_err(|_| ProofError::InvalidFelt("STARKNET_STATE_V0".to_owned()))?;
         let hash = poseidon_hash_many(&[
             global_state_ver,
-            FieldElement::from_hex_be(storage_commitment.as_ref()).map_err(
-                |_| {
-                    jsonrpc::Error::new(
-                        -32701,
-                        "Failed to create Field Element".to_string(),
-                    )
-                },
-            )?,
-            FieldElement::from_hex_be(class_commitment.as_ref()).map_err(
-                |_| {
-                    jsonrpc::Error::new(
-                        -32701,
-                        "Failed to create Field Element".to_string(),
-                    )
-                },
-            )?,
+            as_field_element(&storage_commitment)?,
+            as_field_element(class_commitment)?,
         ]);
-        Felt::try_new(&format!("0x{:x}", hash)).map_err(|_| {
-            jsonrpc::Error::new(
-                -32701,
-                "Failed to create Field Element".to_string(),
-            )
-        })
+        as_felt(hash)
     }
 
     fn parse_proof(
         key: impl Into<String>,
         value: Felt,
         proof: &[Node],
-    ) -> Result<Option<Felt>, jsonrpc::Error> {
+    ) -> Result<Felt, ProofError> {
+        Self::parse_proof_with_hash(key, value, proof, pedersen)
+    }
+
+    fn parse_proof_with_hash(
+        key: impl Into<String>,
+        value: Felt,
+        proof: &[Node],
+        hash: HashFn,
+    ) -> Result<Felt, ProofError> {
+        let (root, path_len) = Self::hash_path(key, value, proof, hash)?;
+        // a proof that stops short of the leaves only proves a subtree
+        if path_len != 251 {
+            return Err(ProofError::PathLengthMismatch {
+                expected: 251,
+                actual: path_len,
+            });
+        }
+        as_felt(root)
+    }
+
+    /// Hash the proof from the leaf holding the value towards the root,
+    /// checking that every node is on the path of the key. Return the hash
+    /// of the topmost node and the number of levels the proof spans.
+    fn hash_path(
+        key: impl Into<String>,
+        value: Felt,
+        proof: &[Node],
+        hash: HashFn,
+    ) -> Result<(FieldElement, i64), ProofError> {
+        let key = key.into();
         let key = felt_to_bits(
-            FieldElement::from_hex_be(&key.into()).map_err(|_| {
-                jsonrpc::Error::new(
-                    -32701,
-                    "Failed to create Field Element".to_string(),
-                )
-            })?,
+            FieldElement::from_hex_be(&key)
+                .map_err(|_| ProofError::InvalidFelt(key))?,
         );
         if key.len() != 251 {
-            return Ok(None);
+            return Err(ProofError::PathLengthMismatch {
+                expected: 251,
+                actual: key.len() as i64,
+            });
         }
-        let value =
-            FieldElement::from_hex_be(value.as_ref()).map_err(|_| {
-                jsonrpc::Error::new(
-                    -32701,
-                    "Failed to create Field Element".to_string(),
-                )
-            })?;
+        let value = as_field_element(&value)?;
+        // an empty tree has a zero root and holds no value but zero
+        if proof.is_empty() {
+            if value != FieldElement::ZERO {
+                return Err(ProofError::ValueMismatch {
+                    name: "value",
+                    provided: format!("0x{:x}", value),
+                    proven: "0x0".to_owned(),
+                });
+            }
+            return Ok((FieldElement::ZERO, 251));
+        }
+        // a zero value is also proven by the absence of the key, in which
+        // case the walk starts from where the diverging edge ends
+        let non_membership = if value == FieldElement::ZERO {
+            Self::diverging_edge_end(&key, proof)
+        } else {
+            None
+        };
         // initialized to the value so if the last node
         // in the proof is a binary node we can still verify
-        let (mut hold, mut path_len) = (value, 0);
+        let (mut hold, mut path_len) =
+            (value, non_membership.map_or(0, |end| 251 - end));
         // reverse the proof in order to hash from the leaf towards the root
         for (i, node) in proof.iter().rev().enumerate() {
             match node {
                 Node::EdgeNode(EdgeNode {
                     edge: EdgeNodeEdge { child, path },
                 }) => {
-                    // calculate edge hash given by provider
-                    let child_felt = FieldElement::from_hex_be(child.as_ref())
-                        .map_err(|_| {
-                            jsonrpc::Error::new(
-                                -32701,
-                                "Failed to create Field Element".to_string(),
-                            )
-                        })?;
-                    let path_value =
-                        FieldElement::from_hex_be(path.value.as_ref())
-                            .map_err(|_| {
-                                jsonrpc::Error::new(
-                                    -32701,
-                                    "Failed to create Field Element"
-                                        .to_string(),
-                                )
-                            })?;
-                    let provided_hash = pedersen_hash(&child_felt, &path_value)
+                    if path.len <= 0 || path_len + path.len > 251 {
+                        return Err(ProofError::PathLengthMismatch {
+                            expected: 251,
+                            actual: path_len + path.len,
+                        });
+                    }
+                    let depth = 251 - path_len - path.len;
+                    let child_felt = as_field_element(child)?;
+                    let path_value = as_field_element(&path.value)?;
+                    let provided_hash = hash(&child_felt, &path_value)
                         + FieldElement::from(path.len as u64);
-                    if i == 0 {
-                        // mask storage key
-                        let computed_hash = match felt_from_bits(
-                            &key,
-                            Some(251 - path.len as usize),
-                        ) {
-                            Ok(masked_key) => {
-                                pedersen_hash(&value, &masked_key)
-                                    + FieldElement::from(path.len as u64)
-                            }
-                            Err(_) => return Ok(None),
-                        };
-                        // verify computed hash against provided hash
-                        if provided_hash != computed_hash {
-                            return Ok(None);
-                        };
+                    // the diverging edge of a non-membership proof leads
+                    // away from the key, every other edge must lead to the
+                    // node hashed so far along the bits of the key
+                    if i > 0 || non_membership.is_none() {
+                        if child_felt != hold {
+                            return Err(ProofError::HashMismatch {
+                                depth,
+                                expected: format!("0x{:x}", hold),
+                                computed: format!("0x{:x}", child_felt),
+                            });
+                        }
+                        let key_path = bits_value(
+                            &key[depth as usize..][..path.len as usize],
+                        );
+                        if path_value != key_path {
+                            return Err(ProofError::EdgePathMismatch {
+                                depth,
+                                expected: format!("0x{:x}", key_path),
+                                actual: format!("0x{:x}", path_value),
+                            });
+                        }
                     }
 
                     // walk up the remaining path
@@ -287,44 +431,302 @@ impl GetProofResult {
                     binary: BinaryNodeBinary { left, right },
                 }) => {
                     path_len += 1;
-                    let left = FieldElement::from_hex_be(left.as_ref())
-                        .map_err(|_| {
-                            jsonrpc::Error::new(
-                                -32701,
-                                "Failed to create Field Element".to_string(),
-                            )
-                        })?;
-                    let right = FieldElement::from_hex_be(right.as_ref())
-                        .map_err(|_| {
-                            jsonrpc::Error::new(
-                                -32701,
-                                "Failed to create Field Element".to_string(),
-                            )
-                        })?;
+                    if path_len > 251 {
+                        return Err(ProofError::PathLengthMismatch {
+                            expected: 251,
+                            actual: path_len,
+                        });
+                    }
+                    let left = as_field_element(left)?;
+                    let right = as_field_element(right)?;
                     // identify path direction for this node
                     let expected_hash =
                         match Direction::from(key[251 - path_len as usize]) {
-                            Direction::Left => pedersen_hash(&hold, &right),
-                            Direction::Right => pedersen_hash(&left, &hold),
+                            Direction::Left => hash(&hold, &right),
+                            Direction::Right => hash(&left, &hold),
                         };
 
-                    hold = pedersen_hash(&left, &right);
+                    hold = hash(&left, &right);
                     // verify calculated hash vs provided hash for the node
                     if hold != expected_hash {
-                        return Ok(None);
+                        return Err(ProofError::HashMismatch {
+                            depth: 251 - path_len,
+                            expected: format!("0x{:x}", hold),
+                            computed: format!("0x{:x}", expected_hash),
+                        });
                     };
                 }
             };
         }
 
-        Ok(Some(Felt::try_new(&format!("0x{:x}", hold))?))
+        Ok((hold, path_len))
+    }
+
+    /// Return the value of the leaf the proof ends at, or zero if the proof
+    /// shows that the key is not in the tree. The proof is not verified.
+    fn proven_value(key: &str, proof: &[Node]) -> Result<Felt, ProofError> {
+        let zero = as_felt(FieldElement::ZERO)?;
+        let Some((last, rest)) = proof.split_last() else {
+            return Ok(zero);
+        };
+        let key = felt_to_bits(
+            FieldElement::from_hex_be(key)
+                .map_err(|_| ProofError::InvalidFelt(key.to_owned()))?,
+        );
+        if key.len() != 251 {
+            return Err(ProofError::PathLengthMismatch {
+                expected: 251,
+                actual: key.len() as i64,
+            });
+        }
+        if Self::diverging_edge_end(&key, proof).is_some() {
+            return Ok(zero);
+        }
+        let depth: i64 = rest.iter().map(path_len).sum();
+        match last {
+            Node::EdgeNode(EdgeNode { edge: EdgeNodeEdge { child, path } })
+                if depth + path.len == 251 =>
+            {
+                Ok(child.clone())
+            }
+            Node::BinaryNode(BinaryNode {
+                binary: BinaryNodeBinary { left, right },
+            }) if depth == 250 => Ok(match Direction::from(key[250]) {
+                Direction::Left => left.clone(),
+                Direction::Right => right.clone(),
+            }),
+            _ => Err(ProofError::PathLengthMismatch {
+                expected: 251,
+                actual: depth + path_len(last),
+            }),
+        }
+    }
+
+    /// Return the depth at which the last node of the proof ends, if that
+    /// node is an edge whose path diverges from the key. Such an edge proves
+    /// that the key is not present in the tree.
+    fn diverging_edge_end(
+        key: &BitSlice<u8, Msb0>,
+        proof: &[Node],
+    ) -> Option<i64> {
+        let (
+            Node::EdgeNode(EdgeNode { edge: EdgeNodeEdge { path, .. } }),
+            rest,
+        ) = proof.split_last()?
+        else {
+            return None;
+        };
+        let start: i64 = rest.iter().map(path_len).sum();
+        let end = start + path.len;
+        if path.len <= 0 || end > 251 {
+            return None;
+        }
+        let key_path = bits_value(&key[start as usize..end as usize]);
+        let path_value = FieldElement::from_hex_be(path.value.as_ref()).ok()?;
+        (key_path != path_value).then_some(end)
     }
 }
+
+impl GetClassProofResult {
+    /// Verify that the class commitment tree maps the class hash to the
+    /// compiled class hash, i.e. that the class has been declared.
+    pub fn verify(
+        &self,
+        class_commitment: &Felt,
+        class_hash: Felt,
+        compiled_class_hash: Felt,
+    ) -> Result<(), ProofError> {
+        if let Some(provided) = self.class_commitment.as_ref() {
+            check_root(class_commitment, provided)?;
+        }
+
+        let leaf = Self::calculate_class_leaf(compiled_class_hash)?;
+        let computed_root = GetProofResult::parse_proof_with_hash(
+            class_hash.as_ref(),
+            leaf,
+            &self.class_proof,
+            poseidon,
+        )?;
+        check_root(class_commitment, &computed_root)
+    }
+
+    /// Verify that the class commitment tree has a leaf for the class hash,
+    /// i.e. that the class has been declared, whatever compiled class hash
+    /// the leaf commits to, and return the leaf.
+    pub fn verify_declared(
+        &self,
+        class_commitment: &Felt,
+        class_hash: Felt,
+    ) -> Result<Felt, ProofError> {
+        if let Some(provided) = self.class_commitment.as_ref() {
+            check_root(class_commitment, provided)?;
+        }
+
+        let leaf = GetProofResult::proven_value(
+            class_hash.as_ref(),
+            &self.class_proof,
+        )?;
+        // the proof is checked first, so that a class is only reported as
+        // undeclared when its absence from the tree is proven
+        let computed_root = GetProofResult::parse_proof_with_hash(
+            class_hash.as_ref(),
+            leaf.clone(),
+            &self.class_proof,
+            poseidon,
+        )?;
+        check_root(class_commitment, &computed_root)?;
+        if as_field_element(&leaf)? == FieldElement::ZERO {
+            return Err(ProofError::UndeclaredClass(
+                class_hash.as_ref().clone(),
+            ));
+        }
+        Ok(leaf)
+    }
+
+    fn calculate_class_leaf(
+        compiled_class_hash: Felt,
+    ) -> Result<Felt, ProofError> {
+        // The leaf is defined as H(CONTRACT_CLASS_LEAF_V0, compiled_class_hash)
+        let class_leaf_ver =
+            FieldElement::from_byte_slice_be(b"CONTRACT_CLASS_LEAF_V0")
+                .map_err(|_| {
+                    ProofError::InvalidFelt("CONTRACT_CLASS_LEAF_V0".to_owned())
+                })?;
+        let hash = poseidon_hash(
+            class_leaf_ver,
+            as_field_element(&compiled_class_hash)?,
+        );
+        as_felt(hash)
+    }
+}
+
+/// A node of a proof as visited from the root towards the leaf.
+#[derive(Debug, Clone)]
+pub struct NodeTrace {
+    /// Depth in the tree at which the node starts.
+    pub depth: i64,
+    pub node: Node,
+    /// Side the key goes to, for binary nodes.
+    pub direction: Option<Direction>,
+    pub hash: Felt,
+}
+
+/// Trace the nodes of a contract or storage proof for the key, computing the
+/// hash of each node. This does not verify the proof, see `verify` for that.
+pub fn trace_proof(
+    key: impl Into<String>,
+    proof: &[Node],
+) -> Result<Vec<NodeTrace>, ProofError> {
+    let key = key.into();
+    let key = felt_to_bits(
+        FieldElement::from_hex_be(&key)
+            .map_err(|_| ProofError::InvalidFelt(key))?,
+    );
+    let mut depth = 0;
+    let mut trace = Vec::with_capacity(proof.len());
+    for node in proof {
+        let hash = as_felt(node_hash(node, pedersen)?)?;
+        let (direction, len) = match node {
+            Node::BinaryNode(_) => {
+                let bit = key.get(depth as usize).as_deref().copied();
+                (bit.map(Direction::from), 1)
+            }
+            Node::EdgeNode(EdgeNode { edge: EdgeNodeEdge { path, .. } }) => {
+                (None, path.len)
+            }
+        };
+        trace.push(NodeTrace { depth, node: node.clone(), direction, hash });
+        depth += len;
+    }
+    Ok(trace)
+}
+
+/// Value of a run of key bits, as the path of an edge node encodes it.
+fn bits_value(bits: &BitSlice<u8, Msb0>) -> FieldElement {
+    bits.iter().fold(FieldElement::ZERO, |acc, bit| {
+        acc + acc + if *bit { FieldElement::ONE } else { FieldElement::ZERO }
+    })
+}
+
+pub(crate) type HashFn = fn(&FieldElement, &FieldElement) -> FieldElement;
+
+/// Hash function of the contract and storage tries.
+pub(crate) fn pedersen(a: &FieldElement, b: &FieldElement) -> FieldElement {
+    pedersen_hash(a, b)
+}
+
+/// Hash function of the class commitment tree.
+pub(crate) fn poseidon(a: &FieldElement, b: &FieldElement) -> FieldElement {
+    poseidon_hash(*a, *b)
+}
+
+fn node_hash(node: &Node, hash: HashFn) -> Result<FieldElement, ProofError> {
+    Ok(match node {
+        Node::EdgeNode(EdgeNode { edge: EdgeNodeEdge { child, path } }) => {
+            hash(&as_field_element(child)?, &as_field_element(&path.value)?)
+                + FieldElement::from(path.len as u64)
+        }
+        Node::BinaryNode(BinaryNode {
+            binary: BinaryNodeBinary { left, right },
+        }) => hash(&as_field_element(left)?, &as_field_element(right)?),
+    })
+}
+
+/// Number of levels of the tree the node spans.
+fn path_len(node: &Node) -> i64 {
+    match node {
+        Node::BinaryNode(_) => 1,
+        Node::EdgeNode(EdgeNode { edge: EdgeNodeEdge { path, .. } }) => {
+            path.len
+        }
+    }
+}
+
+fn check_root(expected: &Felt, computed: &Felt) -> Result<(), ProofError> {
+    if as_field_element(expected)? != as_field_element(computed)? {
+        return Err(ProofError::RootMismatch {
+            expected: expected.as_ref().clone(),
+            computed: computed.as_ref().clone(),
+        });
+    }
+    Ok(())
+}
+
+fn check_value(
+    name: &'static str,
+    provided: &Felt,
+    proven: &Felt,
+) -> Result<(), ProofError> {
+    if as_field_element(provided)? != as_field_element(proven)? {
+        return Err(ProofError::ValueMismatch {
+            name,
+            provided: provided.as_ref().clone(),
+            proven: proven.as_ref().clone(),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn as_field_element(
+    felt: &Felt,
+) -> Result<FieldElement, ProofError> {
+    FieldElement::from_hex_be(felt.as_ref())
+        .map_err(|_| ProofError::InvalidFelt(felt.as_ref().clone()))
+}
+
+pub(crate) fn as_felt(felt: FieldElement) -> Result<Felt, ProofError> {
+    let hex = format!("0x{:x}", felt);
+    Felt::try_new(&hex).map_err(|_| ProofError::InvalidFelt(hex))
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{pedersen, trace_proof, Direction, ProofError};
     use crate::gen::{
-        Address, ContractData, Felt, GetProofResult, Node, StorageKey,
+        Address, ContractData, Felt, GetClassProofResult, GetProofResult, Node,
+        StorageKey,
     };
+    use starknet_crypto::FieldElement;
 
     #[test]
     fn valid_one_level_parse_proof() {
@@ -341,12 +743,13 @@ mod tests {
             }
         }]"#;
         let proof: Vec<Node> = serde_json::from_str(edge_node_string).unwrap();
-        let ret_val = GetProofResult::parse_proof(key, value, &proof).unwrap();
+        // the proof only covers the subtree below depth 20
+        let (root, path_len) =
+            GetProofResult::hash_path(key, value, &proof, pedersen).unwrap();
 
-        assert!(ret_val.is_some());
-        let ret_val = ret_val.unwrap();
+        assert_eq!(path_len, 231);
         assert_eq!(
-            ret_val.as_ref(),
+            format!("0x{:x}", root),
             "0x1e224db31dfb3e1b8c95670a12f1903d4a32ac7bb83f4b209029e14155bbca9"
         );
     }
@@ -394,12 +797,13 @@ mod tests {
             }
         }]"#;
         let proof: Vec<Node> = serde_json::from_str(proof_string).unwrap();
-        let ret_val = GetProofResult::parse_proof(key, value, &proof).unwrap();
+        // the proof only covers the subtree below depth 16
+        let (root, path_len) =
+            GetProofResult::hash_path(key, value, &proof, pedersen).unwrap();
 
-        assert!(ret_val.is_some());
-        let ret_val = ret_val.unwrap();
+        assert_eq!(path_len, 235);
         assert_eq!(
-            ret_val.as_ref(),
+            format!("0x{:x}", root),
             "0x6cc50a732b4256f7b642348e19bd1a8bee7ac76bed3fcee3bc34309538c00c6"
         );
     }
@@ -420,9 +824,10 @@ mod tests {
         }]"#,
         )
         .unwrap();
-        assert!(GetProofResult::parse_proof(key, value, &proof)
-            .unwrap()
-            .is_none());
+        assert!(matches!(
+            GetProofResult::parse_proof(key, value, &proof),
+            Err(ProofError::HashMismatch { depth: 250, .. })
+        ));
     }
 
     #[test]
@@ -440,9 +845,7 @@ mod tests {
             }
         }]"#;
         let proof: Vec<Node> = serde_json::from_str(edge_node_string).unwrap();
-        assert!(GetProofResult::parse_proof(key, value, &proof)
-            .unwrap()
-            .is_none());
+        assert!(GetProofResult::parse_proof(key, value, &proof).is_err());
     }
 
     #[test]
@@ -488,9 +891,7 @@ mod tests {
             }
         }]"#;
         let proof: Vec<Node> = serde_json::from_str(proof_string).unwrap();
-        assert!(GetProofResult::parse_proof(key, value, &proof)
-            .unwrap()
-            .is_none());
+        assert!(GetProofResult::parse_proof(key, value, &proof).is_err());
     }
 
     #[test]
@@ -527,9 +928,54 @@ mod tests {
         };
         let contract_data = storage_proof.contract_data.as_ref().unwrap();
 
-        assert!(storage_proof
-            .verify_storage_proofs(contract_data, key, value)
-            .is_ok());
+        // the recorded proof only covers the subtree below depth 20
+        let storage_proofs = contract_data.storage_proofs.as_ref().unwrap();
+        let (root, path_len) = GetProofResult::hash_path(
+            key.as_ref(),
+            value,
+            &storage_proofs[0],
+            pedersen,
+        )
+        .unwrap();
+        assert_eq!(path_len, 231);
+        assert_eq!(format!("0x{:x}", root), contract_data.root.as_ref());
+    }
+
+    #[test]
+    fn invalid_verify_storage_proof_short_of_root() {
+        // the recorded proof of the previous test, without the upper levels
+        let key = StorageKey::try_new(
+            "0x0341c1bdfd89f69748aa00b5742b03adbffd79b8e80cab5c50d91cd8c2a79be1",
+        )
+        .unwrap();
+        let value =
+            Felt::try_new("0x47616d65206f66204c69666520546f6b656e").unwrap();
+        let proof = vec![edge(
+            &value,
+            231,
+            "0x3dfd89f69748aa00b5742b03adbffd79b8e80cab5c50d91cd8c2a79be1",
+        )];
+        let storage_proof = GetProofResult {
+            contract_data: Some(ContractData {
+                root: Felt::try_new(
+                    "0x1e224db31dfb3e1b8c95670a12f1903d4a32ac7bb83f4b209029e14155bbca9",
+                )
+                .unwrap(),
+                storage_proofs: Some(vec![proof]),
+                class_hash: Felt::try_new("0x0").unwrap(),
+                contract_state_hash_version: Felt::try_new("0x0").unwrap(),
+                nonce: Felt::try_new("0x0").unwrap(),
+            }),
+            class_commitment: Some(Felt::try_new("0x0").unwrap()),
+            contract_proof: vec![],
+            state_commitment: Some(Felt::try_new("0x0").unwrap()),
+        };
+        let contract_data = storage_proof.contract_data.as_ref().unwrap();
+
+        assert_eq!(
+            storage_proof.verify_storage_proofs(contract_data, &[(key, value)]),
+            Err(ProofError::PathLengthMismatch { expected: 251, actual: 231 })
+        );
     }
 
     #[test]
@@ -564,7 +1010,7 @@ mod tests {
         let contract_data = storage_proof.contract_data.as_ref().unwrap();
 
         assert!(storage_proof
-            .verify_storage_proofs(contract_data, key, value)
+            .verify_storage_proofs(contract_data, &[(key, value)])
             .is_err());
     }
 
@@ -643,9 +1089,38 @@ mod tests {
         let contract_address = Address(Felt::try_new("0x6a05844a03bb9e744479e3298f54705a35966ab04140d3d8dd797c1f6dc49d0")
                 .unwrap());
         let contract_data = storage_proof.contract_data.as_ref().unwrap();
-        assert!(storage_proof
-            .verify_contract_proof(contract_data, global_root, contract_address)
-            .is_ok());
+
+        // the recorded proof only covers the subtree below depth 22
+        let state_hash =
+            GetProofResult::calculate_contract_state_hash(contract_data)
+                .unwrap();
+        let (storage_commitment, path_len) = GetProofResult::hash_path(
+            contract_address.0.as_ref(),
+            state_hash,
+            &storage_proof.contract_proof,
+            pedersen,
+        )
+        .unwrap();
+        assert_eq!(path_len, 229);
+        let storage_commitment =
+            Felt::try_new(&format!("0x{:x}", storage_commitment)).unwrap();
+        let class_commitment = storage_proof.class_commitment.as_ref().unwrap();
+        let computed_root = GetProofResult::calculate_global_root(
+            class_commitment,
+            storage_commitment,
+        )
+        .unwrap();
+        assert_eq!(computed_root.as_ref(), global_root.as_ref());
+    }
+
+    #[test]
+    fn invalid_verify_contract_proof_short_of_root() {
+        let (proof, global_root, contract_address) = mainnet_contract_proof();
+        let nonce = Felt::try_new("0x0").unwrap();
+        assert_eq!(
+            proof.verify_nonce(global_root, contract_address, nonce),
+            Err(ProofError::PathLengthMismatch { expected: 251, actual: 229 })
+        );
     }
 
     #[test]
@@ -670,4 +1145,540 @@ mod tests {
             .verify_contract_proof(contract_data, global_root, contract_address)
             .is_err());
     }
+
+    fn mainnet_contract_proof() -> (GetProofResult, Felt, Address) {
+        let edge_node_string = r#"[{
+            "edge": {
+                "child": "0x538a7653ef22e217f93066ac54784c0159a5e1e37d808f83c82d1b42d57457d",
+                "path": {
+                    "len": 229,
+                    "value": "0x4a03bb9e744479e3298f54705a35966ab04140d3d8dd797c1f6dc49d0"
+                }
+            }
+        }]"#;
+        let proof = GetProofResult {
+            contract_proof: serde_json::from_str(edge_node_string).unwrap(),
+            state_commitment: Some(
+                Felt::try_new("0x1e2a7a7ee40c1d897c8c0a9515720ea02c8075ee9e00db277f5f8c3e4edcb54")
+                    .unwrap(),
+            ),
+            contract_data: Some(ContractData {
+                class_hash: Felt::try_new(
+                    "0x4e635d495504b31ec191cbfc3d99b5d109bfcae4d0d9e16f4909a43b2e24c07",
+                )
+                .unwrap(),
+                root: Felt::try_new(
+                    "0x5826149cbab3f8538d346301869ba2742a159d1542463ce19a60a927b826a2f",
+                )
+                .unwrap(),
+                nonce: Felt::try_new("0x0").unwrap(),
+                contract_state_hash_version: Felt::try_new("0x0").unwrap(),
+                storage_proofs: Some(vec![]),
+            }),
+            class_commitment: Some(Felt::try_new("0x0").unwrap()),
+        };
+        let global_root = Felt::try_new(
+            "0x1e2a7a7ee40c1d897c8c0a9515720ea02c8075ee9e00db277f5f8c3e4edcb54",
+        )
+        .unwrap();
+        let contract_address = Address(
+            Felt::try_new("0x6a05844a03bb9e744479e3298f54705a35966ab04140d3d8dd797c1f6dc49d0")
+                .unwrap(),
+        );
+        (proof, global_root, contract_address)
+    }
+
+    #[test]
+    fn valid_verify_nonce() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let nonce = Felt::try_new("0x0").unwrap();
+        assert!(proof
+            .verify_nonce(global_root, contract_address, nonce)
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_verify_nonce_value_mismatch() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let nonce = Felt::try_new("0x1").unwrap();
+        let error = proof
+            .verify_nonce(global_root, contract_address, nonce)
+            .unwrap_err();
+        assert!(matches!(error, ProofError::ValueMismatch { .. }));
+        assert!(error.is_invalid());
+    }
+
+    #[test]
+    fn invalid_verify_nonce_root_mismatch() {
+        let (proof, _, contract_address) = two_key_storage_proof();
+        let global_root = Felt::try_new("0x42").unwrap();
+        let nonce = Felt::try_new("0x0").unwrap();
+        assert!(matches!(
+            proof.verify_nonce(global_root, contract_address, nonce),
+            Err(ProofError::RootMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_contract_data_is_not_invalid_proof() {
+        let (mut proof, global_root, contract_address) =
+            mainnet_contract_proof();
+        proof.contract_data = None;
+        let nonce = Felt::try_new("0x0").unwrap();
+        let error = proof
+            .verify_nonce(global_root, contract_address, nonce)
+            .unwrap_err();
+        assert_eq!(error, ProofError::MissingContractData);
+        assert!(!error.is_invalid());
+
+        let error: iamgroot::jsonrpc::Error = error.into();
+        assert_eq!(error.code, -32701);
+    }
+
+    #[test]
+    fn valid_verify_class_hash() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let class_hash = Felt::try_new("0x0123").unwrap();
+        assert!(proof
+            .verify_class_hash(global_root, contract_address, class_hash)
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_verify_class_hash_value_mismatch() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let class_hash = Felt::try_new("0xbad").unwrap();
+        assert!(proof
+            .verify_class_hash(global_root, contract_address, class_hash)
+            .is_err());
+    }
+
+    #[test]
+    fn valid_verify_class_commitment() {
+        let (proof, global_root, _) = mainnet_contract_proof();
+        let class_commitment =
+            proof.verify_class_commitment(global_root).unwrap();
+        assert_eq!(class_commitment.as_ref(), "0x0");
+    }
+
+    #[test]
+    fn invalid_verify_class_commitment_root_mismatch() {
+        let (proof, _, _) = mainnet_contract_proof();
+        let global_root = Felt::try_new("0x42").unwrap();
+        assert!(proof.verify_class_commitment(global_root).is_err());
+    }
+
+    fn class_proof() -> (GetClassProofResult, Felt, Felt) {
+        let edge_node_string = r#"[{
+            "edge": {
+                "child": "0x7b80c4d5d2b3bbf805caad0d76e9f0c1cc40ba6b5a2458894a532f2533b8a41",
+                "path": {
+                    "len": 251,
+                    "value": "0xabc"
+                }
+            }
+        }]"#;
+        let proof = GetClassProofResult {
+            class_commitment: None,
+            class_proof: serde_json::from_str(edge_node_string).unwrap(),
+        };
+        let class_commitment = Felt::try_new(
+            "0x4a97062d30e38519de81e24c084fca7272f1de4fcc86c33dd4be26dab15c611",
+        )
+        .unwrap();
+        let class_hash = Felt::try_new("0xabc").unwrap();
+        (proof, class_commitment, class_hash)
+    }
+
+    #[test]
+    fn valid_verify_class_proof() {
+        let (proof, class_commitment, class_hash) = class_proof();
+        let compiled_class_hash = Felt::try_new("0x1234").unwrap();
+        assert!(proof
+            .verify(&class_commitment, class_hash, compiled_class_hash)
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_verify_class_proof_compiled_class_hash_mismatch() {
+        let (proof, class_commitment, class_hash) = class_proof();
+        let compiled_class_hash = Felt::try_new("0x1235").unwrap();
+        assert!(proof
+            .verify(&class_commitment, class_hash, compiled_class_hash)
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_verify_class_proof_commitment_mismatch() {
+        let (mut proof, class_commitment, class_hash) = class_proof();
+        proof.class_commitment = Some(Felt::try_new("0x42").unwrap());
+        let compiled_class_hash = Felt::try_new("0x1234").unwrap();
+        assert!(proof
+            .verify(&class_commitment, class_hash, compiled_class_hash)
+            .is_err());
+    }
+
+    #[test]
+    fn valid_verify_declared() {
+        let (proof, class_commitment, class_hash) = class_proof();
+        let leaf = proof.verify_declared(&class_commitment, class_hash);
+        let expected = GetClassProofResult::calculate_class_leaf(
+            Felt::try_new("0x1234").unwrap(),
+        );
+        assert_eq!(leaf.unwrap().as_ref(), expected.unwrap().as_ref());
+    }
+
+    #[test]
+    fn invalid_verify_declared_other_class() {
+        // the proof shows that 0xabd is not in the tree
+        let (proof, class_commitment, _) = class_proof();
+        let class_hash = Felt::try_new("0xabd").unwrap();
+        assert!(matches!(
+            proof.verify_declared(&class_commitment, class_hash),
+            Err(ProofError::UndeclaredClass(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_verify_declared_commitment_mismatch() {
+        let (proof, _, class_hash) = class_proof();
+        let class_commitment = Felt::try_new("0x42").unwrap();
+        assert!(matches!(
+            proof.verify_declared(&class_commitment, class_hash),
+            Err(ProofError::RootMismatch { .. })
+        ));
+    }
+
+    fn two_key_storage_proof() -> (GetProofResult, Felt, Address) {
+        let storage_proofs = r#"[
+            [
+                {
+                    "binary": {
+                        "left": "0x5928741ef23cf1718621841b99e4386cc4d007c4afd70078abb61bb357f4415",
+                        "right": "0x34d1b77c3bbad0cafb5b28510ed8020c4ba6dca2c6e479976c156dd3f46c793"
+                    }
+                },
+                {
+                    "edge": {
+                        "child": "0xaa",
+                        "path": {
+                            "len": 250,
+                            "value": "0x1"
+                        }
+                    }
+                }
+            ],
+            [
+                {
+                    "binary": {
+                        "left": "0x5928741ef23cf1718621841b99e4386cc4d007c4afd70078abb61bb357f4415",
+                        "right": "0x34d1b77c3bbad0cafb5b28510ed8020c4ba6dca2c6e479976c156dd3f46c793"
+                    }
+                },
+                {
+                    "edge": {
+                        "child": "0xbb",
+                        "path": {
+                            "len": 250,
+                            "value": "0x0"
+                        }
+                    }
+                }
+            ]
+        ]"#;
+        let contract_proof = r#"[{
+            "edge": {
+                "child": "0x1380e27bc6444a46b83088b8864d46a9f5e60b6e9a4ee46823f72e9da7cf3f3",
+                "path": {
+                    "len": 251,
+                    "value": "0x456"
+                }
+            }
+        }]"#;
+        let global_root = Felt::try_new(
+            "0x7669a93a47f9afbeb1519a0e125fe6dc5db080015f116d3cd993a0f4348e313",
+        )
+        .unwrap();
+        let proof = GetProofResult {
+            contract_proof: serde_json::from_str(contract_proof).unwrap(),
+            state_commitment: Some(global_root.clone()),
+            contract_data: Some(ContractData {
+                class_hash: Felt::try_new("0x123").unwrap(),
+                root: Felt::try_new(
+                    "0x4c9144b078df3806622cf3b52be7820662da1dcbcb498506297756cc8aed04d",
+                )
+                .unwrap(),
+                nonce: Felt::try_new("0x0").unwrap(),
+                contract_state_hash_version: Felt::try_new("0x0").unwrap(),
+                storage_proofs: Some(serde_json::from_str(storage_proofs).unwrap()),
+            }),
+            class_commitment: Some(Felt::try_new("0x0").unwrap()),
+        };
+        let contract_address = Address(Felt::try_new("0x456").unwrap());
+        (proof, global_root, contract_address)
+    }
+
+    fn two_key_entries(value_b: &str) -> Vec<(StorageKey, Felt)> {
+        vec![
+            (
+                StorageKey::try_new("0x01").unwrap(),
+                Felt::try_new("0xaa").unwrap(),
+            ),
+            (
+                StorageKey::try_new(
+                    "0x0400000000000000000000000000000000000000000000000000000000000000",
+                )
+                .unwrap(),
+                Felt::try_new(value_b).unwrap(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn valid_verify_batch() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let entries = two_key_entries("0xbb");
+        assert!(proof
+            .verify_batch(global_root, contract_address, &entries)
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_verify_batch_value_mismatch() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let entries = two_key_entries("0xbc");
+        assert!(proof
+            .verify_batch(global_root, contract_address, &entries)
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_verify_batch_proof_count_mismatch() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let entries = &two_key_entries("0xbb")[..1];
+        assert!(proof
+            .verify_batch(global_root, contract_address, entries)
+            .is_err());
+    }
+
+    #[test]
+    fn valid_non_membership_parse_proof_diverging_leaf_edge() {
+        // the edge leads to key 0x01, so key 0x03 is not in the tree
+        let key = "0x03".to_string();
+        let value = Felt::try_new("0x0").unwrap();
+        let (proof, _, _) = two_key_storage_proof();
+        let contract_data = proof.contract_data.unwrap();
+        let storage_proof = &contract_data.storage_proofs.unwrap()[0];
+
+        let ret_val =
+            GetProofResult::parse_proof(key, value, storage_proof).unwrap();
+        assert_eq!(ret_val.as_ref(), contract_data.root.as_ref());
+    }
+
+    #[test]
+    fn valid_non_membership_parse_proof_diverging_root_edge() {
+        // the only leaf of the tree is at key 0x456
+        let key = "0x457".to_string();
+        let value = Felt::try_new("0x0").unwrap();
+        let (proof, _, _) = two_key_storage_proof();
+
+        let ret_val =
+            GetProofResult::parse_proof(key, value, &proof.contract_proof)
+                .unwrap();
+        assert_eq!(
+            ret_val.as_ref(),
+            "0x49f37b982765fa2ebf4f74941c7203a3b2fc405cf812416c5b125fead655087"
+        );
+    }
+
+    #[test]
+    fn invalid_non_membership_parse_proof_non_zero_value() {
+        let key = "0x03".to_string();
+        let value = Felt::try_new("0xaa").unwrap();
+        let (proof, _, _) = two_key_storage_proof();
+        let storage_proofs =
+            proof.contract_data.unwrap().storage_proofs.unwrap();
+
+        assert!(GetProofResult::parse_proof(key, value, &storage_proofs[0])
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_non_membership_parse_proof_present_key() {
+        let key = "0x01".to_string();
+        let value = Felt::try_new("0x0").unwrap();
+        let (proof, _, _) = two_key_storage_proof();
+        let storage_proofs =
+            proof.contract_data.unwrap().storage_proofs.unwrap();
+
+        assert!(GetProofResult::parse_proof(key, value, &storage_proofs[0])
+            .is_err());
+    }
+
+    #[test]
+    fn valid_parse_proof_empty_tree() {
+        let key = "0x01".to_string();
+        let value = Felt::try_new("0x0").unwrap();
+        let ret_val = GetProofResult::parse_proof(key, value, &[]).unwrap();
+        assert_eq!(ret_val.as_ref(), "0x0");
+    }
+
+    #[test]
+    fn invalid_parse_proof_empty_tree_non_zero_value() {
+        let key = "0x01".to_string();
+        let value = Felt::try_new("0x1").unwrap();
+        assert!(GetProofResult::parse_proof(key, value, &[]).is_err());
+    }
+
+    #[test]
+    fn valid_verify_unset_storage_slot() {
+        let (mut proof, global_root, contract_address) =
+            two_key_storage_proof();
+        let contract_data = proof.contract_data.as_mut().unwrap();
+        let storage_proofs = contract_data.storage_proofs.as_mut().unwrap();
+        storage_proofs.truncate(1);
+
+        let key = StorageKey::try_new("0x03").unwrap();
+        let value = Felt::try_new("0x0").unwrap();
+        assert!(proof
+            .verify(global_root, contract_address, key, value)
+            .is_ok());
+    }
+
+    #[test]
+    fn valid_verify_contract_state() {
+        let (proof, global_root, contract_address) = two_key_storage_proof();
+        let (class_hash, nonce) =
+            proof.verify_contract_state(global_root, contract_address).unwrap();
+        assert_eq!(class_hash.as_ref(), "0x123");
+        assert_eq!(nonce.as_ref(), "0x0");
+    }
+
+    #[test]
+    fn valid_verify_contract_state_not_deployed() {
+        let (mut proof, global_root, _) = two_key_storage_proof();
+        proof.contract_data = None;
+        let contract_address = Address(Felt::try_new("0x457").unwrap());
+        let (class_hash, nonce) =
+            proof.verify_contract_state(global_root, contract_address).unwrap();
+        assert_eq!(class_hash.as_ref(), "0x0");
+        assert_eq!(nonce.as_ref(), "0x0");
+    }
+
+    #[test]
+    fn invalid_verify_contract_state_deployed_contract_missing() {
+        let (mut proof, global_root, contract_address) =
+            two_key_storage_proof();
+        proof.contract_data = None;
+        assert!(proof
+            .verify_contract_state(global_root, contract_address)
+            .is_err());
+    }
+
+    #[test]
+    fn trace_proof_from_root_to_leaf() {
+        let (proof, _, _) = two_key_storage_proof();
+        let contract_data = proof.contract_data.unwrap();
+        let storage_proof = &contract_data.storage_proofs.unwrap()[0];
+        let trace = trace_proof("0x1", storage_proof).unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].depth, 0);
+        assert_eq!(trace[0].direction, Some(Direction::Left));
+        assert_eq!(trace[0].hash.as_ref(), contract_data.root.as_ref());
+        assert_eq!(trace[1].depth, 1);
+        assert_eq!(trace[1].direction, None);
+        assert_eq!(
+            trace[1].hash.as_ref(),
+            "0x5928741ef23cf1718621841b99e4386cc4d007c4afd70078abb61bb357f4415"
+        );
+    }
+
+    /// A tree holding 0x11 at key 0x2 and 0x22 at key 0x3. Keys and values
+    /// share an edge from the root down to the binary node at depth 250.
+    /// Returns the root, the binary node hash and the binary node.
+    fn two_leaf_tree() -> (Felt, Felt, Node) {
+        let binary = pedersen(
+            &FieldElement::from(0x11u64),
+            &FieldElement::from(0x22u64),
+        );
+        let root =
+            pedersen(&binary, &FieldElement::ONE) + FieldElement::from(250u64);
+        let node = serde_json::from_value(serde_json::json!({
+            "binary": { "left": "0x11", "right": "0x22" }
+        }))
+        .unwrap();
+        (
+            Felt::try_new(&format!("0x{:x}", root)).unwrap(),
+            Felt::try_new(&format!("0x{:x}", binary)).unwrap(),
+            node,
+        )
+    }
+
+    fn edge(child: &Felt, len: i64, value: &str) -> Node {
+        serde_json::from_value(serde_json::json!({
+            "edge": {
+                "child": child.as_ref(),
+                "path": { "len": len, "value": value }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn valid_parse_proof_two_leaf_tree() {
+        let (root, binary, node) = two_leaf_tree();
+        let proof = vec![edge(&binary, 250, "0x1"), node];
+        let value = Felt::try_new("0x11").unwrap();
+        let ret_val = GetProofResult::parse_proof("0x2", value, &proof);
+        assert_eq!(ret_val.unwrap().as_ref(), root.as_ref());
+    }
+
+    #[test]
+    fn invalid_parse_proof_forged_subtree_under_edge() {
+        // the real root edge above a fabricated leaf edge for key 0x2
+        let (_, binary, _) = two_leaf_tree();
+        let value = Felt::try_new("0x99").unwrap();
+        let proof = vec![edge(&binary, 250, "0x1"), edge(&value, 1, "0x0")];
+        assert!(matches!(
+            GetProofResult::parse_proof("0x2", value, &proof),
+            Err(ProofError::HashMismatch { depth: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_parse_proof_edge_path_off_key() {
+        // the real nodes, but for a key the root edge does not lead to
+        let (_, binary, node) = two_leaf_tree();
+        let proof = vec![edge(&binary, 250, "0x1"), node];
+        let value = Felt::try_new("0x11").unwrap();
+        assert!(matches!(
+            GetProofResult::parse_proof("0x402", value, &proof),
+            Err(ProofError::EdgePathMismatch { depth: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_parse_proof_short_of_leaf() {
+        // the root edge alone, with the binary node hash as the value
+        let (_, binary, _) = two_leaf_tree();
+        let proof = vec![edge(&binary, 250, "0x1")];
+        assert!(matches!(
+            GetProofResult::parse_proof("0x1", binary, &proof),
+            Err(ProofError::PathLengthMismatch { expected: 251, actual: 250 })
+        ));
+    }
+
+    #[test]
+    fn invalid_non_membership_parse_proof_forged_subtree_under_edge() {
+        // a diverging leaf edge below the real root edge, proving key 0x2
+        // unset although it holds 0x11
+        let (_, binary, _) = two_leaf_tree();
+        let fake = Felt::try_new("0x33").unwrap();
+        let proof = vec![edge(&binary, 250, "0x1"), edge(&fake, 1, "0x1")];
+        let value = Felt::try_new("0x0").unwrap();
+        assert!(matches!(
+            GetProofResult::parse_proof("0x2", value, &proof),
+            Err(ProofError::HashMismatch { depth: 0, .. })
+        ));
+    }
 }
@@ -1,8 +1,12 @@
 use axum::{
     extract::State, response::IntoResponse, routing::post, Json, Router,
 };
+use ethers::types::{Address as L1Address, Bytes, H256, U256, U64};
 use iamgroot::jsonrpc;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use starknet_crypto::FieldElement;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::{
     net::{TcpListener, ToSocketAddrs},
@@ -11,8 +15,16 @@ use tokio::{
 };
 
 use crate::client::State as ClientState;
+use crate::eth::{
+    message_to_l1_hash, BlockTag as L1BlockTag, CallOpts, EthereumClient,
+    MessageToL2Status as L1MessageStatus,
+};
 
+use crate::block::{BlockData, MessageToL1, VerifyError};
 use crate::exe::err::Error;
+use crate::exe::{Chain as ExecutionChain, DataGas, DATA_GAS_VERSION};
+use crate::feeder::FeederGateway;
+use crate::proof::{as_field_element, ProofError};
 
 use super::gen::*;
 use gen::GetBlockWithTxHashesResult;
@@ -37,13 +49,40 @@ impl Server {
     }
 }
 
+/// Settings of the RPC server besides the provider and the trusted state.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Chain id of the network, which transaction hashes commit to.
+    pub chain_id: Felt,
+    /// Addresses of the ETH and STRK fee tokens, which executed transactions
+    /// are charged in.
+    pub eth_fee_token_address: Felt,
+    pub strk_fee_token_address: Felt,
+    /// Verify `starknet_getEvents` results against the event commitments of
+    /// verified blocks.
+    pub verify_events: bool,
+    /// Serve the `beerus_eth*` methods, which pass L1 reads on to Helios.
+    pub eth_methods: bool,
+    /// URL of the feeder gateway, which serves the parts of blocks since
+    /// 0.13.2 that their hash commits to but RPC does not serve. Without it
+    /// such blocks cannot be verified.
+    pub feeder_gateway: Option<String>,
+}
+
+/// Serve the RPC API on top of the L1-verified `state`. The `settled` states
+/// (keyed by block number) are the earlier ones seen settled on L1, which
+/// blocks older than `state` are verified against when they are closer. The
+/// `ethereum` client, if any, serves the methods that read L1 directly.
 pub async fn serve<A: ToSocketAddrs>(
     url: &str,
     addr: A,
     state: Arc<RwLock<ClientState>>,
+    settled: Arc<RwLock<BTreeMap<u64, ClientState>>>,
+    ethereum: Option<Arc<EthereumClient>>,
+    options: Options,
 ) -> Result<Server, Error> {
     let listener = TcpListener::bind(addr).await?;
-    let server = serve_on(url, listener, state)?;
+    let server = serve_on(url, listener, state, settled, ethereum, options)?;
     Ok(server)
 }
 
@@ -51,6 +90,9 @@ fn serve_on(
     url: &str,
     listener: TcpListener,
     state: Arc<RwLock<ClientState>>,
+    settled: Arc<RwLock<BTreeMap<u64, ClientState>>>,
+    ethereum: Option<Arc<EthereumClient>>,
+    options: Options,
 ) -> Result<Server, Error> {
     const DEFAULT_TIMEOUT: std::time::Duration =
         std::time::Duration::from_secs(30);
@@ -59,10 +101,25 @@ fn serve_on(
         .timeout(DEFAULT_TIMEOUT)
         .build()?;
 
+    let gateway = options
+        .feeder_gateway
+        .as_deref()
+        .map(|url| FeederGateway::new(url, client.clone()));
     let ctx = Context {
         url: url.to_owned(),
         client: Arc::new(gen::client::Client::with_client(url, client)),
         state,
+        settled,
+        ethereum,
+        gateway,
+        blocks: Default::default(),
+        chain: ExecutionChain {
+            chain_id: options.chain_id,
+            eth_fee_token_address: options.eth_fee_token_address,
+            strk_fee_token_address: options.strk_fee_token_address,
+        },
+        verify_events: options.verify_events,
+        eth_methods: options.eth_methods,
     };
 
     let app = Router::new().route("/rpc", post(handle_request)).with_state(ctx);
@@ -108,19 +165,68 @@ impl IntoResponse for RpcError {
     }
 }
 
+/// Maximum number of blocks walked back from the nearest verified block.
+const MAX_BLOCK_WALK: u64 = 1024;
+
+/// Maximum number of verified blocks kept in memory.
+const MAX_VERIFIED_BLOCKS: usize = 16 * 1024;
+
+const EVENTS_CHUNK_SIZE: i64 = 1024;
+
+/// Maximum number of blocks covered by a single verified events chunk.
+const MAX_EVENTS_BLOCK_RANGE: u64 = 256;
+
+/// Block whose hash was recomputed and linked to the L1-verified block
+/// through the chain of parent hashes.
+#[derive(Clone, Debug)]
+struct VerifiedBlock {
+    block_hash: Felt,
+    parent_hash: Felt,
+    root: Felt,
+}
+
+impl VerifiedBlock {
+    fn state(&self, block_number: u64) -> ClientState {
+        ClientState {
+            block_number,
+            block_hash: self.block_hash.clone(),
+            root: self.root.clone(),
+        }
+    }
+}
+
+/// Block whose hash was recomputed from its contents, which are therefore
+/// the committed ones.
+struct VerifiedContents {
+    block: BlockWithTxs,
+    /// All events emitted in the block, in order.
+    events: Vec<EmittedEvent>,
+    /// Only for blocks since 0.13.2, whose hash commits to it.
+    data: Option<BlockData>,
+}
+
 #[derive(Clone)]
 struct Context {
     url: String,
     client: Arc<gen::client::Client>,
     state: Arc<RwLock<ClientState>>,
+    settled: Arc<RwLock<BTreeMap<u64, ClientState>>>,
+    ethereum: Option<Arc<EthereumClient>>,
+    gateway: Option<FeederGateway>,
+    blocks: Arc<RwLock<BTreeMap<u64, VerifiedBlock>>>,
+    chain: ExecutionChain,
+    verify_events: bool,
+    eth_methods: bool,
 }
 
 impl Context {
+    /// Read the state from the header of the block as served by the
+    /// provider, i.e. without any verification.
     async fn get_state(
         &self,
         block_id: BlockId,
     ) -> std::result::Result<ClientState, jsonrpc::Error> {
-        let block = self.getBlockWithTxHashes(block_id).await?;
+        let block = self.client.getBlockWithTxHashes(block_id).await?;
         let gen::GetBlockWithTxHashesResult::BlockWithTxHashes(block) = block
         else {
             return Err(jsonrpc::Error {
@@ -135,24 +241,17 @@ impl Context {
         })
     }
 
-    async fn get_latest_state(
-        &self,
-    ) -> std::result::Result<ClientState, jsonrpc::Error> {
-        let block_id = gen::BlockId::BlockTag(gen::BlockTag::Latest);
-        self.get_state(block_id).await
-    }
-
     async fn resolve_block_id(
         &self,
         block_id: BlockId,
     ) -> std::result::Result<(BlockId, Felt), jsonrpc::Error> {
-        let state = &self.state.read().await;
+        let state = self.state.read().await.clone();
         match block_id {
             gen::BlockId::BlockNumber { block_number } => {
-                self.resolve_block_by_number(block_number, state).await
+                self.resolve_block_by_number(block_number, &state).await
             }
             gen::BlockId::BlockHash { block_hash } => {
-                self.resolve_block_by_hash(block_hash, state).await
+                self.resolve_block_by_hash(block_hash, &state).await
             }
             gen::BlockId::BlockTag(BlockTag::Latest) => {
                 let block_number =
@@ -182,19 +281,8 @@ impl Context {
                 current_state.root.clone(),
             ));
         }
-        let state = self
-            .get_state(BlockId::BlockNumber {
-                block_number: block_number.clone(),
-            })
-            .await?;
-        if state.block_number != req_block_number {
-            return Err(jsonrpc::Error {
-                code: -1,
-                message: "Failed to verify requested block by number"
-                    .to_string(),
-            });
-        }
-        Ok((BlockId::BlockNumber { block_number }, state.root))
+        let block = self.verify_block(req_block_number, current_state).await?;
+        Ok((BlockId::BlockNumber { block_number }, block.root))
     }
 
     async fn resolve_block_by_hash(
@@ -211,15 +299,695 @@ impl Context {
         let state = self
             .get_state(BlockId::BlockHash { block_hash: block_hash.clone() })
             .await?;
-        if block_hash.0.as_ref() != state.block_hash.as_ref()
-            || state.block_number >= current_state.block_number
-        {
+        if state.block_number >= current_state.block_number {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: "Failed to verify requested block by hash".to_string(),
+            });
+        }
+        let block =
+            self.verify_block(state.block_number, current_state).await?;
+        if !same_hash(&block_hash.0, &block.block_hash) {
             return Err(jsonrpc::Error {
                 code: -1,
                 message: "Failed to verify requested block by hash".to_string(),
             });
         }
-        Ok((BlockId::BlockHash { block_hash }, state.root))
+        Ok((BlockId::BlockHash { block_hash }, block.root))
+    }
+
+    /// Return the block with the given number, verified by walking the chain
+    /// of parent hashes down from the nearest already verified block (or the
+    /// nearest block settled on L1) and recomputing the hash of every block.
+    async fn verify_block(
+        &self,
+        block_number: u64,
+        current_state: &ClientState,
+    ) -> Result<ClientState, jsonrpc::Error> {
+        let (mut number, mut expected_hash) = {
+            let settled = self.settled.read().await;
+            // The hash and root of a settled block are the ones on L1.
+            if let Some(state) = settled.get(&block_number) {
+                return Ok(state.clone());
+            }
+            let blocks = self.blocks.read().await;
+            if let Some(block) = blocks.get(&block_number) {
+                return Ok(block.state(block_number));
+            }
+            // The next block to fetch and the hash it must have: the parent
+            // of the nearest verified block or the nearest settled block.
+            let verified = blocks
+                .range(block_number + 1..)
+                .next()
+                .map(|(number, block)| (number - 1, &block.parent_hash));
+            let settled = settled
+                .range(block_number..)
+                .next()
+                .map(|(number, state)| (*number, &state.block_hash));
+            let (number, hash) = [verified, settled]
+                .into_iter()
+                .flatten()
+                .min_by_key(|(number, _)| *number)
+                .unwrap_or((
+                    current_state.block_number,
+                    &current_state.block_hash,
+                ));
+            (number, hash.clone())
+        };
+        if number < block_number || number - block_number >= MAX_BLOCK_WALK {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: format!(
+                    "Block {block_number} is too far behind the latest verified block"
+                ),
+            });
+        }
+
+        loop {
+            let contents =
+                self.get_verified_block(number, &expected_hash).await?;
+            let header = contents.block.block_header;
+            let block = VerifiedBlock {
+                block_hash: expected_hash,
+                parent_hash: header.parent_hash.0,
+                root: header.new_root,
+            };
+            tracing::debug!(number, ?block, "block verified");
+
+            let mut blocks = self.blocks.write().await;
+            blocks.insert(number, block.clone());
+            while blocks.len() > MAX_VERIFIED_BLOCKS {
+                blocks.pop_first();
+            }
+
+            if number == block_number {
+                return Ok(block.state(block_number));
+            }
+            expected_hash = block.parent_hash;
+            number -= 1;
+        }
+    }
+
+    /// Fetch the block with the given number together with its events (and
+    /// since 0.13.2 its [`BlockData`]) and check that it hashes to
+    /// `expected_hash`.
+    async fn get_verified_block(
+        &self,
+        block_number: u64,
+        expected_hash: &Felt,
+    ) -> Result<VerifiedContents, jsonrpc::Error> {
+        let block_id = BlockId::BlockNumber {
+            block_number: BlockNumber::try_new(block_number as i64)?,
+        };
+        let block = self.client.getBlockWithTxs(block_id.clone()).await?;
+        let GetBlockWithTxsResult::BlockWithTxs(block) = block else {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: "Pending block received".to_owned(),
+            });
+        };
+        let events = self.get_block_events(block_id.clone()).await?;
+        let data = if crate::block::requires_block_data(&block) {
+            Some(self.get_block_data(block_id, block_number).await?)
+        } else {
+            None
+        };
+        crate::block::verify_block_hash(
+            &block,
+            &events,
+            data.as_ref(),
+            expected_hash,
+        )?;
+        if !same_hash(&block.block_header.block_hash.0, expected_hash) {
+            return Err(ProofError::ValueMismatch {
+                name: "block hash",
+                provided: block.block_header.block_hash.0.as_ref().clone(),
+                proven: expected_hash.as_ref().clone(),
+            }
+            .into());
+        }
+        Ok(VerifiedContents { block, events, data })
+    }
+
+    /// Fetch the state diff from the provider, and the rest of the block
+    /// data from the feeder gateway. Neither is trusted: both are checked by
+    /// recomputing the block hash.
+    async fn get_block_data(
+        &self,
+        block_id: BlockId,
+        block_number: u64,
+    ) -> Result<BlockData, jsonrpc::Error> {
+        let gateway =
+            self.gateway.as_ref().ok_or(VerifyError::MissingBlockData {
+                block_number: block_number as i64,
+                name: "a feeder gateway",
+            })?;
+        let state_update = self.client.getStateUpdate(block_id).await?;
+        let GetStateUpdateResult::StateUpdate(state_update) = state_update
+        else {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: "Pending state update received".to_owned(),
+            });
+        };
+        let block = gateway.get_block(block_number).await?;
+        Ok(block.with_state_diff(state_update.state_diff))
+    }
+
+    async fn get_block_events(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<EmittedEvent>, jsonrpc::Error> {
+        let mut events = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let filter = GetEventsFilter {
+                event_filter: EventFilter {
+                    address: None,
+                    from_block: Some(block_id.clone()),
+                    keys: None,
+                    to_block: Some(block_id.clone()),
+                },
+                result_page_request: ResultPageRequest {
+                    chunk_size: ResultPageRequestChunkSize::try_new(
+                        EVENTS_CHUNK_SIZE,
+                    )?,
+                    continuation_token,
+                },
+            };
+            let chunk = self.client.getEvents(filter).await?;
+            events.extend(chunk.events);
+            match chunk.continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok(events),
+            }
+        }
+    }
+
+    /// Resolve the block id and return the block, verified against the
+    /// hash of its child (or against L1 for the latest block).
+    async fn get_block(
+        &self,
+        block_id: BlockId,
+    ) -> Result<BlockWithTxs, jsonrpc::Error> {
+        Ok(self.get_block_contents(block_id).await?.block)
+    }
+
+    /// Like [`Self::get_block`], together with the rest of the verified
+    /// contents of the block.
+    async fn get_block_contents(
+        &self,
+        block_id: BlockId,
+    ) -> Result<VerifiedContents, jsonrpc::Error> {
+        let (block_id, _) = self.resolve_block_id(block_id).await?;
+        let state = self.state.read().await.clone();
+        let block_number = match &block_id {
+            BlockId::BlockNumber { block_number } => {
+                *block_number.as_ref() as u64
+            }
+            _ => self.get_state(block_id.clone()).await?.block_number,
+        };
+        let expected_hash = if block_number == state.block_number {
+            state.block_hash.clone()
+        } else {
+            self.verify_block(block_number, &state).await?.block_hash
+        };
+        if let BlockId::BlockHash { block_hash } = &block_id {
+            if !same_hash(&block_hash.0, &expected_hash) {
+                return Err(jsonrpc::Error {
+                    code: -1,
+                    message: "Failed to verify requested block by hash"
+                        .to_string(),
+                });
+            }
+        }
+        self.get_verified_block(block_number, &expected_hash).await
+    }
+
+    /// Return the transaction from the verified block, after checking that
+    /// its body hashes to the transaction hash the block commits to.
+    async fn get_included_transaction(
+        &self,
+        block_id: BlockId,
+        transaction_hash: &TxnHash,
+    ) -> Result<(BlockHeader, BlockTransaction), jsonrpc::Error> {
+        let mut block = self.get_block(block_id).await?;
+        let index = transaction_index(&block, transaction_hash)?;
+        let transaction =
+            block.block_body_with_txs.transactions.swap_remove(index);
+        self.verify_transaction_hash(&block.block_header, &transaction)?;
+        Ok((block.block_header, transaction))
+    }
+
+    /// Return the events of the block as emitted by its transactions. Since
+    /// 0.13.2 each event is committed to together with the hash of its
+    /// transaction, before that they are taken from the receipts and checked
+    /// against the event commitment.
+    async fn get_verified_events(
+        &self,
+        block_number: u64,
+    ) -> Result<Vec<EmittedEvent>, jsonrpc::Error> {
+        let block_number = BlockNumber::try_new(block_number as i64)?;
+        let contents = self
+            .get_block_contents(BlockId::BlockNumber {
+                block_number: block_number.clone(),
+            })
+            .await?;
+        if contents.data.is_some() {
+            return Ok(contents.events);
+        }
+        let block = contents.block;
+        let block_hash = block.block_header.block_hash.clone();
+
+        let mut events = Vec::new();
+        for transaction in &block.block_body_with_txs.transactions {
+            let transaction_hash = &transaction.transaction_hash;
+            let receipt = self
+                .client
+                .getTransactionReceipt(transaction_hash.clone())
+                .await?;
+            let receipt = receipt_properties(&receipt)?;
+            if !same_hash(&receipt.transaction_hash.0, &transaction_hash.0) {
+                return Err(ProofError::ValueMismatch {
+                    name: "receipt transaction hash",
+                    provided: receipt.transaction_hash.0.as_ref().clone(),
+                    proven: transaction_hash.0.as_ref().clone(),
+                }
+                .into());
+            }
+            events.extend(receipt.events.iter().map(|event| EmittedEvent {
+                event: event.clone(),
+                block_hash: Some(block_hash.clone()),
+                block_number: Some(block_number.clone()),
+                transaction_hash: transaction_hash.clone(),
+            }));
+        }
+
+        crate::block::verify_block_hash(&block, &events, None, &block_hash.0)?;
+        Ok(events)
+    }
+
+    /// Serve the page of events matching the filter, checked against the
+    /// verified events of the blocks it covers. The continuation token is
+    /// the position of the next event among the verified events: a block
+    /// number and the index of the event among the matching events of that
+    /// block. The provider is asked for the events from the start of that
+    /// block, so the page must be a prefix of the verified events from there
+    /// on (and all of them up to `to_block` for the last page). An event
+    /// dropped at the end of a page is then expected at the start of the
+    /// next one. The provider is asked for at most `MAX_EVENTS_BLOCK_RANGE`
+    /// blocks at a time, the events of the next blocks being served from
+    /// the next page.
+    async fn get_verified_events_chunk(
+        &self,
+        filter: &GetEventsFilter,
+    ) -> Result<EventsChunk, jsonrpc::Error> {
+        let latest = self.state.read().await.block_number;
+        let (from_block, skip) =
+            match &filter.result_page_request.continuation_token {
+                None => {
+                    let block_id = filter.event_filter.from_block.clone();
+                    (self.get_block_number(block_id, 0).await?, 0)
+                }
+                Some(token) => parse_events_token(token)?,
+            };
+        let filter_to_block = {
+            let block_id = filter.event_filter.to_block.clone();
+            self.get_block_number(block_id, latest).await?
+        };
+        let range_end =
+            filter_to_block.min(from_block + MAX_EVENTS_BLOCK_RANGE - 1);
+        let chunk_size =
+            *filter.result_page_request.chunk_size.as_ref() + skip as i64;
+        let request = GetEventsFilter {
+            event_filter: EventFilter {
+                from_block: Some(BlockId::BlockNumber {
+                    block_number: BlockNumber::try_new(from_block as i64)?,
+                }),
+                to_block: Some(BlockId::BlockNumber {
+                    block_number: BlockNumber::try_new(range_end as i64)?,
+                }),
+                ..filter.event_filter.clone()
+            },
+            result_page_request: ResultPageRequest {
+                chunk_size: ResultPageRequestChunkSize::try_new(chunk_size)?,
+                continuation_token: None,
+            },
+        };
+        let mut chunk = self.client.getEvents(request).await?;
+        let is_last_page = chunk.continuation_token.is_none();
+
+        let event_blocks = chunk
+            .events
+            .iter()
+            .map(|event| {
+                event
+                    .block_number
+                    .as_ref()
+                    .map(|block_number| *block_number.as_ref() as u64)
+                    .ok_or_else(|| jsonrpc::Error {
+                        code: -1,
+                        message: "Pending events are not supported".to_owned(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(block_number) = event_blocks.iter().find(|n| **n > latest) {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: format!(
+                    "Events of block {block_number} cannot be verified before the block is verified on L1"
+                ),
+            });
+        }
+
+        if !is_last_page && chunk.events.len() <= skip {
+            // The continuation token would not move past the events served.
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: format!(
+                    "Events page from block {from_block} is not the last one but has no new events"
+                ),
+            });
+        }
+        if event_blocks.iter().any(|n| *n > range_end) {
+            return Err(VerifyError::EventsMismatch {
+                from_block: from_block as i64,
+                to_block: range_end as i64,
+            }
+            .into());
+        }
+
+        let to_block = match (is_last_page, event_blocks.last()) {
+            (false, Some(block_number)) => *block_number,
+            _ => range_end.min(latest),
+        };
+
+        let mut expected = Vec::new();
+        for block_number in from_block..=to_block {
+            let events = self.get_verified_events(block_number).await?;
+            expected.extend(events.into_iter().filter(|event| {
+                matches_event_filter(&filter.event_filter, &event.event)
+            }));
+        }
+        if !is_events_prefix(&expected, &chunk.events, is_last_page) {
+            return Err(VerifyError::EventsMismatch {
+                from_block: from_block as i64,
+                to_block: to_block as i64,
+            }
+            .into());
+        }
+
+        let continuation_token = match event_blocks.last() {
+            // The page starts at the first matching event of `from_block`.
+            Some(last) if !is_last_page => {
+                let index = event_blocks.iter().filter(|n| *n == last).count();
+                Some(format!("{to_block}-{index}"))
+            }
+            _ if range_end < filter_to_block.min(latest) => {
+                Some(format!("{}-0", range_end + 1))
+            }
+            _ => None,
+        };
+        let events = chunk.events.split_off(skip.min(chunk.events.len()));
+        Ok(EventsChunk { continuation_token, events })
+    }
+
+    /// Return the number of the block, verifying it when given by hash.
+    async fn get_block_number(
+        &self,
+        block_id: Option<BlockId>,
+        default: u64,
+    ) -> Result<u64, jsonrpc::Error> {
+        match block_id {
+            None => Ok(default),
+            Some(BlockId::BlockNumber { block_number }) => {
+                Ok(*block_number.as_ref() as u64)
+            }
+            Some(BlockId::BlockTag(BlockTag::Latest)) => {
+                Ok(self.state.read().await.block_number)
+            }
+            Some(BlockId::BlockTag(BlockTag::Pending)) => Err(jsonrpc::Error {
+                code: -1,
+                message: "Pending block is not supported".to_owned(),
+            }),
+            Some(block_id) => {
+                let block = self.get_block(block_id).await?;
+                Ok(*block.block_header.block_number.as_ref() as u64)
+            }
+        }
+    }
+
+    /// Verify the state diff with proofs of every contract it touches at the
+    /// block against the new root for the values the diff sets, and with a
+    /// proof at the parent block against the old root that a contract the
+    /// diff deploys does not exist yet. The old values of the storage, nonce
+    /// and class hash are not checked, as the diff may carry writes which
+    /// leave a value unchanged. Whether entries are missing from the diff is
+    /// checked by the caller against the block where it commits to the diff.
+    /// Classes declared by the block are only proven against the new root,
+    /// and deprecated (Cairo 0) classes are not committed to by the state.
+    async fn verify_state_diff(
+        &self,
+        state_diff: &StateDiff,
+        (block_id, new_root): (&BlockId, &Felt),
+        parent: Option<&(BlockId, Felt)>,
+    ) -> Result<(), jsonrpc::Error> {
+        for (contract_address, diff) in contract_diffs(state_diff)? {
+            let keys: Vec<StorageKey> =
+                diff.storage.iter().map(|(key, _)| key.clone()).collect();
+
+            let proof = self
+                .client
+                .getProof(block_id.clone(), contract_address.clone(), keys)
+                .await?;
+            if !diff.storage.is_empty() {
+                proof.verify_batch(
+                    new_root.clone(),
+                    contract_address.clone(),
+                    &diff.storage,
+                )?;
+            }
+            let (class_hash, nonce) = proof.verify_contract_state(
+                new_root.clone(),
+                contract_address.clone(),
+            )?;
+            if let Some(provided) = &diff.class_hash {
+                check_same("class hash", provided, &class_hash)?;
+            }
+            if let Some(provided) = &diff.nonce {
+                check_same("nonce", provided, &nonce)?;
+            }
+
+            let (true, Some((parent_id, old_root))) = (diff.deployed, parent)
+            else {
+                continue;
+            };
+            let proof = self
+                .client
+                .getProof(parent_id.clone(), contract_address.clone(), vec![])
+                .await?;
+            let (old_class_hash, _) = proof.verify_contract_state(
+                old_root.clone(),
+                contract_address.clone(),
+            )?;
+            if proof.contract_data.is_some() {
+                return Err(ProofError::ValueMismatch {
+                    name: "class hash before deployment",
+                    provided: "0x0".to_owned(),
+                    proven: old_class_hash.as_ref().clone(),
+                }
+                .into());
+            }
+        }
+
+        if state_diff.declared_classes.is_empty() {
+            return Ok(());
+        }
+        let class_commitment = GetProofResult::fetch_class_commitment(
+            self.client.as_ref(),
+            block_id.clone(),
+            new_root.clone(),
+        )
+        .await?;
+        for declared_class in &state_diff.declared_classes {
+            let (Some(class_hash), Some(compiled_class_hash)) = (
+                &declared_class.class_hash,
+                &declared_class.compiled_class_hash,
+            ) else {
+                return Err(incomplete_state_diff());
+            };
+            let class_proof = self
+                .client
+                .getClassProof(block_id.clone(), class_hash.clone())
+                .await?;
+            class_proof.verify(
+                &class_commitment,
+                class_hash.clone(),
+                compiled_class_hash.clone(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Re-execute the transactions of the block on top of the verified state
+    /// of its parent, and return their traces.
+    async fn trace_transactions(
+        &self,
+        block_header: BlockHeader,
+        data_gas: Option<DataGas>,
+        transactions: Vec<BlockTransaction>,
+    ) -> Result<Vec<TransactionTrace>, jsonrpc::Error> {
+        let block_number = *block_header.block_number.as_ref() as u64;
+        let parent_number = block_number.checked_sub(1).ok_or_else(|| {
+            jsonrpc::Error::new(
+                -1,
+                "Transactions of the genesis block cannot be traced".to_owned(),
+            )
+        })?;
+        let state = self.state.read().await.clone();
+        let parent_root = self.verify_block(parent_number, &state).await?.root;
+        let chain = self.chain.clone();
+
+        // The re-executed block is checked against its verified state diff.
+        let block_id = BlockId::BlockNumber {
+            block_number: block_header.block_number.clone(),
+        };
+        let GetStateUpdateResult::StateUpdate(state_update) =
+            self.getStateUpdate(block_id).await?
+        else {
+            return Err(jsonrpc::Error::new(
+                -1,
+                "Pending state update received".to_owned(),
+            ));
+        };
+
+        let client = gen::client::blocking::Client::new(&self.url);
+        let traces = tokio::task::spawn_blocking(move || {
+            crate::exe::trace_block(
+                &client,
+                &block_header,
+                parent_root,
+                &chain,
+                data_gas.as_ref(),
+                transactions,
+                &state_update.state_diff,
+            )
+        })
+        .await
+        .map_err(|e| {
+            iamgroot::jsonrpc::Error::new(500, format!("join error: {e}"))
+        })??;
+        Ok(traces)
+    }
+
+    /// The header of the block to call on. The root of the latest block is
+    /// the one verified on L1, so the block is not verified against its hash
+    /// (which since 0.13.2 takes its state diff and the feeder gateway): the
+    /// header is only checked to be the one of that block, and its root is
+    /// taken from the verified state.
+    async fn get_call_header(
+        &self,
+        block_id: BlockId,
+    ) -> Result<BlockHeader, jsonrpc::Error> {
+        let state = self.state.read().await.clone();
+        let latest = match &block_id {
+            BlockId::BlockTag(BlockTag::Latest) => true,
+            BlockId::BlockTag(BlockTag::Pending) => false,
+            BlockId::BlockNumber { block_number } => {
+                *block_number.as_ref() as u64 >= state.block_number
+            }
+            BlockId::BlockHash { block_hash } => {
+                same_hash(&block_hash.0, &state.block_hash)
+            }
+        };
+        if !latest {
+            return Ok(self.get_block(block_id).await?.block_header);
+        }
+
+        let block_id = BlockId::BlockNumber {
+            block_number: BlockNumber::try_new(state.block_number as i64)?,
+        };
+        let GetBlockWithTxHashesResult::BlockWithTxHashes(block) =
+            self.client.getBlockWithTxHashes(block_id).await?
+        else {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: "Pending block received".to_owned(),
+            });
+        };
+        check_same(
+            "block hash",
+            &block.block_header.block_hash.0,
+            &state.block_hash,
+        )?;
+        Ok(BlockHeader { new_root: state.root, ..block.block_header })
+    }
+
+    /// The header of the verified block to execute transactions on, and the
+    /// L1 data gas price and DA mode they are charged with.
+    async fn get_execution_block(
+        &self,
+        block_id: BlockId,
+    ) -> Result<(BlockHeader, Option<DataGas>), jsonrpc::Error> {
+        let contents = self.get_block_contents(block_id).await?;
+        let data_gas = self.get_data_gas(&contents).await?;
+        Ok((contents.block.block_header, data_gas))
+    }
+
+    /// The L1 data gas price and DA mode the block was executed with. Since
+    /// 0.13.2 they are part of the verified block data. The hash of a 0.13.1
+    /// block does not commit to them, so they are taken from the feeder
+    /// gateway as they are: a wrong price shows up as a fee transfer that
+    /// disagrees with the receipt when the trace is verified.
+    async fn get_data_gas(
+        &self,
+        contents: &VerifiedContents,
+    ) -> Result<Option<DataGas>, jsonrpc::Error> {
+        if let Some(data) = &contents.data {
+            return Ok(Some(DataGas {
+                price: data.l1_data_gas_price.clone(),
+                mode: data.l1_da_mode,
+            }));
+        }
+        let header = &contents.block.block_header;
+        let charges_data_gas = crate::block::parse_version(
+            &header.starknet_version,
+        )
+        .is_some_and(|version| version.as_slice() >= &DATA_GAS_VERSION[..]);
+        if !charges_data_gas {
+            return Ok(None);
+        }
+        let block_number = *header.block_number.as_ref();
+        let gateway =
+            self.gateway.as_ref().ok_or(VerifyError::MissingBlockData {
+                block_number,
+                name: "a feeder gateway",
+            })?;
+        let block = gateway.get_block(block_number as u64).await?;
+        Ok(Some(DataGas {
+            price: block.l1_data_gas_price,
+            mode: block.l1_da_mode,
+        }))
+    }
+
+    fn verify_transaction_hash(
+        &self,
+        header: &BlockHeader,
+        transaction: &BlockTransaction,
+    ) -> Result<(), jsonrpc::Error> {
+        // The first blocks carry no version and their transactions are
+        // hashed with legacy schemes.
+        if header.starknet_version.is_empty() {
+            return Err(VerifyError::UnsupportedTransactionVersion(
+                String::new(),
+            )
+            .into());
+        }
+        crate::transaction::verify_transaction_hash(
+            transaction,
+            &self.chain.chain_id,
+        )?;
+        Ok(())
     }
 }
 
@@ -229,7 +997,7 @@ async fn handle_request(
 ) -> Result<impl IntoResponse, RpcError> {
     match req {
         Request::Single(req) => {
-            let res = gen::handle(&ctx, &req).await;
+            let res = dispatch(&ctx, &req).await;
             if req.id.is_some() {
                 Ok(Json(Response::Single(res)))
             } else {
@@ -240,7 +1008,7 @@ async fn handle_request(
             let mut ret = Vec::with_capacity(reqs.len());
             for req in reqs {
                 let ctx = ctx.clone();
-                let res = gen::handle(&ctx, &req).await;
+                let res = dispatch(&ctx, &req).await;
                 if req.id.is_some() {
                     ret.push(res);
                 }
@@ -291,14 +1059,14 @@ impl gen::Rpc for Context {
     async fn call(
         &self,
         request: FunctionCall,
-        _block_id: BlockId,
+        block_id: BlockId,
     ) -> std::result::Result<Vec<Felt>, jsonrpc::Error> {
         let client = gen::client::blocking::Client::new(&self.url);
 
-        // TODO: address that effectively only the 'latest' block is supported
-        let state_root = self.get_latest_state().await?.root;
+        let block_header = self.get_call_header(block_id).await?;
+        let chain = self.chain.clone();
         let call_info = tokio::task::spawn_blocking(move || {
-            crate::exe::call(&client, request, state_root)
+            crate::exe::call(&client, request, &block_header, &chain)
         })
         .await
         .map_err(|e| {
@@ -317,7 +1085,7 @@ impl gen::Rpc for Context {
     }
 
     async fn chainId(&self) -> std::result::Result<ChainId, jsonrpc::Error> {
-        self.client.chainId().await
+        ChainId::try_new(self.chain.chain_id.as_ref())
     }
 
     async fn estimateFee(
@@ -326,7 +1094,30 @@ impl gen::Rpc for Context {
         simulation_flags: Vec<SimulationFlagForEstimateFee>,
         block_id: BlockId,
     ) -> std::result::Result<Vec<FeeEstimate>, jsonrpc::Error> {
-        self.client.estimateFee(request, simulation_flags, block_id).await
+        let (block_header, data_gas) =
+            self.get_execution_block(block_id).await?;
+        let chain = self.chain.clone();
+        let validate = !simulation_flags.iter().any(|flag| {
+            matches!(flag, SimulationFlagForEstimateFee::SkipValidate)
+        });
+
+        let client = gen::client::blocking::Client::new(&self.url);
+        let estimates = tokio::task::spawn_blocking(move || {
+            crate::exe::estimate_fee(
+                &client,
+                &block_header,
+                &chain,
+                data_gas.as_ref(),
+                request,
+                validate,
+            )
+        })
+        .await
+        .map_err(|e| {
+            iamgroot::jsonrpc::Error::new(500, format!("join error: {e}"))
+        })??;
+
+        Ok(estimates)
     }
 
     async fn estimateMessageFee(
@@ -349,14 +1140,26 @@ impl gen::Rpc for Context {
         &self,
         block_id: BlockId,
     ) -> std::result::Result<GetBlockWithTxHashesResult, jsonrpc::Error> {
-        self.client.getBlockWithTxHashes(block_id).await
+        let block = self.get_block(block_id).await?;
+        let transactions = block
+            .block_body_with_txs
+            .transactions
+            .into_iter()
+            .map(|transaction| transaction.transaction_hash)
+            .collect();
+        Ok(GetBlockWithTxHashesResult::BlockWithTxHashes(BlockWithTxHashes {
+            status: block.status,
+            block_header: block.block_header,
+            block_body_with_tx_hashes: BlockBodyWithTxHashes { transactions },
+        }))
     }
 
     async fn getBlockWithTxs(
         &self,
         block_id: BlockId,
     ) -> std::result::Result<GetBlockWithTxsResult, jsonrpc::Error> {
-        self.client.getBlockWithTxs(block_id).await
+        let block = self.get_block(block_id).await?;
+        Ok(GetBlockWithTxsResult::BlockWithTxs(block))
     }
 
     async fn getClass(
@@ -364,7 +1167,32 @@ impl gen::Rpc for Context {
         block_id: BlockId,
         class_hash: Felt,
     ) -> std::result::Result<GetClassResult, jsonrpc::Error> {
-        self.client.getClass(block_id, class_hash).await
+        let (block_id, state_root) = self.resolve_block_id(block_id).await?;
+
+        let result =
+            self.client.getClass(block_id.clone(), class_hash.clone()).await?;
+        tracing::info!(?class_hash, ?block_id, "getClass");
+
+        let computed_class_hash = hash_class(result.clone()).await?;
+        check_same("class hash", &class_hash, &computed_class_hash)?;
+
+        // A Sierra class is declared if the class commitment tree has a leaf
+        // for its hash, which already authenticates the class served. Cairo
+        // 0 classes are not in the tree.
+        if let GetClassResult::ContractClass(_) = &result {
+            let class_commitment = GetProofResult::fetch_class_commitment(
+                self.client.as_ref(),
+                block_id.clone(),
+                state_root,
+            )
+            .await?;
+            let class_proof =
+                self.client.getClassProof(block_id, class_hash.clone()).await?;
+            class_proof.verify_declared(&class_commitment, class_hash)?;
+        }
+        tracing::info!("getClass: verified");
+
+        Ok(result)
     }
 
     async fn getClassAt(
@@ -372,7 +1200,48 @@ impl gen::Rpc for Context {
         block_id: BlockId,
         contract_address: Address,
     ) -> std::result::Result<GetClassAtResult, jsonrpc::Error> {
-        self.client.getClassAt(block_id, contract_address).await
+        let (block_id, state_root) = self.resolve_block_id(block_id).await?;
+
+        let result = self
+            .client
+            .getClassAt(block_id.clone(), contract_address.clone())
+            .await?;
+        tracing::info!(?contract_address, ?block_id, "getClassAt");
+
+        let class = match result.clone() {
+            GetClassAtResult::ContractClass(class) => {
+                GetClassResult::ContractClass(class)
+            }
+            GetClassAtResult::DeprecatedContractClass(class) => {
+                GetClassResult::DeprecatedContractClass(class)
+            }
+        };
+        let is_sierra = matches!(class, GetClassResult::ContractClass(_));
+        let class_hash = hash_class(class).await?;
+
+        let proof = self
+            .client
+            .getProof(block_id.clone(), contract_address.clone(), vec![])
+            .await?;
+        proof.verify_class_hash(
+            state_root.clone(),
+            contract_address,
+            class_hash.clone(),
+        )?;
+
+        if is_sierra {
+            let class_commitment = proof.verify_class_commitment(state_root)?;
+
+            let class_proof =
+                self.client.getClassProof(block_id, class_hash.clone()).await?;
+            class_proof.verify_declared(&class_commitment, class_hash)?;
+        } else {
+            // The class of a deployed contract is declared.
+            set_declared_legacy_class(&class_hash, &block_id);
+        }
+        tracing::info!("getClassAt: verified");
+
+        Ok(result)
     }
 
     async fn getClassHashAt(
@@ -380,29 +1249,123 @@ impl gen::Rpc for Context {
         block_id: BlockId,
         contract_address: Address,
     ) -> std::result::Result<Felt, jsonrpc::Error> {
-        self.client.getClassHashAt(block_id, contract_address).await
-    }
+        let (block_id, state_root) = self.resolve_block_id(block_id).await?;
 
-    async fn getEvents(
-        &self,
-        filter: GetEventsFilter,
-    ) -> std::result::Result<EventsChunk, jsonrpc::Error> {
-        self.client.getEvents(filter).await
-    }
+        let result = self
+            .client
+            .getClassHashAt(block_id.clone(), contract_address.clone())
+            .await?;
+        tracing::info!(?contract_address, ?block_id, ?result, "getClassHashAt");
 
-    async fn getNonce(
-        &self,
+        let proof = self
+            .client
+            .getProof(block_id, contract_address.clone(), vec![])
+            .await?;
+
+        proof.verify_class_hash(
+            state_root,
+            contract_address,
+            result.clone(),
+        )?;
+        tracing::info!("getProof: verified");
+
+        Ok(result)
+    }
+
+    async fn getEvents(
+        &self,
+        filter: GetEventsFilter,
+    ) -> std::result::Result<EventsChunk, jsonrpc::Error> {
+        if !self.verify_events {
+            return self.client.getEvents(filter).await;
+        }
+
+        let result = self.get_verified_events_chunk(&filter).await?;
+        tracing::info!(events = result.events.len(), "getEvents: verified");
+
+        Ok(result)
+    }
+
+    async fn getNonce(
+        &self,
         block_id: BlockId,
         contract_address: Address,
     ) -> std::result::Result<Felt, jsonrpc::Error> {
-        self.client.getNonce(block_id, contract_address).await
+        let (block_id, state_root) = self.resolve_block_id(block_id).await?;
+
+        let result = self
+            .client
+            .getNonce(block_id.clone(), contract_address.clone())
+            .await?;
+        tracing::info!(?contract_address, ?block_id, ?result, "getNonce");
+
+        let proof = self
+            .client
+            .getProof(block_id, contract_address.clone(), vec![])
+            .await?;
+
+        proof.verify_nonce(state_root, contract_address, result.clone())?;
+        tracing::info!("getProof: verified");
+
+        Ok(result)
     }
 
     async fn getStateUpdate(
         &self,
         block_id: BlockId,
     ) -> std::result::Result<GetStateUpdateResult, jsonrpc::Error> {
-        self.client.getStateUpdate(block_id).await
+        let contents = self.get_block_contents(block_id).await?;
+        let header = contents.block.block_header;
+        let block_number = *header.block_number.as_ref() as u64;
+        let block_id =
+            BlockId::BlockNumber { block_number: header.block_number.clone() };
+
+        let result = self.client.getStateUpdate(block_id.clone()).await?;
+        let GetStateUpdateResult::StateUpdate(state_update) = &result else {
+            return Err(jsonrpc::Error {
+                code: -1,
+                message: "Pending state update received".to_owned(),
+            });
+        };
+        tracing::info!(?block_id, "getStateUpdate");
+
+        check_same(
+            "block hash",
+            &state_update.block_hash.0,
+            &header.block_hash.0,
+        )?;
+        check_same("new root", &state_update.new_root, &header.new_root)?;
+        if let Some(data) = &contents.data {
+            // Since 0.13.2 the block commits to its whole state diff.
+            verify_state_diff_commitment(
+                &state_update.state_diff,
+                &data.state_diff,
+            )?;
+        }
+        let parent = match block_number.checked_sub(1) {
+            Some(parent_number) => {
+                let state = self.state.read().await.clone();
+                let parent = self.verify_block(parent_number, &state).await?;
+                let block_number = BlockNumber::try_new(parent_number as i64)?;
+                Some((BlockId::BlockNumber { block_number }, parent.root))
+            }
+            None => None,
+        };
+        let old_root = match &parent {
+            Some((_, root)) => root.clone(),
+            None => Felt::try_new("0x0")?,
+        };
+        check_same("old root", &state_update.old_root, &old_root)?;
+
+        self.verify_state_diff(
+            &state_update.state_diff,
+            (&block_id, &header.new_root),
+            parent.as_ref(),
+        )
+        .await?;
+        tracing::info!("getStateUpdate: verified");
+
+        Ok(result)
     }
 
     async fn getStorageAt(
@@ -448,21 +1411,84 @@ impl gen::Rpc for Context {
         GetTransactionByBlockIdAndIndexResult,
         jsonrpc::Error,
     > {
-        self.client.getTransactionByBlockIdAndIndex(block_id, index).await
+        let block = self.get_block(block_id).await?;
+        let Some(transaction) = block
+            .block_body_with_txs
+            .transactions
+            .into_iter()
+            .nth(*index.as_ref() as usize)
+        else {
+            return Err(jsonrpc::Error::new(
+                27,
+                "Invalid transaction index in a block".to_owned(),
+            ));
+        };
+        self.verify_transaction_hash(&block.block_header, &transaction)?;
+
+        let BlockTransaction { txn, transaction_hash } = transaction;
+        Ok(GetTransactionByBlockIdAndIndexResult { txn, transaction_hash })
     }
 
     async fn getTransactionByHash(
         &self,
         transaction_hash: TxnHash,
     ) -> std::result::Result<GetTransactionByHashResult, jsonrpc::Error> {
-        self.client.getTransactionByHash(transaction_hash).await
+        let receipt =
+            self.client.getTransactionReceipt(transaction_hash.clone()).await?;
+        let receipt = receipt_properties(&receipt)?;
+        let block_id =
+            BlockId::BlockHash { block_hash: receipt.block_hash.clone() };
+
+        let (_, transaction) =
+            self.get_included_transaction(block_id, &transaction_hash).await?;
+        tracing::info!(?transaction_hash, "getTransactionByHash: verified");
+
+        let BlockTransaction { txn, transaction_hash } = transaction;
+        Ok(GetTransactionByHashResult { txn, transaction_hash })
     }
 
     async fn getTransactionReceipt(
         &self,
         transaction_hash: TxnHash,
     ) -> std::result::Result<GetTransactionReceiptResult, jsonrpc::Error> {
-        self.client.getTransactionReceipt(transaction_hash).await
+        let result =
+            self.client.getTransactionReceipt(transaction_hash.clone()).await?;
+        let receipt = receipt_properties(&result)?;
+        if !same_hash(&receipt.transaction_hash.0, &transaction_hash.0) {
+            return Err(ProofError::ValueMismatch {
+                name: "transaction hash",
+                provided: receipt.transaction_hash.0.as_ref().clone(),
+                proven: transaction_hash.0.as_ref().clone(),
+            }
+            .into());
+        }
+
+        let block_id =
+            BlockId::BlockHash { block_hash: receipt.block_hash.clone() };
+        let contents = self.get_block_contents(block_id).await?;
+        let index = transaction_index(&contents.block, &transaction_hash)?;
+        let transactions = &contents.block.block_body_with_txs.transactions;
+        let header = &contents.block.block_header;
+        self.verify_transaction_hash(header, &transactions[index])?;
+        if receipt.block_number.as_ref() != header.block_number.as_ref() {
+            return Err(ProofError::ValueMismatch {
+                name: "block number",
+                provided: receipt.block_number.as_ref().to_string(),
+                proven: header.block_number.as_ref().to_string(),
+            }
+            .into());
+        }
+        // Before 0.13.2 the block hash does not commit to receipts, so only
+        // the inclusion of the transaction in the block is verified: the
+        // fee, execution status, messages sent and events are as served by
+        // the provider. The fee unit and execution resources are never
+        // verified.
+        if let Some(data) = &contents.data {
+            verify_receipt(receipt, &data.receipts[index], &contents.events)?;
+        }
+        tracing::info!(?transaction_hash, "getTransactionReceipt: verified");
+
+        Ok(result)
     }
 
     async fn getTransactionStatus(
@@ -478,9 +1504,34 @@ impl gen::Rpc for Context {
         transactions: Vec<BroadcastedTxn>,
         simulation_flags: Vec<SimulationFlag>,
     ) -> std::result::Result<Vec<SimulatedTransaction>, jsonrpc::Error> {
-        self.client
-            .simulateTransactions(block_id, transactions, simulation_flags)
-            .await
+        let (block_header, data_gas) =
+            self.get_execution_block(block_id).await?;
+        let chain = self.chain.clone();
+        let validate = !simulation_flags
+            .iter()
+            .any(|flag| matches!(flag, SimulationFlag::SkipValidate));
+        let charge_fee = !simulation_flags
+            .iter()
+            .any(|flag| matches!(flag, SimulationFlag::SkipFeeCharge));
+
+        let client = gen::client::blocking::Client::new(&self.url);
+        let simulated = tokio::task::spawn_blocking(move || {
+            crate::exe::simulate(
+                &client,
+                &block_header,
+                &chain,
+                data_gas.as_ref(),
+                transactions,
+                validate,
+                charge_fee,
+            )
+        })
+        .await
+        .map_err(|e| {
+            iamgroot::jsonrpc::Error::new(500, format!("join error: {e}"))
+        })??;
+
+        Ok(simulated)
     }
 
     async fn specVersion(&self) -> std::result::Result<String, jsonrpc::Error> {
@@ -497,14 +1548,82 @@ impl gen::Rpc for Context {
         &self,
         block_id: BlockId,
     ) -> std::result::Result<Vec<BlockTransactionTrace>, jsonrpc::Error> {
-        self.client.traceBlockTransactions(block_id).await
+        let contents = self.get_block_contents(block_id).await?;
+        let data_gas = self.get_data_gas(&contents).await?;
+        let block = contents.block;
+        let block_hash = block.block_header.block_hash.clone();
+        let transaction_hashes = block
+            .block_body_with_txs
+            .transactions
+            .iter()
+            .map(|transaction| transaction.transaction_hash.clone())
+            .collect::<Vec<_>>();
+        let traces = self
+            .trace_transactions(
+                block.block_header,
+                data_gas,
+                block.block_body_with_txs.transactions,
+            )
+            .await?;
+
+        let mut result = Vec::with_capacity(traces.len());
+        for (transaction_hash, trace) in
+            transaction_hashes.into_iter().zip(traces)
+        {
+            let receipt = self
+                .client
+                .getTransactionReceipt(transaction_hash.clone())
+                .await?;
+            let receipt = receipt_properties(&receipt)?;
+            check_same(
+                "transaction hash",
+                &receipt.transaction_hash.0,
+                &transaction_hash.0,
+            )?;
+            check_same("block hash", &receipt.block_hash.0, &block_hash.0)?;
+            crate::transaction::verify_trace(&trace, receipt)?;
+            result.push(BlockTransactionTrace {
+                trace_root: Some(trace),
+                transaction_hash: Some(transaction_hash.0),
+            });
+        }
+        Ok(result)
     }
 
     async fn traceTransaction(
         &self,
         transaction_hash: TxnHash,
     ) -> std::result::Result<TransactionTrace, jsonrpc::Error> {
-        self.client.traceTransaction(transaction_hash).await
+        let receipt =
+            self.getTransactionReceipt(transaction_hash.clone()).await?;
+        let receipt = receipt_properties(&receipt)?;
+
+        let block_id =
+            BlockId::BlockHash { block_hash: receipt.block_hash.clone() };
+        let contents = self.get_block_contents(block_id).await?;
+        let data_gas = self.get_data_gas(&contents).await?;
+        let block = contents.block;
+        let transactions = block.block_body_with_txs.transactions;
+        let index = transactions
+            .iter()
+            .position(|transaction| {
+                same_hash(&transaction.transaction_hash.0, &transaction_hash.0)
+            })
+            .ok_or_else(|| VerifyError::TransactionNotInBlock {
+                block_number: *block.block_header.block_number.as_ref(),
+                transaction_hash: transaction_hash.0.as_ref().clone(),
+            })?;
+        // The whole block is traced, to check the state diff it leaves.
+        let trace = self
+            .trace_transactions(block.block_header, data_gas, transactions)
+            .await?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| {
+                jsonrpc::Error::new(-1, "Missing transaction trace".to_owned())
+            })?;
+        crate::transaction::verify_trace(&trace, receipt)?;
+        Ok(trace)
     }
 
     async fn getProof(
@@ -516,6 +1635,14 @@ impl gen::Rpc for Context {
         self.client.getProof(block_id, contract_address, keys).await
     }
 
+    async fn getClassProof(
+        &self,
+        block_id: gen::BlockId,
+        class_hash: gen::Felt,
+    ) -> std::result::Result<gen::GetClassProofResult, jsonrpc::Error> {
+        self.client.getClassProof(block_id, class_hash).await
+    }
+
     async fn getTxStatus(
         &self,
         transaction_hash: gen::TxnHash,
@@ -528,374 +1655,1591 @@ impl gen::Rpc for Context {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::sync::Arc;
+/// Handle the methods Beerus serves on top of the Starknet API and pass all
+/// the other ones on to it.
+async fn dispatch(ctx: &Context, req: &jsonrpc::Request) -> jsonrpc::Response {
+    let params = req.params.clone().unwrap_or_default();
 
-    use iamgroot::jsonrpc;
-    use tokio::sync::RwLock;
-    use wiremock::{
-        matchers::any, Mock, MockGuard, MockServer, ResponseTemplate,
+    let response = match req.method.as_str() {
+        "beerus_getMessagesToL2Status" => {
+            match param(&params, 0, "transaction_hash") {
+                Some(hash) => response(ctx.messages_to_l2_status(hash).await),
+                None => jsonrpc::Response::error(-32602, "Invalid params"),
+            }
+        }
+        "beerus_getMessageToL2Status" => {
+            match param(&params, 0, "message_hash") {
+                Some(hash) => response(ctx.message_to_l2_status(hash).await),
+                None => jsonrpc::Response::error(-32602, "Invalid params"),
+            }
+        }
+        "beerus_getMessageToL1Count" => match param(&params, 0, "message") {
+            Some(message) => response(ctx.message_to_l1_count(&message).await),
+            None => jsonrpc::Response::error(-32602, "Invalid params"),
+        },
+        "beerus_ethCall" | "beerus_ethGetBalance" | "beerus_ethBlockNumber"
+            if !ctx.eth_methods =>
+        {
+            jsonrpc::Response::error(-32601, "Method not found")
+        }
+        "beerus_ethCall" => {
+            let opts = param(&params, 0, "transaction");
+            match (opts, block_tag(&params, 1)) {
+                (Some(opts), Some(tag)) => {
+                    response(ctx.eth_call(&opts, tag).await)
+                }
+                _ => jsonrpc::Response::error(-32602, "Invalid params"),
+            }
+        }
+        "beerus_ethGetBalance" => {
+            let address = param(&params, 0, "address");
+            match (address, block_tag(&params, 1)) {
+                (Some(address), Some(tag)) => {
+                    response(ctx.eth_get_balance(address, tag).await)
+                }
+                _ => jsonrpc::Response::error(-32602, "Invalid params"),
+            }
+        }
+        "beerus_ethBlockNumber" => response(ctx.eth_block_number().await),
+        _ => return gen::handle(ctx, req).await,
     };
 
-    use crate::rpc::{BlockHash, BlockId, BlockNumber, BlockTag, Felt};
+    if let Some(id) = req.id.as_ref() {
+        response.with_id(id.clone())
+    } else {
+        response
+    }
+}
 
-    use super::{client::Client, ClientState, Context};
+/// The parameter at the given position or with the given name, depending on
+/// whether the parameters are passed by position or by name.
+fn param<T: DeserializeOwned>(
+    params: &Value,
+    index: usize,
+    name: &str,
+) -> Option<T> {
+    let value = param_value(params, index, name)?;
+    serde_json::from_value(value.clone()).ok()
+}
 
-    fn make_state(block_number: u64, block_hash: &str) -> ClientState {
-        ClientState {
-            block_number,
-            block_hash: Felt::try_new(block_hash).unwrap(),
-            root: Felt::try_new("0x0").unwrap(),
-        }
+fn param_value<'a>(
+    params: &'a Value,
+    index: usize,
+    name: &str,
+) -> Option<&'a Value> {
+    match params {
+        Value::Array(params) => params.get(index),
+        Value::Object(params) => params.get(name),
+        _ => None,
     }
+}
 
-    fn make_context(
-        url_local: &str,
-        url_client: &str,
-        state: ClientState,
-    ) -> Context {
-        Context {
-            url: url_local.to_string(),
-            client: Arc::new(Client::new(url_client)),
-            state: Arc::new(RwLock::new(state)),
-        }
+/// The L1 block of an Ethereum request, the latest one unless specified.
+fn block_tag(params: &Value, index: usize) -> Option<L1BlockTag> {
+    match param_value(params, index, "block") {
+        None | Some(Value::Null) => Some(L1BlockTag::Latest),
+        Some(value) => serde_json::from_value(value.clone()).ok(),
     }
+}
 
-    fn block_from_hash(block_hash: &str) -> BlockId {
-        BlockId::BlockHash {
-            block_hash: BlockHash(Felt::try_new(block_hash).unwrap()),
+fn response<T: Serialize>(
+    result: std::result::Result<T, jsonrpc::Error>,
+) -> jsonrpc::Response {
+    match result.map(serde_json::to_value) {
+        Ok(Ok(ret)) => jsonrpc::Response::result(ret),
+        Ok(Err(error)) => {
+            tracing::debug!(?error, "failed to parse response object");
+            jsonrpc::Response::error(-32603, "Internal error")
         }
+        Err(e) => jsonrpc::Response::error(e.code, &e.message),
     }
+}
 
-    fn block_from_number(block_number: u64) -> BlockId {
-        BlockId::BlockNumber {
-            block_number: BlockNumber::try_new(block_number as i64).unwrap(),
-        }
+/// Status of an L1->L2 message as recorded by the core contract on L1. When
+/// the message itself is known, it comes with the L1 handler transaction
+/// that consumed it on L2 (if any).
+#[derive(Debug, Serialize)]
+struct MessageToL2Status {
+    message_hash: H256,
+    status: L1MessageStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<MsgFromL1>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction_hash: Option<TxnHash>,
+}
+
+/// Number of times an L2->L1 message can be consumed on L1, as recorded by
+/// the core contract: zero until the L2 block that sent it is settled on L1,
+/// and again once the message has been consumed.
+#[derive(Debug, Serialize)]
+struct MessageToL1Count {
+    message_hash: H256,
+    count: u64,
+}
+
+impl Context {
+    fn ethereum(&self) -> std::result::Result<&EthereumClient, jsonrpc::Error> {
+        self.ethereum.as_deref().ok_or_else(|| jsonrpc::Error {
+            code: -1,
+            message: "Ethereum client is not available".to_owned(),
+        })
     }
 
-    fn block_from_tag(tag: &str) -> BlockId {
-        match tag {
-            "pending" => BlockId::BlockTag(BlockTag::Pending),
-            _ => BlockId::BlockTag(BlockTag::Latest),
+    /// Status of every L1->L2 message sent by the L1 transaction.
+    async fn messages_to_l2_status(
+        &self,
+        transaction_hash: H256,
+    ) -> std::result::Result<Vec<MessageToL2Status>, jsonrpc::Error> {
+        let messages = self
+            .ethereum()?
+            .messages_to_l2(transaction_hash)
+            .await
+            .map_err(eth_error)?;
+
+        let mut ret = Vec::with_capacity(messages.len());
+        for message in messages {
+            let mut status = self.message_to_l2_status(message.hash()).await?;
+            let msg = message.msg_from_l1().map_err(eth_error)?;
+            let nonce = message.l1_handler_nonce().map_err(eth_error)?;
+            status.transaction_hash =
+                self.consuming_transaction(&msg, &nonce).await?;
+            // The message was sent, so one that is no longer waiting on L1
+            // was consumed. A cancellation only completes while the message
+            // is still waiting on L1, which it no longer is once consumed.
+            if status.status == L1MessageStatus::Unknown
+                || (status.transaction_hash.is_some()
+                    && status.status == L1MessageStatus::Cancelled)
+            {
+                status.status = L1MessageStatus::Consumed;
+            }
+            status.message = Some(msg);
+            status.nonce = Some(nonce);
+            ret.push(status);
         }
+        Ok(ret)
     }
 
-    fn eq(lhs: &BlockId, rhs: &BlockId) -> bool {
-        match (lhs, rhs) {
-            (
-                BlockId::BlockNumber { block_number: lhs },
-                BlockId::BlockNumber { block_number: rhs },
-            ) => lhs.as_ref() == rhs.as_ref(),
-            (
-                BlockId::BlockHash { block_hash: lhs },
-                BlockId::BlockHash { block_hash: rhs },
-            ) => lhs.0.as_ref() == rhs.0.as_ref(),
-            (
-                BlockId::BlockTag(BlockTag::Latest),
-                BlockId::BlockTag(BlockTag::Latest),
-            ) => true,
-            (
-                BlockId::BlockTag(BlockTag::Pending),
-                BlockId::BlockTag(BlockTag::Pending),
-            ) => true,
-            _ => false,
-        }
+    async fn message_to_l2_status(
+        &self,
+        message_hash: H256,
+    ) -> std::result::Result<MessageToL2Status, jsonrpc::Error> {
+        let status = self
+            .ethereum()?
+            .message_to_l2_status(message_hash)
+            .await
+            .map_err(eth_error)?;
+        Ok(MessageToL2Status {
+            message_hash,
+            status,
+            message: None,
+            nonce: None,
+            transaction_hash: None,
+        })
     }
 
-    fn get_block_with_tx_hashes_response(
-        block_num: u64,
-        block_hash: &str,
-    ) -> String {
-        serde_json::to_string(&serde_json::json!(
-            {
-                "jsonrpc": "2.0",
-                "id":1,
-                "result": {
-                    "block_hash": block_hash,
-                    "block_number": block_num,
-                    "l1_gas_price": {
-                        "price_in_fri": "0x2",
-                        "price_in_wei": "0x3"
-                    },
-                    "new_root": "0x4",
-                    "parent_hash": "0x5",
-                    "sequencer_address": "0x6",
-                    "starknet_version": "0.13.1",
-                    "status": "ACCEPTED_ON_L1",
-                    "timestamp": 1,
-                    "transactions" : []
-                }
-            }
-        ))
-        .unwrap()
+    async fn message_to_l1_count(
+        &self,
+        message: &MsgToL1,
+    ) -> std::result::Result<MessageToL1Count, jsonrpc::Error> {
+        let ethereum = self.ethereum()?;
+        let message_hash = message_to_l1_hash(message).map_err(eth_error)?;
+        let count = ethereum
+            .message_to_l1_count(message_hash)
+            .await
+            .map_err(eth_error)?;
+        Ok(MessageToL1Count { message_hash, count })
     }
 
-    async fn setup_test_env(
-        starknet_server: &MockServer,
-        helios_block_num: u64,
-        starknet_response_block_num: u64,
-        starknet_response_block_hash: &str,
-        expect_request: u64,
-    ) -> (MockGuard, Context) {
-        let mock_guard = Mock::given(any())
-            .respond_with(ResponseTemplate::new(200).set_body_string(
-                get_block_with_tx_hashes_response(
-                    starknet_response_block_num,
-                    starknet_response_block_hash,
-                ),
-            ))
-            .expect(expect_request)
-            .mount_as_scoped(starknet_server)
-            .await;
-        let state = make_state(helios_block_num, "0x27");
-        let context =
-            make_context("127.0.0.1:3030", &starknet_server.uri(), state);
+    async fn eth_call(
+        &self,
+        opts: &CallOpts,
+        tag: L1BlockTag,
+    ) -> std::result::Result<Bytes, jsonrpc::Error> {
+        self.ethereum()?.eth_call(opts, tag).await.map_err(eth_error)
+    }
 
-        (mock_guard, context)
+    async fn eth_get_balance(
+        &self,
+        address: L1Address,
+        tag: L1BlockTag,
+    ) -> std::result::Result<U256, jsonrpc::Error> {
+        self.ethereum()?.eth_get_balance(address, tag).await.map_err(eth_error)
     }
 
-    async fn resolve_block_by_number_test(
-        requested_starknet_block_num: u64,
-        helios_block_num: u64,
-        starknet_response_block_num: u64,
-        expect_request: u64,
-    ) -> Result<(BlockId, Felt), jsonrpc::Error> {
-        let starknet_server = MockServer::start().await;
-        let request_block_num =
-            BlockNumber::try_new(requested_starknet_block_num as i64).unwrap();
-
-        let (_mock_guard, context) = setup_test_env(
-            &starknet_server,
-            helios_block_num,
-            starknet_response_block_num,
-            "0x3",
-            expect_request,
-        )
-        .await;
+    async fn eth_block_number(
+        &self,
+    ) -> std::result::Result<U64, jsonrpc::Error> {
+        let block_number =
+            self.ethereum()?.eth_block_number().await.map_err(eth_error)?;
+        Ok(U64::from(block_number))
+    }
 
-        let state = &context.state.read().await;
-        context.resolve_block_by_number(request_block_num, state).await
+    /// Hash of the L1 handler transaction that consumed the message on L2,
+    /// if the provider knows of it. Its receipt must verify and show that it
+    /// succeeded; a receipt that fails to verify is an error, not a miss.
+    async fn consuming_transaction(
+        &self,
+        message: &MsgFromL1,
+        nonce: &Felt,
+    ) -> std::result::Result<Option<TxnHash>, jsonrpc::Error> {
+        let chain_id = &self.chain.chain_id;
+
+        let from_address = message.from_address.as_ref();
+        let from_address =
+            from_address.trim_start_matches("0x").trim_start_matches('0');
+        let from_address = Felt::try_new(&format!("0x{from_address:0>1}"))?;
+
+        let txn = Txn::L1HandlerTxn(L1HandlerTxn {
+            nonce: NumAsHex::try_new(nonce.as_ref())?,
+            r#type: L1HandlerTxnType::L1Handler,
+            version: Felt::try_new("0x0")?,
+            function_call: FunctionCall {
+                calldata: std::iter::once(from_address)
+                    .chain(message.payload.iter().cloned())
+                    .collect(),
+                contract_address: message.to_address.clone(),
+                entry_point_selector: message.entry_point_selector.clone(),
+            },
+        });
+        let transaction_hash =
+            TxnHash(crate::transaction::transaction_hash(&txn, chain_id)?);
+
+        let not_found = jsonrpc::Error::from(gen::error::TXN_HASH_NOT_FOUND);
+        let result =
+            match self.getTransactionReceipt(transaction_hash.clone()).await {
+                Ok(result) => result,
+                Err(e) if e.code == not_found.code => {
+                    tracing::debug!(?transaction_hash, "not consumed");
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            };
+        let receipt = receipt_properties(&result)?;
+        Ok(matches!(receipt.execution_status, TxnExecutionStatus::Succeeded)
+            .then_some(transaction_hash))
     }
+}
 
-    async fn resolve_block_by_hash_test(
-        requested_starknet_block_hash: &str,
-        helios_block_num: u64,
-        starknet_response_block_hash: &str,
-        starknet_response_block_num: u64,
-        expect_request: u64,
-    ) -> Result<(BlockId, Felt), jsonrpc::Error> {
-        let starknet_server = MockServer::start().await;
-        let request_block_hash =
-            BlockHash(Felt::try_new(requested_starknet_block_hash).unwrap());
-
-        let (_mock_guard, context) = setup_test_env(
-            &starknet_server,
-            helios_block_num,
-            starknet_response_block_num,
-            starknet_response_block_hash,
-            expect_request,
-        )
-        .await;
+fn eth_error(e: eyre::Report) -> jsonrpc::Error {
+    jsonrpc::Error { code: -1, message: format!("{e:#}") }
+}
+
+/// Properties of an accepted receipt. Pending transactions are not part of
+/// any block yet, so their inclusion cannot be verified.
+fn receipt_properties(
+    receipt: &GetTransactionReceiptResult,
+) -> std::result::Result<&CommonReceiptProperties, jsonrpc::Error> {
+    let GetTransactionReceiptResult::TxnReceipt(receipt) = receipt else {
+        return Err(jsonrpc::Error {
+            code: -1,
+            message: "Pending transaction receipt received".to_owned(),
+        });
+    };
+    Ok(match receipt {
+        TxnReceipt::InvokeTxnReceipt(receipt) => {
+            &receipt.common_receipt_properties
+        }
+        TxnReceipt::L1HandlerTxnReceipt(receipt) => {
+            &receipt.common_receipt_properties
+        }
+        TxnReceipt::DeclareTxnReceipt(receipt) => {
+            &receipt.common_receipt_properties
+        }
+        TxnReceipt::DeployTxnReceipt(receipt) => {
+            &receipt.common_receipt_properties
+        }
+        TxnReceipt::DeployAccountTxnReceipt(receipt) => {
+            &receipt.common_receipt_properties
+        }
+    })
+}
 
-        let state = &context.state.read().await;
-        context.resolve_block_by_hash(request_block_hash, state).await
+/// Compare felts by value, so that leading zeros and case do not matter.
+fn same_hash(lhs: &Felt, rhs: &Felt) -> bool {
+    match (as_field_element(lhs), as_field_element(rhs)) {
+        (Ok(lhs), Ok(rhs)) => lhs == rhs,
+        _ => false,
     }
+}
 
-    async fn resolve_block_id_test(
-        block: BlockId,
-        helios_block_num: u64,
-        starknet_response_block_num: u64,
-        starknet_response_block_hash: &str,
-        expect_request: u64,
-    ) -> Result<(BlockId, Felt), jsonrpc::Error> {
-        let starknet_server = MockServer::start().await;
+fn check_same(
+    name: &'static str,
+    provided: &Felt,
+    proven: &Felt,
+) -> Result<(), ProofError> {
+    if !same_hash(provided, proven) {
+        return Err(ProofError::ValueMismatch {
+            name,
+            provided: provided.as_ref().clone(),
+            proven: proven.as_ref().clone(),
+        });
+    }
+    Ok(())
+}
 
-        let (_mock_guard, context) = setup_test_env(
-            &starknet_server,
-            helios_block_num,
-            starknet_response_block_num,
-            starknet_response_block_hash,
-            expect_request,
-        )
-        .await;
+/// Changes made to a single contract by a state diff.
+#[derive(Default)]
+struct ContractDiff {
+    storage: Vec<(StorageKey, Felt)>,
+    nonce: Option<Felt>,
+    class_hash: Option<Felt>,
+    deployed: bool,
+}
 
-        context.resolve_block_id(block).await
+/// Group the state diff by contract, in the order of contract addresses.
+fn contract_diffs(
+    state_diff: &StateDiff,
+) -> Result<Vec<(Address, ContractDiff)>, jsonrpc::Error> {
+    let mut contracts: BTreeMap<FieldElement, (Address, ContractDiff)> =
+        BTreeMap::new();
+    fn contract<'a>(
+        contracts: &'a mut BTreeMap<FieldElement, (Address, ContractDiff)>,
+        address: &Felt,
+    ) -> Result<&'a mut ContractDiff, ProofError> {
+        Ok(&mut contracts
+            .entry(as_field_element(address)?)
+            .or_insert_with(|| (Address(address.clone()), Default::default()))
+            .1)
+    }
+    for item in &state_diff.storage_diffs {
+        let diff = contract(&mut contracts, &item.address)?;
+        for entry in &item.storage_entries {
+            let (Some(key), Some(value)) = (&entry.key, &entry.value) else {
+                return Err(incomplete_state_diff());
+            };
+            diff.storage.push((as_storage_key(key)?, value.clone()));
+        }
+    }
+    for item in &state_diff.nonces {
+        let (Some(address), Some(nonce)) =
+            (&item.contract_address, &item.nonce)
+        else {
+            return Err(incomplete_state_diff());
+        };
+        contract(&mut contracts, &address.0)?.nonce = Some(nonce.clone());
+    }
+    for item in &state_diff.deployed_contracts {
+        let diff = contract(&mut contracts, &item.address)?;
+        diff.class_hash = Some(item.class_hash.clone());
+        diff.deployed = true;
+    }
+    for item in &state_diff.replaced_classes {
+        let (Some(address), Some(class_hash)) =
+            (&item.contract_address, &item.class_hash)
+        else {
+            return Err(incomplete_state_diff());
+        };
+        contract(&mut contracts, &address.0)?.class_hash =
+            Some(class_hash.clone());
     }
+    Ok(contracts.into_values().collect())
+}
 
-    #[tokio::test]
-    async fn resolve_block_by_number_request_lower_success() {
-        let requested_starknet_block_num = 3;
-        let helios_state_block_num = 27;
-        let starknet_response_block_number = 3;
-        let expected_num_request = 1;
-
-        let result = resolve_block_by_number_test(
-            requested_starknet_block_num,
-            helios_state_block_num,
-            starknet_response_block_number,
-            expected_num_request,
-        )
-        .await;
+/// Storage keys are zero-padded to 32 bytes, unlike felts.
+fn as_storage_key(key: &Felt) -> Result<StorageKey, jsonrpc::Error> {
+    let digits = key.as_ref().trim_start_matches("0x");
+    StorageKey::try_new(&format!("0x{digits:0>64}"))
+}
 
-        assert!(result.is_ok());
-        let (returned_block, _) = result.unwrap();
-        assert!(eq(
-            &block_from_number(requested_starknet_block_num),
-            &returned_block
-        ));
+/// Check that the state diff has the same commitment as the one the verified
+/// block commits to, so that no entry is missing or added.
+fn verify_state_diff_commitment(
+    state_diff: &StateDiff,
+    verified: &StateDiff,
+) -> Result<(), jsonrpc::Error> {
+    let commitment = |state_diff: &StateDiff| {
+        crate::block::state_diff_commitment(state_diff)
+            .map(|(commitment, _)| commitment)
+            .ok_or_else(incomplete_state_diff)
+    };
+    let provided = commitment(state_diff)?;
+    let proven = commitment(verified)?;
+    if provided != proven {
+        return Err(ProofError::ValueMismatch {
+            name: "state diff commitment",
+            provided: format!("{provided:#x}"),
+            proven: format!("{proven:#x}"),
+        }
+        .into());
     }
+    Ok(())
+}
 
-    #[tokio::test]
-    async fn resolve_block_by_number_request_higher_success() {
-        let requested_starknet_block_num = 42;
-        let helios_state_block_num = 27;
-        let starknet_response_block_number = 42;
-        let expected_num_request = 0;
-
-        let result = resolve_block_by_number_test(
-            requested_starknet_block_num,
-            helios_state_block_num,
-            starknet_response_block_number,
-            expected_num_request,
-        )
-        .await;
+fn incomplete_state_diff() -> jsonrpc::Error {
+    jsonrpc::Error {
+        code: -1,
+        message: "Incomplete state diff received".to_owned(),
+    }
+}
 
-        assert!(result.is_ok());
-        let (returned_block, _) = result.unwrap();
-        assert!(eq(
-            &block_from_number(helios_state_block_num),
-            &returned_block
+fn same_felts(lhs: &[Felt], rhs: &[Felt]) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(l, r)| same_hash(l, r))
+}
+
+/// Index of the transaction in the block.
+fn transaction_index(
+    block: &BlockWithTxs,
+    transaction_hash: &TxnHash,
+) -> Result<usize, VerifyError> {
+    block
+        .block_body_with_txs
+        .transactions
+        .iter()
+        .position(|tx| same_hash(&tx.transaction_hash.0, &transaction_hash.0))
+        .ok_or_else(|| VerifyError::TransactionNotInBlock {
+            block_number: *block.block_header.block_number.as_ref(),
+            transaction_hash: transaction_hash.0.as_ref().clone(),
+        })
+}
+
+/// Check the receipt against the one the block commits to (since 0.13.2),
+/// and its events against the events committed to for its transaction.
+fn verify_receipt(
+    receipt: &CommonReceiptProperties,
+    committed: &crate::block::Receipt,
+    events: &[EmittedEvent],
+) -> Result<(), ProofError> {
+    let mismatch = |name, provided: String, proven: String| {
+        ProofError::ValueMismatch { name, provided, proven }
+    };
+
+    let actual_fee = as_field_element(&receipt.actual_fee.amount)?;
+    if actual_fee != committed.actual_fee {
+        return Err(mismatch(
+            "actual fee",
+            receipt.actual_fee.amount.as_ref().clone(),
+            format!("{:#x}", committed.actual_fee),
         ));
     }
 
-    #[tokio::test]
-    async fn resolve_block_by_number_same_success() {
-        let requested_starknet_block_num = 27;
-        let helios_state_block_num = 27;
-        let starknet_response_block_number = requested_starknet_block_num;
-        let expected_num_request = 0;
-
-        let result = resolve_block_by_number_test(
-            requested_starknet_block_num,
-            helios_state_block_num,
-            starknet_response_block_number,
-            expected_num_request,
-        )
-        .await;
+    let revert_reason = match receipt.execution_status {
+        TxnExecutionStatus::Succeeded => None,
+        TxnExecutionStatus::Reverted => {
+            Some(receipt.revert_reason.clone().unwrap_or_default())
+        }
+    };
+    if revert_reason != committed.revert_reason {
+        return Err(mismatch(
+            "revert reason",
+            format!("{revert_reason:?}"),
+            format!("{:?}", committed.revert_reason),
+        ));
+    }
 
-        assert!(result.is_ok());
-        let (returned_block, _) = result.unwrap();
-        assert!(eq(
-            &block_from_number(requested_starknet_block_num),
-            &returned_block
+    let same_message = |message: &MsgToL1, committed: &MessageToL1| {
+        let payload = message
+            .payload
+            .iter()
+            .map(as_field_element)
+            .collect::<Result<Vec<_>, _>>();
+        as_field_element(&message.from_address) == Ok(committed.from_address)
+            && as_field_element(&message.to_address) == Ok(committed.to_address)
+            && payload == Ok(committed.payload.clone())
+    };
+    if receipt.messages_sent.len() != committed.messages_sent.len()
+        || !receipt
+            .messages_sent
+            .iter()
+            .zip(&committed.messages_sent)
+            .all(|(message, committed)| same_message(message, committed))
+    {
+        return Err(mismatch(
+            "messages sent",
+            format!("{} messages", receipt.messages_sent.len()),
+            format!("{} messages", committed.messages_sent.len()),
         ));
     }
 
-    #[tokio::test]
-    async fn resolve_block_by_number_wrong_number_return() {
-        let requested_starknet_block_num = 5;
-        let helios_state_block_num = 27;
-        let starknet_response_block_number = 999;
-        let expected_num_request = 1;
-
-        let result = resolve_block_by_number_test(
-            requested_starknet_block_num,
-            helios_state_block_num,
-            starknet_response_block_number,
-            expected_num_request,
+    let events: Vec<&Event> = events
+        .iter()
+        .filter(|event| {
+            same_hash(&event.transaction_hash.0, &receipt.transaction_hash.0)
+        })
+        .map(|event| &event.event)
+        .collect();
+    let same_event = |lhs: &Event, rhs: &Event| {
+        same_hash(&lhs.from_address.0, &rhs.from_address.0)
+            && same_felts(&lhs.event_content.keys, &rhs.event_content.keys)
+            && same_felts(&lhs.event_content.data, &rhs.event_content.data)
+    };
+    if receipt.events.len() != events.len()
+        || !receipt
+            .events
+            .iter()
+            .zip(&events)
+            .all(|(event, committed)| same_event(event, committed))
+    {
+        return Err(mismatch(
+            "events",
+            format!("{} events", receipt.events.len()),
+            format!("{} events", events.len()),
+        ));
+    }
+    Ok(())
+}
+
+fn same_event(lhs: &EmittedEvent, rhs: &EmittedEvent) -> bool {
+    let block_hash = |event: &EmittedEvent| {
+        event
+            .block_hash
+            .as_ref()
+            .and_then(|hash| as_field_element(&hash.0).ok())
+    };
+    let block_number = |event: &EmittedEvent| {
+        event.block_number.as_ref().map(|number| *number.as_ref())
+    };
+    same_hash(&lhs.event.from_address.0, &rhs.event.from_address.0)
+        && same_felts(
+            &lhs.event.event_content.keys,
+            &rhs.event.event_content.keys,
         )
-        .await;
+        && same_felts(
+            &lhs.event.event_content.data,
+            &rhs.event.event_content.data,
+        )
+        && same_hash(&lhs.transaction_hash.0, &rhs.transaction_hash.0)
+        && block_hash(lhs) == block_hash(rhs)
+        && block_number(lhs) == block_number(rhs)
+}
+
+/// An empty list of keys at some position matches any key at it.
+fn matches_event_filter(filter: &EventFilter, event: &Event) -> bool {
+    let address = match &filter.address {
+        Some(address) => same_hash(&address.0, &event.from_address.0),
+        None => true,
+    };
+    let keys = filter.keys.iter().flatten().enumerate().all(|(i, keys)| {
+        keys.is_empty()
+            || event
+                .event_content
+                .keys
+                .get(i)
+                .is_some_and(|key| keys.iter().any(|k| same_hash(k, key)))
+    });
+    address && keys
+}
+
+/// Check that `events` are the first of the `expected` events, and all of
+/// them for the last page.
+fn is_events_prefix(
+    expected: &[EmittedEvent],
+    events: &[EmittedEvent],
+    is_last_page: bool,
+) -> bool {
+    let complete = !is_last_page || events.len() == expected.len();
+    complete
+        && events.len() <= expected.len()
+        && expected
+            .iter()
+            .zip(events)
+            .all(|(expected, event)| same_event(expected, event))
+}
+
+/// Parse the continuation token of a verified events chunk: the block
+/// number and the index of the next event among the matching events of
+/// the block, as in "1234-5".
+fn parse_events_token(token: &str) -> Result<(u64, usize), jsonrpc::Error> {
+    token
+        .split_once('-')
+        .and_then(|(block, index)| {
+            Some((block.parse().ok()?, index.parse().ok()?))
+        })
+        .ok_or_else(|| gen::error::INVALID_CONTINUATION_TOKEN.into())
+}
+
+/// Compute the hash of the class. Decoding a Cairo 0 program is CPU-bound,
+/// hence the blocking task.
+async fn hash_class(
+    class: GetClassResult,
+) -> std::result::Result<Felt, jsonrpc::Error> {
+    let class_hash =
+        tokio::task::spawn_blocking(move || crate::class::class_hash(&class))
+            .await
+            .map_err(|e| {
+                iamgroot::jsonrpc::Error::new(500, format!("join error: {e}"))
+            })??;
+
+    Felt::try_new(&format!("0x{:x}", class_hash))
+}
+
+/// Remember that the Cairo 0 class is declared at the block, for executions
+/// to take it as declared without fetching it again.
+fn set_declared_legacy_class(class_hash: &Felt, block_id: &BlockId) {
+    let BlockId::BlockNumber { block_number } = block_id else {
+        return;
+    };
+    let block_number = *block_number.as_ref() as u64;
+    if let Err(e) =
+        crate::exe::cache::set_declared_legacy_class(class_hash, block_number)
+    {
+        tracing::warn!(?class_hash, error=?e, "declaration not cached");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use iamgroot::jsonrpc;
+    use serde_json::{json, Value};
+    use tokio::sync::RwLock;
+    use wiremock::{
+        matchers::any, Mock, MockServer, Respond, ResponseTemplate,
+    };
+
+    use crate::gen::{self, Rpc};
+    use crate::rpc::{
+        BlockHash, BlockId, BlockNumber, BlockTag, Felt,
+        GetTransactionByBlockIdAndIndexIndex, Txn, TxnHash,
+    };
+
+    use super::{
+        client::Client, dispatch, ClientState, Context, ExecutionChain,
+        FeederGateway, MAX_BLOCK_WALK,
+    };
+
+    const CHAIN_ID: &str = "0x534e5f4d41494e";
+
+    /// Blocks of a consistent chain, served by block number and block hash.
+    /// An alias serves the block at the given index for an unknown hash.
+    /// Events served for a filter by address can be tampered with, as can
+    /// the chunks they are served in, and so can state updates, which have
+    /// an empty state diff.
+    struct Chain {
+        blocks: Vec<Value>,
+        events: Vec<Value>,
+        alias: Option<(String, usize)>,
+        receipt_block: Option<usize>,
+        tamper_events: Option<fn(&mut Vec<Value>)>,
+        tamper_events_chunk: Option<fn(&mut Value)>,
+        tamper_state_update: Option<fn(&mut Value)>,
+        tamper_gateway_block: Option<fn(&mut Value)>,
+        tamper_receipt: Option<fn(&mut Value)>,
+    }
+
+    impl Chain {
+        fn new(len: u64) -> Self {
+            Self::with_events(len, |_| json!([]))
+        }
+
+        /// Every block has a single transaction emitting the given events.
+        fn with_events(len: u64, events: impl Fn(u64) -> Value) -> Self {
+            Self::build(len, "0.13.1", events, deploy)
+        }
+
+        /// Blocks of version 0.13.2, whose hashes commit to the block data
+        /// served by the feeder gateway.
+        fn with_block_data(len: u64) -> Self {
+            Self::build(len, "0.13.2", |_| json!([]), deploy)
+        }
+
+        /// The transaction of the block with the given number is the given
+        /// L1 handler transaction.
+        fn with_l1_handler(len: u64, number: u64, transaction: Value) -> Self {
+            Self::build(
+                len,
+                "0.13.1",
+                |_| json!([]),
+                |n| if n == number { transaction.clone() } else { deploy(n) },
+            )
+        }
+
+        fn build(
+            len: u64,
+            version: &str,
+            events: impl Fn(u64) -> Value,
+            transaction: impl Fn(u64) -> Value,
+        ) -> Self {
+            let chain_id = Felt::try_new(CHAIN_ID).unwrap();
+            let mut blocks: Vec<Value> = Vec::new();
+            let events: Vec<Value> = (0..len).map(events).collect();
+            for number in 0..len {
+                let parent_hash = blocks
+                    .last()
+                    .map(|block| block["block_hash"].clone())
+                    .unwrap_or(json!("0x0"));
+                let mut transaction = transaction(number);
+                let txn = serde_json::from_value(transaction.clone()).unwrap();
+                transaction["transaction_hash"] = json!(
+                    crate::transaction::transaction_hash(&txn, &chain_id)
+                        .unwrap()
+                );
+                let mut block = json!({
+                    "status": "ACCEPTED_ON_L1",
+                    "block_hash": "0x0",
+                    "block_number": number,
+                    "l1_gas_price": {
+                        "price_in_fri": "0x2",
+                        "price_in_wei": "0x3"
+                    },
+                    "new_root": format!("0x{:x}", 0x100 + number),
+                    "parent_hash": parent_hash,
+                    "sequencer_address": "0x6",
+                    "starknet_version": version,
+                    "timestamp": 1 + number,
+                    "transactions": [transaction]
+                });
+                let transaction_hash = &transaction["transaction_hash"];
+                let data = crate::block::requires_block_data(
+                    &serde_json::from_value(block.clone()).unwrap(),
+                )
+                .then(|| block_data(transaction_hash));
+                let hash = crate::block::block_hash(
+                    &serde_json::from_value(block.clone()).unwrap(),
+                    &emitted_events(&events[number as usize], transaction_hash),
+                    data.as_ref(),
+                )
+                .unwrap();
+                block["block_hash"] = json!(hash);
+                blocks.push(block);
+            }
+            Self {
+                blocks,
+                events,
+                alias: None,
+                receipt_block: None,
+                tamper_events: None,
+                tamper_events_chunk: None,
+                tamper_state_update: None,
+                tamper_gateway_block: None,
+                tamper_receipt: None,
+            }
+        }
+
+        fn hash(&self, number: usize) -> &str {
+            self.blocks[number]["block_hash"].as_str().unwrap()
+        }
+
+        fn root(&self, number: usize) -> &str {
+            self.blocks[number]["new_root"].as_str().unwrap()
+        }
+
+        fn transaction_hash(&self, number: usize) -> &str {
+            self.blocks[number]["transactions"][0]["transaction_hash"]
+                .as_str()
+                .unwrap()
+        }
+
+        fn state(&self, number: usize) -> ClientState {
+            ClientState {
+                block_number: number as u64,
+                block_hash: Felt::try_new(self.hash(number)).unwrap(),
+                root: Felt::try_new(self.root(number)).unwrap(),
+            }
+        }
+
+        fn find(&self, block_id: &Value) -> Option<&Value> {
+            if let Some(number) = block_id["block_number"].as_u64() {
+                return self.blocks.get(number as usize);
+            }
+            let hash = block_id["block_hash"].as_str()?;
+            match &self.alias {
+                Some((alias, index)) if alias == hash => {
+                    self.blocks.get(*index)
+                }
+                _ => {
+                    self.blocks.iter().find(|block| block["block_hash"] == hash)
+                }
+            }
+        }
+
+        fn emitted_events(&self, number: usize) -> Vec<Value> {
+            let block = &self.blocks[number];
+            self.events[number]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|event| {
+                    let mut event = event.clone();
+                    event["block_hash"] = block["block_hash"].clone();
+                    event["block_number"] = block["block_number"].clone();
+                    event["transaction_hash"] =
+                        block["transactions"][0]["transaction_hash"].clone();
+                    event
+                })
+                .collect()
+        }
+
+        /// Serve the events as a chunk, the continuation token being the
+        /// offset of the next event.
+        fn events_chunk(&self, filter: &Value) -> Option<Value> {
+            let number = |block_id: &Value| {
+                self.find(block_id).map(|block| {
+                    block["block_number"].as_u64().unwrap() as usize
+                })
+            };
+            let from_block = number(&filter["from_block"])?;
+            let to_block = number(&filter["to_block"])?;
+            let address = &filter["address"];
+            let mut events: Vec<Value> = (from_block..=to_block)
+                .flat_map(|number| self.emitted_events(number))
+                .filter(|event| {
+                    address.is_null() || event["from_address"] == *address
+                })
+                .collect();
+            if let (false, Some(tamper)) =
+                (address.is_null(), self.tamper_events)
+            {
+                tamper(&mut events);
+            }
+            let offset = filter["continuation_token"]
+                .as_str()
+                .map(|token| token.parse().unwrap())
+                .unwrap_or(0);
+            let end = events
+                .len()
+                .min(offset + filter["chunk_size"].as_u64().unwrap() as usize);
+            let mut chunk = json!({ "events": events[offset..end] });
+            if end < events.len() {
+                chunk["continuation_token"] = json!(end.to_string());
+            }
+            if let Some(tamper) = self.tamper_events_chunk {
+                tamper(&mut chunk);
+            }
+            Some(chunk)
+        }
+
+        fn state_update(&self, block_id: &Value) -> Option<Value> {
+            let block = self.find(block_id)?;
+            let old_root = match block["block_number"].as_u64().unwrap() {
+                0 => json!("0x0"),
+                number => self.blocks[number as usize - 1]["new_root"].clone(),
+            };
+            let mut state_update = json!({
+                "block_hash": block["block_hash"],
+                "new_root": block["new_root"],
+                "old_root": old_root,
+                "state_diff": {
+                    "declared_classes": [],
+                    "deployed_contracts": [],
+                    "deprecated_declared_classes": [],
+                    "nonces": [],
+                    "replaced_classes": [],
+                    "storage_diffs": []
+                }
+            });
+            if let Some(tamper) = self.tamper_state_update {
+                tamper(&mut state_update);
+            }
+            Some(state_update)
+        }
+
+        fn receipt(&self, transaction_hash: &Value) -> Option<Value> {
+            let index = self.blocks.iter().position(|block| {
+                block["transactions"][0]["transaction_hash"]
+                    == *transaction_hash
+            })?;
+            let events = self.events[index].clone();
+            let block = &self.blocks[self.receipt_block.unwrap_or(index)];
+            let mut receipt = json!({
+                "type": "DEPLOY",
+                "contract_address": "0x1",
+                "transaction_hash": transaction_hash,
+                "actual_fee": {"amount": "0x0", "unit": "WEI"},
+                "block_hash": block["block_hash"],
+                "block_number": block["block_number"],
+                "events": events,
+                "execution_resources": {"steps": 1},
+                "execution_status": "SUCCEEDED",
+                "finality_status": "ACCEPTED_ON_L1",
+                "messages_sent": []
+            });
+            if let Some(tamper) = self.tamper_receipt {
+                tamper(&mut receipt);
+            }
+            Some(receipt)
+        }
+    }
+
+    /// A deploy transaction unique to the block with the given number.
+    fn deploy(number: u64) -> Value {
+        json!({
+            "transaction_hash": "0x0",
+            "type": "DEPLOY",
+            "version": "0x0",
+            "class_hash": "0xa",
+            "contract_address_salt": "0xb",
+            "constructor_calldata": [format!("0x{:x}", number)]
+        })
+    }
+
+    /// Events as emitted by the given transaction.
+    fn emitted_events(
+        events: &Value,
+        transaction_hash: &Value,
+    ) -> Vec<gen::EmittedEvent> {
+        let events = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| {
+                let mut event = event.clone();
+                event["transaction_hash"] = transaction_hash.clone();
+                event
+            })
+            .collect();
+        serde_json::from_value(Value::Array(events)).unwrap()
+    }
+
+    /// Block data of a block with a single transaction and an empty state
+    /// diff, matching [`gateway_block`].
+    fn block_data(transaction_hash: &Value) -> crate::block::BlockData {
+        use crate::block::{BlockData, GasPrice, L1DataAvailabilityMode};
+        use starknet_crypto::FieldElement;
+
+        let transaction_hash = transaction_hash.as_str().unwrap();
+        BlockData {
+            state_diff: serde_json::from_value(json!({
+                "declared_classes": [],
+                "deployed_contracts": [],
+                "deprecated_declared_classes": [],
+                "nonces": [],
+                "replaced_classes": [],
+                "storage_diffs": []
+            }))
+            .unwrap(),
+            l1_da_mode: L1DataAvailabilityMode::Blob,
+            l1_data_gas_price: GasPrice {
+                price_in_wei: FieldElement::from(4u64),
+                price_in_fri: FieldElement::from(5u64),
+            },
+            l2_gas_price: GasPrice::default(),
+            receipts: vec![crate::block::Receipt {
+                transaction_hash: FieldElement::from_hex_be(transaction_hash)
+                    .unwrap(),
+                actual_fee: FieldElement::ZERO,
+                messages_sent: vec![],
+                revert_reason: None,
+                l1_gas: 0,
+                l1_data_gas: 192,
+                l2_gas: 0,
+            }],
+        }
+    }
+
+    /// The block as served by the feeder gateway.
+    fn gateway_block(number: u64, transaction_hash: &Value) -> Value {
+        json!({
+            "block_number": number,
+            "l1_da_mode": "BLOB",
+            "l1_data_gas_price": {
+                "price_in_wei": "0x4",
+                "price_in_fri": "0x5"
+            },
+            "transaction_receipts": [{
+                "transaction_index": 0,
+                "transaction_hash": transaction_hash,
+                "actual_fee": "0x0",
+                "l2_to_l1_messages": [],
+                "execution_status": "SUCCEEDED",
+                "execution_resources": {
+                    "total_gas_consumed": {
+                        "l1_gas": 0,
+                        "l1_data_gas": 192
+                    }
+                }
+            }]
+        })
+    }
+
+    impl Respond for Chain {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            if request.url.path() == "/get_block" {
+                let number = request
+                    .url
+                    .query_pairs()
+                    .find(|(key, _)| key == "blockNumber")
+                    .map(|(_, value)| value.parse::<u64>().unwrap())
+                    .unwrap();
+                let block = &self.blocks[number as usize];
+                let mut body = gateway_block(
+                    number,
+                    &block["transactions"][0]["transaction_hash"],
+                );
+                if let Some(tamper) = self.tamper_gateway_block {
+                    tamper(&mut body);
+                }
+                return ResponseTemplate::new(200).set_body_json(body);
+            }
+            let request: Value = serde_json::from_slice(&request.body).unwrap();
+            let params = &request["params"];
+            let method = request["method"].as_str().unwrap();
+            let result = match method {
+                "starknet_getBlockWithTxs" => self.find(&params[0]).cloned(),
+                "starknet_getBlockWithTxHashes" => {
+                    self.find(&params[0]).map(|block| {
+                        let mut block = block.clone();
+                        block["transactions"] = block["transactions"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|tx| tx["transaction_hash"].clone())
+                            .collect();
+                        block
+                    })
+                }
+                "starknet_getEvents" => self.events_chunk(&params[0]),
+                "starknet_getTransactionReceipt" => self.receipt(&params[0]),
+                "starknet_getStateUpdate" => self.state_update(&params[0]),
+                "starknet_chainId" => Some(json!(CHAIN_ID)),
+                _ => None,
+            };
+            let body = match result {
+                Some(result) => {
+                    json!({"jsonrpc": "2.0", "id": 1, "result": result})
+                }
+                None if method == "starknet_getTransactionReceipt" => json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {
+                        "code": 29,
+                        "message": "Transaction hash not found"
+                    }
+                }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {"code": 24, "message": "Block not found"}
+                }),
+            };
+            ResponseTemplate::new(200).set_body_json(body)
+        }
+    }
+
+    async fn setup_test_env(
+        chain: Chain,
+        helios_block_num: usize,
+    ) -> (MockServer, Context) {
+        let state = chain.state(helios_block_num);
+        let starknet_server = MockServer::start().await;
+        Mock::given(any()).respond_with(chain).mount(&starknet_server).await;
+        let context = Context {
+            url: "127.0.0.1:3030".to_string(),
+            client: Arc::new(Client::new(&starknet_server.uri())),
+            state: Arc::new(RwLock::new(state)),
+            settled: Default::default(),
+            ethereum: None,
+            gateway: None,
+            blocks: Default::default(),
+            chain: ExecutionChain {
+                chain_id: Felt::try_new(CHAIN_ID).unwrap(),
+                eth_fee_token_address: Felt::try_new("0xe7").unwrap(),
+                strk_fee_token_address: Felt::try_new("0x57").unwrap(),
+            },
+            verify_events: true,
+            eth_methods: true,
+        };
+        (starknet_server, context)
+    }
+
+    async fn received_requests(server: &MockServer) -> usize {
+        server.received_requests().await.unwrap().len()
+    }
+
+    fn block_from_hash(block_hash: &str) -> BlockId {
+        BlockId::BlockHash {
+            block_hash: BlockHash(Felt::try_new(block_hash).unwrap()),
+        }
+    }
+
+    fn block_from_number(block_number: u64) -> BlockId {
+        BlockId::BlockNumber {
+            block_number: BlockNumber::try_new(block_number as i64).unwrap(),
+        }
+    }
+
+    fn block_from_tag(tag: &str) -> BlockId {
+        match tag {
+            "pending" => BlockId::BlockTag(BlockTag::Pending),
+            _ => BlockId::BlockTag(BlockTag::Latest),
+        }
+    }
+
+    fn eq(lhs: &BlockId, rhs: &BlockId) -> bool {
+        match (lhs, rhs) {
+            (
+                BlockId::BlockNumber { block_number: lhs },
+                BlockId::BlockNumber { block_number: rhs },
+            ) => lhs.as_ref() == rhs.as_ref(),
+            (
+                BlockId::BlockHash { block_hash: lhs },
+                BlockId::BlockHash { block_hash: rhs },
+            ) => lhs.0.as_ref() == rhs.0.as_ref(),
+            (
+                BlockId::BlockTag(BlockTag::Latest),
+                BlockId::BlockTag(BlockTag::Latest),
+            ) => true,
+            (
+                BlockId::BlockTag(BlockTag::Pending),
+                BlockId::BlockTag(BlockTag::Pending),
+            ) => true,
+            _ => false,
+        }
+    }
+
+    async fn resolve_block_id_test(
+        chain: Chain,
+        helios_block_num: usize,
+        block: BlockId,
+    ) -> (Result<(BlockId, Felt), jsonrpc::Error>, usize) {
+        let (server, context) = setup_test_env(chain, helios_block_num).await;
+        let result = context.resolve_block_id(block).await;
+        (result, received_requests(&server).await)
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_with_block_data_success() {
+        let chain = Chain::with_block_data(28);
+        let expected_root = chain.root(25).to_owned();
+        let (server, mut context) = setup_test_env(chain, 27).await;
+        context.gateway =
+            Some(FeederGateway::new(&server.uri(), reqwest::Client::new()));
+
+        let result = context.resolve_block_id(block_from_number(25)).await;
+
+        let (_, root) = result.unwrap();
+        assert_eq!(root.as_ref(), &expected_root);
+        // blocks 27 down to 25, each fetched with its events, state update
+        // and gateway block
+        assert_eq!(received_requests(&server).await, 4 * 3);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_without_feeder_gateway_error() {
+        let chain = Chain::with_block_data(28);
+
+        let (result, _) =
+            resolve_block_id_test(chain, 27, block_from_number(25)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("without a feeder gateway"));
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_tampered_gateway_block_error() {
+        let mut chain = Chain::with_block_data(28);
+        chain.tamper_gateway_block = Some(|block| {
+            block["transaction_receipts"][0]["execution_resources"]
+                ["total_gas_consumed"]["l1_data_gas"] = json!(128);
+        });
+        let (server, mut context) = setup_test_env(chain, 27).await;
+        context.gateway =
+            Some(FeederGateway::new(&server.uri(), reqwest::Client::new()));
+
+        let result = context.resolve_block_id(block_from_number(25)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("block 27 hash mismatch"));
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_request_lower_success() {
+        let chain = Chain::new(28);
+        let expected_root = chain.root(3).to_owned();
+
+        let (result, requests) =
+            resolve_block_id_test(chain, 27, block_from_number(3)).await;
+
+        let (returned_block, root) = result.unwrap();
+        assert!(eq(&block_from_number(3), &returned_block));
+        assert_eq!(root.as_ref(), &expected_root);
+        // blocks 27 down to 3, each fetched with its events
+        assert_eq!(requests, 2 * 25);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_request_higher_success() {
+        let chain = Chain::new(28);
+        let expected_root = chain.root(27).to_owned();
+
+        let (result, requests) =
+            resolve_block_id_test(chain, 27, block_from_number(42)).await;
+
+        let (returned_block, root) = result.unwrap();
+        assert!(eq(&block_from_number(27), &returned_block));
+        assert_eq!(root.as_ref(), &expected_root);
+        assert_eq!(requests, 0);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_same_success() {
+        let (result, requests) =
+            resolve_block_id_test(Chain::new(28), 27, block_from_number(27))
+                .await;
+
+        let (returned_block, _) = result.unwrap();
+        assert!(eq(&block_from_number(27), &returned_block));
+        assert_eq!(requests, 0);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_wrong_number_return() {
+        let mut chain = Chain::new(28);
+        chain.blocks[5]["block_number"] = json!(999);
+
+        let (result, _) =
+            resolve_block_id_test(chain, 27, block_from_number(5)).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_tampered_block_error() {
+        let mut chain = Chain::new(28);
+        chain.blocks[20]["new_root"] = json!("0x42");
+
+        let (result, requests) =
+            resolve_block_id_test(chain, 27, block_from_number(3)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("block 20 hash mismatch"));
+        // the walk stops at the first block that does not verify
+        assert_eq!(requests, 2 * 8);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_missing_event_error() {
+        let mut chain = Chain::new(28);
+        // The provider claims block 26 emitted no events, but the committed
+        // event count is one.
+        let mut block: gen::BlockWithTxs =
+            serde_json::from_value(chain.blocks[26].clone()).unwrap();
+        let events: Vec<gen::EmittedEvent> = serde_json::from_value(json!([{
+            "from_address": "0x1",
+            "keys": [],
+            "data": [],
+            "transaction_hash": "0x1"
+        }]))
+        .unwrap();
+        block.block_header.block_hash.0 =
+            crate::block::block_hash(&block, &events, None).unwrap();
+        chain.blocks[26] = serde_json::to_value(&block).unwrap();
+        chain.blocks[27]["parent_hash"] =
+            serde_json::to_value(&block.block_header.block_hash).unwrap();
+        let block27 = serde_json::from_value(chain.blocks[27].clone()).unwrap();
+        chain.blocks[27]["block_hash"] =
+            json!(crate::block::block_hash(&block27, &[], None).unwrap());
+
+        let (result, _) =
+            resolve_block_id_test(chain, 27, block_from_number(25)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("block 26 hash mismatch"));
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_reuses_verified_blocks() {
+        let (server, context) = setup_test_env(Chain::new(28), 27).await;
+
+        context.resolve_block_id(block_from_number(3)).await.unwrap();
+        server.reset().await;
+        Mock::given(any()).respond_with(Chain::new(28)).mount(&server).await;
+
+        context.resolve_block_id(block_from_number(10)).await.unwrap();
+        assert_eq!(received_requests(&server).await, 0);
+
+        context.resolve_block_id(block_from_number(2)).await.unwrap();
+        assert_eq!(received_requests(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_too_far_error() {
+        let chain = Chain::new(1);
+        let state = chain.state(0);
+        let (server, context) = setup_test_env(chain, 0).await;
+        *context.state.write().await =
+            ClientState { block_number: MAX_BLOCK_WALK + 3, ..state };
+
+        let result = context.resolve_block_id(block_from_number(3)).await;
+
+        assert!(result.is_err());
+        assert_eq!(received_requests(&server).await, 0);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_from_settled_block() {
+        let chain = Chain::new(28);
+        let expected_root = chain.root(3).to_owned();
+        let settled = chain.state(5);
+        let (server, context) = setup_test_env(chain, 27).await;
+        context.settled.write().await.insert(5, settled);
+
+        let result = context.resolve_block_id(block_from_number(3)).await;
+
+        let (_, root) = result.unwrap();
+        assert_eq!(root.as_ref(), &expected_root);
+        // blocks 5 down to 3 instead of 27 down to 3
+        assert_eq!(received_requests(&server).await, 2 * 3);
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_tampered_settled_block_error() {
+        let mut chain = Chain::new(28);
+        chain.blocks[5]["new_root"] = json!("0x42");
+        let settled = Chain::new(28).state(5);
+        let (_server, context) = setup_test_env(chain, 27).await;
+        context.settled.write().await.insert(5, settled);
+
+        let result = context.resolve_block_id(block_from_number(4)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("block 5 hash mismatch"));
+    }
+
+    #[tokio::test]
+    async fn resolve_block_by_number_settled_block_not_rehashed() {
+        let chain = Chain::new(28);
+        let settled = ClientState {
+            root: Felt::try_new("0x1234").unwrap(),
+            ..chain.state(5)
+        };
+        let (server, context) = setup_test_env(chain, 27).await;
+        context.settled.write().await.insert(5, settled);
+
+        let result = context.resolve_block_id(block_from_number(5)).await;
+
+        let (_, root) = result.unwrap();
+        assert_eq!(root.as_ref(), "0x1234");
+        assert_eq!(received_requests(&server).await, 0);
+    }
+
+    fn extension_request(method: &str, params: Value) -> jsonrpc::Request {
+        serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn message_to_l2_status_without_ethereum_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let message_hash = format!("0x{}", "ab".repeat(32));
+        let req = extension_request(
+            "beerus_getMessageToL2Status",
+            json!({ "message_hash": message_hash }),
+        );
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.message, "Ethereum client is not available");
+    }
+
+    #[tokio::test]
+    async fn message_to_l2_status_invalid_params_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let req =
+            extension_request("beerus_getMessagesToL2Status", json!(["0x1"]));
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.code, -32602);
+    }
+
+    const L1_SENDER: &str = "0xae0ee0a63a2ce6baeeffe56e7714fb4efe48d419";
+
+    fn msg_from_l1() -> (gen::MsgFromL1, Felt) {
+        let message = serde_json::from_value(json!({
+            "from_address": L1_SENDER,
+            "to_address": "0x7331",
+            "entry_point_selector": "0x2d75",
+            "payload": ["0x1", "0x2"]
+        }))
+        .unwrap();
+        (message, Felt::try_new("0x4d2").unwrap())
+    }
+
+    /// The L1 handler transaction consuming [`msg_from_l1`].
+    fn l1_handler() -> Value {
+        json!({
+            "type": "L1_HANDLER",
+            "version": "0x0",
+            "nonce": "0x4d2",
+            "contract_address": "0x7331",
+            "entry_point_selector": "0x2d75",
+            "calldata": [L1_SENDER, "0x1", "0x2"]
+        })
+    }
+
+    #[tokio::test]
+    async fn consuming_transaction_verified() {
+        let chain = Chain::with_l1_handler(4, 2, l1_handler());
+        let transaction_hash = chain.transaction_hash(2).to_owned();
+        let (_server, context) = setup_test_env(chain, 3).await;
+        let (message, nonce) = msg_from_l1();
+
+        let result = context.consuming_transaction(&message, &nonce).await;
+
+        assert_eq!(result.unwrap().unwrap().0.as_ref(), &transaction_hash);
+    }
+
+    #[tokio::test]
+    async fn consuming_transaction_not_found_none() {
+        let (_server, context) = setup_test_env(Chain::new(4), 3).await;
+        let (message, nonce) = msg_from_l1();
+
+        let result = context.consuming_transaction(&message, &nonce).await;
+
+        assert!(result.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn consuming_transaction_reverted_none() {
+        let mut chain = Chain::with_l1_handler(4, 2, l1_handler());
+        chain.tamper_receipt = Some(|receipt| {
+            receipt["execution_status"] = json!("REVERTED");
+        });
+        let (_server, context) = setup_test_env(chain, 3).await;
+        let (message, nonce) = msg_from_l1();
+
+        let result = context.consuming_transaction(&message, &nonce).await;
+
+        assert!(result.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn consuming_transaction_wrong_block_error() {
+        let mut chain = Chain::with_l1_handler(4, 2, l1_handler());
+        chain.receipt_block = Some(1);
+        let (_server, context) = setup_test_env(chain, 3).await;
+        let (message, nonce) = msg_from_l1();
+
+        let result = context.consuming_transaction(&message, &nonce).await;
 
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn message_to_l1_count_without_ethereum_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let req = extension_request(
+            "beerus_getMessageToL1Count",
+            json!({ "message": {
+                "from_address": "0x1",
+                "to_address": "0x2",
+                "payload": ["0x3", "0x4"]
+            }}),
+        );
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.message, "Ethereum client is not available");
+    }
+
+    #[tokio::test]
+    async fn message_to_l1_count_invalid_params_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let req = extension_request(
+            "beerus_getMessageToL1Count",
+            json!([{ "from_address": "0x1" }]),
+        );
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.code, -32602);
+    }
+
+    #[tokio::test]
+    async fn eth_block_number_without_ethereum_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let req = extension_request("beerus_ethBlockNumber", json!([]));
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.message, "Ethereum client is not available");
+    }
+
+    #[tokio::test]
+    async fn eth_methods_disabled_error() {
+        let (_server, mut context) = setup_test_env(Chain::new(1), 0).await;
+        context.eth_methods = false;
+        let req = extension_request("beerus_ethBlockNumber", json!([]));
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.code, -32601);
+    }
+
+    #[tokio::test]
+    async fn eth_get_balance_unknown_named_param_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let address = format!("0x{}", "ab".repeat(20));
+        let req = extension_request(
+            "beerus_ethGetBalance",
+            json!({ "account": address, "block": "latest" }),
+        );
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.code, -32602);
+    }
+
+    #[tokio::test]
+    async fn eth_get_balance_invalid_params_error() {
+        let (_server, context) = setup_test_env(Chain::new(1), 0).await;
+        let address = format!("0x{}", "ab".repeat(20));
+        let req = extension_request(
+            "beerus_ethGetBalance",
+            json!([address, "not a block"]),
+        );
+
+        let error = dispatch(&context, &req).await.error.unwrap();
+
+        assert_eq!(error.code, -32602);
+    }
+
     #[tokio::test]
     async fn resolve_block_by_hash_different_success() {
-        let requested_starknet_block_hash = "0x3";
-        let helios_state_block_num = 27;
-        let starknet_response_block_hash = requested_starknet_block_hash;
-        let starknet_response_block_num = 3;
-        let expected_num_request = 1;
-
-        let result = resolve_block_by_hash_test(
-            requested_starknet_block_hash,
-            helios_state_block_num,
-            starknet_response_block_hash,
-            starknet_response_block_num,
-            expected_num_request,
-        )
-        .await;
+        let chain = Chain::new(28);
+        let requested_hash = chain.hash(3).to_owned();
+        let expected_root = chain.root(3).to_owned();
 
-        assert!(result.is_ok());
-        let (returned_block, _) = result.unwrap();
-        assert!(eq(
-            &block_from_hash(requested_starknet_block_hash),
-            &returned_block
-        ));
+        let (result, _) =
+            resolve_block_id_test(chain, 27, block_from_hash(&requested_hash))
+                .await;
+
+        let (returned_block, root) = result.unwrap();
+        assert!(eq(&block_from_hash(&requested_hash), &returned_block));
+        assert_eq!(root.as_ref(), &expected_root);
     }
 
     #[tokio::test]
     async fn resolve_block_by_hash_same_success() {
-        let requested_starknet_block_hash = "0x27";
-        let helios_state_block_num = 27;
-        let starknet_response_block_hash = requested_starknet_block_hash;
-        let starknet_response_block_num = 27;
-        let expected_num_request = 0;
-
-        let result = resolve_block_by_hash_test(
-            requested_starknet_block_hash,
-            helios_state_block_num,
-            starknet_response_block_hash,
-            starknet_response_block_num,
-            expected_num_request,
-        )
-        .await;
+        let chain = Chain::new(28);
+        let requested_hash = chain.hash(27).to_owned();
+
+        let (result, requests) =
+            resolve_block_id_test(chain, 27, block_from_hash(&requested_hash))
+                .await;
 
-        assert!(result.is_ok());
         let (returned_block, _) = result.unwrap();
-        assert!(eq(
-            &block_from_hash(requested_starknet_block_hash),
-            &returned_block
-        ));
+        assert!(eq(&block_from_hash(&requested_hash), &returned_block));
+        assert_eq!(requests, 0);
     }
 
     #[tokio::test]
     async fn resolve_block_by_hash_wrong_number_return_error() {
-        let requested_starknet_block_hash = "0x99";
-        let helios_state_block_num = 27;
-        let starknet_response_block_hash = requested_starknet_block_hash;
-        let starknet_response_block_num = 27;
-        let expected_num_request = 1;
-
-        let result = resolve_block_by_hash_test(
-            requested_starknet_block_hash,
-            helios_state_block_num,
-            starknet_response_block_hash,
-            starknet_response_block_num,
-            expected_num_request,
-        )
-        .await;
+        let mut chain = Chain::new(28);
+        chain.alias = Some(("0x99".to_owned(), 27));
+
+        let (result, requests) =
+            resolve_block_id_test(chain, 27, block_from_hash("0x99")).await;
 
         assert!(result.is_err());
+        assert_eq!(requests, 1);
     }
 
     #[tokio::test]
     async fn resolve_block_by_hash_wrong_hash_return_error() {
-        let requested_starknet_block_hash = "0x99";
-        let helios_state_block_num = 27;
-        let starknet_response_block_hash = "0xbad";
-        let starknet_response_block_num = 3;
-        let expected_num_request = 1;
-
-        let result = resolve_block_by_hash_test(
-            requested_starknet_block_hash,
-            helios_state_block_num,
-            starknet_response_block_hash,
-            starknet_response_block_num,
-            expected_num_request,
-        )
-        .await;
+        let mut chain = Chain::new(28);
+        chain.alias = Some(("0x99".to_owned(), 3));
+
+        let (result, _) =
+            resolve_block_id_test(chain, 27, block_from_hash("0x99")).await;
 
         assert!(result.is_err());
     }
@@ -903,19 +3247,10 @@ mod tests {
     #[tokio::test]
     async fn resolve_block_id_number_success() {
         let request_block = block_from_number(3);
-        let helios_block_num = 27;
-        let starknet_response_block_num = 3;
-        let starknet_response_block_hash = "0x3";
-        let expected_num_request = 1;
-
-        let result = resolve_block_id_test(
-            request_block.clone(),
-            helios_block_num,
-            starknet_response_block_num,
-            starknet_response_block_hash,
-            expected_num_request,
-        )
-        .await;
+
+        let (result, _) =
+            resolve_block_id_test(Chain::new(28), 27, request_block.clone())
+                .await;
 
         assert!(result.is_ok());
         let (returned_block, _) = result.unwrap();
@@ -924,20 +3259,11 @@ mod tests {
 
     #[tokio::test]
     async fn resolve_block_id_hash_success() {
-        let request_block = block_from_hash("0x3");
-        let helios_block_num = 27;
-        let starknet_response_block_num = 3;
-        let starknet_response_block_hash = "0x3";
-        let expected_num_request = 1;
-
-        let result = resolve_block_id_test(
-            request_block.clone(),
-            helios_block_num,
-            starknet_response_block_num,
-            starknet_response_block_hash,
-            expected_num_request,
-        )
-        .await;
+        let chain = Chain::new(28);
+        let request_block = block_from_hash(chain.hash(3));
+
+        let (result, _) =
+            resolve_block_id_test(chain, 27, request_block.clone()).await;
 
         assert!(result.is_ok());
         let (returned_block, _) = result.unwrap();
@@ -946,43 +3272,589 @@ mod tests {
 
     #[tokio::test]
     async fn resolve_block_id_tag_latest_success() {
-        let request_block = block_from_tag("latest");
-        let helios_block_num = 27;
-        let starknet_response_block_num = 33;
-        let starknet_response_block_hash = "0x33";
-        let expected_num_request = 0;
-
-        let result = resolve_block_id_test(
-            request_block.clone(),
-            helios_block_num,
-            starknet_response_block_num,
-            starknet_response_block_hash,
-            expected_num_request,
-        )
-        .await;
+        let (result, requests) =
+            resolve_block_id_test(Chain::new(28), 27, block_from_tag("latest"))
+                .await;
 
-        assert!(result.is_ok());
         let (returned_block, _) = result.unwrap();
-        assert!(eq(&block_from_number(helios_block_num), &returned_block));
+        assert!(eq(&block_from_number(27), &returned_block));
+        assert_eq!(requests, 0);
     }
 
     #[tokio::test]
     async fn resolve_block_id_tag_pending_error() {
-        let request_block = block_from_tag("pending");
-        let helios_block_num = 27;
-        let starknet_response_block_num = 33;
-        let starknet_response_block_hash = "0x33";
-        let expected_num_request = 0;
-
-        let result = resolve_block_id_test(
-            request_block.clone(),
-            helios_block_num,
-            starknet_response_block_num,
-            starknet_response_block_hash,
-            expected_num_request,
+        let (result, requests) = resolve_block_id_test(
+            Chain::new(28),
+            27,
+            block_from_tag("pending"),
         )
         .await;
 
         assert!(result.is_err());
+        assert_eq!(requests, 0);
+    }
+
+    #[tokio::test]
+    async fn get_block_with_tx_hashes_verified() {
+        let chain = Chain::new(28);
+        let requested_hash = chain.hash(3).to_owned();
+        let transaction_hash = chain.transaction_hash(3).to_owned();
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context
+            .getBlockWithTxHashes(block_from_hash(&requested_hash))
+            .await
+            .unwrap();
+
+        let gen::GetBlockWithTxHashesResult::BlockWithTxHashes(block) = result
+        else {
+            panic!("pending block returned");
+        };
+        assert_eq!(block.block_header.block_hash.0.as_ref(), &requested_hash);
+        let transactions = block.block_body_with_tx_hashes.transactions;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].0.as_ref(), &transaction_hash);
+    }
+
+    #[tokio::test]
+    async fn get_block_with_tx_hashes_latest_verified_against_l1() {
+        let mut chain = Chain::new(28);
+        chain.blocks[27]["transactions"][0]["transaction_hash"] = json!("0x1");
+
+        let (_server, context) = setup_test_env(chain, 27).await;
+        let result =
+            context.getBlockWithTxHashes(block_from_tag("latest")).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn call_header_latest_takes_root_from_state() {
+        let (server, context) = setup_test_env(Chain::new(28), 27).await;
+        let root = Felt::try_new("0x1234").unwrap();
+        context.state.write().await.root = root.clone();
+
+        let header =
+            context.get_call_header(block_from_tag("latest")).await.unwrap();
+
+        assert_eq!(*header.block_number.as_ref(), 27);
+        assert_eq!(header.new_root.as_ref(), root.as_ref());
+        // only the header, the block is not verified against its hash
+        assert_eq!(received_requests(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn call_header_latest_with_wrong_hash_error() {
+        let (_server, context) = setup_test_env(Chain::new(28), 27).await;
+        context.state.write().await.block_hash = Felt::try_new("0x1").unwrap();
+
+        let result = context.get_call_header(block_from_number(27)).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn chain_id_is_configured_one() {
+        let (server, context) = setup_test_env(Chain::new(28), 27).await;
+
+        let result = context.chainId().await.unwrap();
+
+        assert_eq!(result.as_ref(), CHAIN_ID);
+        assert_eq!(received_requests(&server).await, 0);
+    }
+
+    #[tokio::test]
+    async fn get_transaction_by_hash_verified() {
+        let chain = Chain::new(28);
+        let transaction_hash = chain.transaction_hash(3).to_owned();
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context
+            .getTransactionByHash(TxnHash(
+                Felt::try_new(&transaction_hash).unwrap(),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(result.transaction_hash.0.as_ref(), &transaction_hash);
+        assert!(matches!(result.txn, Txn::DeployTxn(_)));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_by_hash_tampered_body_error() {
+        let mut chain = Chain::new(28);
+        let transaction_hash = chain.transaction_hash(3).to_owned();
+        // The block still verifies, as it only commits to the hash.
+        chain.blocks[3]["transactions"][0]["constructor_calldata"] =
+            json!(["0x42"]);
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context
+            .getTransactionByHash(TxnHash(
+                Felt::try_new(&transaction_hash).unwrap(),
+            ))
+            .await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("transaction hash mismatch"));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_by_hash_legacy_block_error() {
+        // The first mainnet blocks carry no version, and their transactions
+        // are hashed with legacy schemes, like this deploy from block 1470.
+        let legacy = json!({
+            "transaction_hash": "0x0",
+            "type": "DEPLOY",
+            "version": "0x0",
+            "class_hash": "0x71c3c99f5cf76fc19945d4b8b7d34c7c5528f22730d56192b50c6bbfd338a64",
+            "constructor_calldata": [
+                "0x5f28c66afd8a6799ddbe1933bce2c144625031aafa881fa38fa830790eff204"
+            ],
+            "contract_address_salt": "0x1cb8f5514ceac8a6abdf232cd24ea9eacfb310eaba427432784b80b5f95bc8d"
+        });
+        let chain = Chain::build(
+            28,
+            "",
+            |_| json!([]),
+            |n| if n == 3 { legacy.clone() } else { deploy(n) },
+        );
+        let transaction_hash = chain.transaction_hash(3).to_owned();
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context
+            .getTransactionByHash(TxnHash(
+                Felt::try_new(&transaction_hash).unwrap(),
+            ))
+            .await;
+
+        // The block hash is verified, the transaction hash is not.
+        let error = result.unwrap_err();
+        assert_eq!(error.code, -32701);
+        assert!(error.message.contains("transaction hash"));
+        assert!(error.message.contains("starknet version ''"));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_receipt_verified() {
+        let chain = Chain::new(28);
+        let transaction_hash = chain.transaction_hash(3).to_owned();
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context
+            .getTransactionReceipt(TxnHash(
+                Felt::try_new(&transaction_hash).unwrap(),
+            ))
+            .await;
+
+        assert!(matches!(
+            result,
+            Ok(gen::GetTransactionReceiptResult::TxnReceipt(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_receipt_with_block_data_verified() {
+        let chain = Chain::with_block_data(28);
+        let transaction_hash = chain.transaction_hash(26).to_owned();
+        let (server, mut context) = setup_test_env(chain, 27).await;
+        context.gateway =
+            Some(FeederGateway::new(&server.uri(), reqwest::Client::new()));
+
+        let result = context
+            .getTransactionReceipt(TxnHash(
+                Felt::try_new(&transaction_hash).unwrap(),
+            ))
+            .await;
+
+        assert!(matches!(
+            result,
+            Ok(gen::GetTransactionReceiptResult::TxnReceipt(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_receipt_with_block_data_tampered_error() {
+        let tampered: [fn(&mut Value); 4] = [
+            |receipt| receipt["actual_fee"]["amount"] = json!("0x1"),
+            |receipt| {
+                receipt["execution_status"] = json!("REVERTED");
+                receipt["revert_reason"] = json!("out of gas");
+            },
+            |receipt| {
+                receipt["messages_sent"] = json!([{
+                    "from_address": "0x1",
+                    "to_address": "0x2",
+                    "payload": []
+                }])
+            },
+            |receipt| {
+                receipt["events"] = json!([{
+                    "from_address": "0x1",
+                    "keys": [],
+                    "data": []
+                }])
+            },
+        ];
+        for tamper in tampered {
+            let mut chain = Chain::with_block_data(28);
+            let transaction_hash = chain.transaction_hash(26).to_owned();
+            chain.tamper_receipt = Some(tamper);
+            let (server, mut context) = setup_test_env(chain, 27).await;
+            context.gateway =
+                Some(FeederGateway::new(&server.uri(), reqwest::Client::new()));
+
+            let result = context
+                .getTransactionReceipt(TxnHash(
+                    Felt::try_new(&transaction_hash).unwrap(),
+                ))
+                .await;
+
+            let error = result.unwrap_err();
+            assert!(error.message.contains("mismatch"), "{}", error.message);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_transaction_receipt_wrong_block_error() {
+        let mut chain = Chain::new(28);
+        let transaction_hash = chain.transaction_hash(3).to_owned();
+        chain.receipt_block = Some(4);
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context
+            .getTransactionReceipt(TxnHash(
+                Felt::try_new(&transaction_hash).unwrap(),
+            ))
+            .await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("not found in block 4"));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_by_block_id_and_index_verified() {
+        let chain = Chain::new(28);
+        let transaction_hash = chain.transaction_hash(27).to_owned();
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let index = GetTransactionByBlockIdAndIndexIndex::try_new(0).unwrap();
+        let result = context
+            .getTransactionByBlockIdAndIndex(block_from_tag("latest"), index)
+            .await
+            .unwrap();
+        assert_eq!(result.transaction_hash.0.as_ref(), &transaction_hash);
+
+        let index = GetTransactionByBlockIdAndIndexIndex::try_new(1).unwrap();
+        let result = context
+            .getTransactionByBlockIdAndIndex(block_from_tag("latest"), index)
+            .await;
+        assert_eq!(result.unwrap_err().code, 27);
+    }
+
+    fn two_events(number: u64) -> Value {
+        json!([
+            {
+                "from_address": "0x1",
+                "keys": ["0x10"],
+                "data": [format!("0x{:x}", number)]
+            },
+            {"from_address": "0x2", "keys": ["0x20"], "data": []}
+        ])
+    }
+
+    fn events_filter(
+        from_block: u64,
+        to_block: u64,
+        chunk_size: i64,
+        continuation_token: Option<&str>,
+    ) -> gen::GetEventsFilter {
+        serde_json::from_value(json!({
+            "from_block": {"block_number": from_block},
+            "to_block": {"block_number": to_block},
+            "address": "0x1",
+            "chunk_size": chunk_size,
+            "continuation_token": continuation_token
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn get_events_verified() {
+        let chain = Chain::with_events(28, two_events);
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getEvents(events_filter(3, 5, 10, None)).await;
+
+        let chunk = result.unwrap();
+        assert_eq!(chunk.events.len(), 3);
+        assert!(chunk.continuation_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_events_paginated_verified() {
+        let chain = Chain::with_events(28, two_events);
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let chunk =
+            context.getEvents(events_filter(3, 5, 2, None)).await.unwrap();
+        assert_eq!(chunk.events.len(), 2);
+        let token = chunk.continuation_token.unwrap();
+
+        let chunk = context
+            .getEvents(events_filter(3, 5, 2, Some(&token)))
+            .await
+            .unwrap();
+        assert_eq!(chunk.events.len(), 1);
+        assert!(chunk.continuation_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_events_dropped_event_error() {
+        let mut chain = Chain::with_events(28, two_events);
+        chain.tamper_events = Some(|events| {
+            events.remove(1);
+        });
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getEvents(events_filter(3, 5, 10, None)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("events do not match"));
+    }
+
+    #[tokio::test]
+    async fn get_events_dropped_at_page_end_error() {
+        // Both events of each block match, the first page ends in block 4.
+        let mut chain = Chain::with_events(28, |number| {
+            json!([
+                {"from_address": "0x1", "keys": ["0x10"], "data": []},
+                {
+                    "from_address": "0x1",
+                    "keys": ["0x11"],
+                    "data": [format!("0x{:x}", number)]
+                }
+            ])
+        });
+        chain.tamper_events = Some(|events| {
+            events.retain(|event| {
+                event["block_number"] != json!(4) || event["keys"][0] != "0x11"
+            });
+        });
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let chunk =
+            context.getEvents(events_filter(3, 5, 3, None)).await.unwrap();
+        assert_eq!(chunk.events.len(), 3);
+        let token = chunk.continuation_token.unwrap();
+
+        let result =
+            context.getEvents(events_filter(3, 5, 3, Some(&token))).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("events do not match"));
+    }
+
+    #[tokio::test]
+    async fn get_events_long_range_verified_in_steps() {
+        let chain = Chain::with_events(300, two_events);
+        let (_server, context) = setup_test_env(chain, 299).await;
+
+        let chunk =
+            context.getEvents(events_filter(3, 290, 1000, None)).await.unwrap();
+        assert_eq!(chunk.events.len(), 256);
+        let token = chunk.continuation_token.unwrap();
+        assert_eq!(token, "259-0");
+
+        let chunk = context
+            .getEvents(events_filter(3, 290, 1000, Some(&token)))
+            .await
+            .unwrap();
+        assert_eq!(chunk.events.len(), 32);
+        assert!(chunk.continuation_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_events_empty_page_not_last_error() {
+        let mut chain = Chain::with_events(28, two_events);
+        chain.tamper_events_chunk = Some(|chunk| {
+            chunk["events"] = json!([]);
+            chunk["continuation_token"] = json!("0");
+        });
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getEvents(events_filter(3, 5, 2, None)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("has no new events"));
+    }
+
+    #[tokio::test]
+    async fn get_events_invalid_token_error() {
+        let chain = Chain::with_events(28, two_events);
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getEvents(events_filter(3, 5, 2, Some("2"))).await;
+
+        assert_eq!(result.unwrap_err().code, 33);
+    }
+
+    #[tokio::test]
+    async fn get_events_fabricated_event_error() {
+        let mut chain = Chain::with_events(28, two_events);
+        chain.tamper_events = Some(|events| {
+            events[0]["data"] = json!(["0x42"]);
+        });
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getEvents(events_filter(3, 5, 10, None)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("events do not match"));
+    }
+
+    #[tokio::test]
+    async fn get_events_after_latest_verified_block_error() {
+        let chain = Chain::with_events(28, two_events);
+        let (_server, context) = setup_test_env(chain, 25).await;
+
+        let result = context.getEvents(events_filter(24, 27, 10, None)).await;
+
+        let error = result.unwrap_err();
+        assert!(error
+            .message
+            .contains("Events of block 26 cannot be verified"));
+    }
+
+    #[tokio::test]
+    async fn get_events_not_verified_when_disabled() {
+        let mut chain = Chain::with_events(28, two_events);
+        chain.tamper_events = Some(|events| {
+            events.remove(1);
+        });
+        let (_server, mut context) = setup_test_env(chain, 27).await;
+        context.verify_events = false;
+
+        let result = context.getEvents(events_filter(3, 5, 10, None)).await;
+
+        assert_eq!(result.unwrap().events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn get_state_update_verified() {
+        let chain = Chain::new(28);
+        let expected_root = chain.root(5).to_owned();
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getStateUpdate(block_from_number(5)).await;
+
+        let gen::GetStateUpdateResult::StateUpdate(state_update) =
+            result.unwrap()
+        else {
+            panic!("pending state update returned");
+        };
+        assert_eq!(state_update.new_root.as_ref(), &expected_root);
+    }
+
+    #[tokio::test]
+    async fn get_state_update_old_root_mismatch_error() {
+        let mut chain = Chain::new(28);
+        chain.tamper_state_update = Some(|state_update| {
+            state_update["old_root"] = json!("0x42");
+        });
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getStateUpdate(block_from_number(5)).await;
+
+        let error = result.unwrap_err();
+        assert!(error.message.contains("old root mismatch"));
+    }
+
+    #[tokio::test]
+    async fn get_state_update_incomplete_state_diff_error() {
+        let mut chain = Chain::new(28);
+        chain.tamper_state_update = Some(|state_update| {
+            state_update["state_diff"]["nonces"] =
+                json!([{"contract_address": "0x1"}]);
+        });
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getStateUpdate(block_from_number(5)).await;
+
+        let error = result.unwrap_err();
+        assert_eq!(error.message, "Incomplete state diff received");
+    }
+
+    #[tokio::test]
+    async fn get_state_update_with_block_data_verified() {
+        let chain = Chain::with_block_data(28);
+        let (_server, context) = setup_test_env(chain, 27).await;
+
+        let result = context.getStateUpdate(block_from_number(5)).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn state_diff_commitment_missing_entry_error() {
+        let verified: gen::StateDiff = serde_json::from_value(json!({
+            "declared_classes": [],
+            "deployed_contracts": [],
+            "deprecated_declared_classes": [],
+            "nonces": [
+                {"contract_address": "0xa", "nonce": "0x3"},
+                {"contract_address": "0xb", "nonce": "0x4"}
+            ],
+            "replaced_classes": [],
+            "storage_diffs": []
+        }))
+        .unwrap();
+        assert!(
+            super::verify_state_diff_commitment(&verified, &verified).is_ok()
+        );
+
+        let mut state_diff = verified.clone();
+        state_diff.nonces.pop();
+        let error = super::verify_state_diff_commitment(&state_diff, &verified)
+            .unwrap_err();
+
+        assert_eq!(error.code, -32700);
+        assert!(error.message.contains("state diff commitment mismatch"));
+    }
+
+    #[test]
+    fn contract_diffs_grouped_by_contract() {
+        let state_diff: gen::StateDiff = serde_json::from_value(json!({
+            "declared_classes": [],
+            "deployed_contracts": [{"address": "0xb", "class_hash": "0x2"}],
+            "deprecated_declared_classes": [],
+            "nonces": [{"contract_address": "0xa", "nonce": "0x3"}],
+            "replaced_classes": [],
+            "storage_diffs": [
+                {
+                    "address": "0xb",
+                    "storage_entries": [{"key": "0x5", "value": "0x6"}]
+                },
+                {
+                    "address": "0xa",
+                    "storage_entries": [{"key": "0x7", "value": "0x8"}]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let diffs = super::contract_diffs(&state_diff).unwrap();
+
+        assert_eq!(diffs.len(), 2);
+        let (address, diff) = &diffs[0];
+        assert_eq!(address.0.as_ref(), "0xa");
+        assert_eq!(diff.nonce.as_ref().unwrap().as_ref(), "0x3");
+        assert_eq!(diff.storage.len(), 1);
+        assert!(!diff.deployed);
+        let (address, diff) = &diffs[1];
+        assert_eq!(address.0.as_ref(), "0xb");
+        assert_eq!(diff.class_hash.as_ref().unwrap().as_ref(), "0x2");
+        assert_eq!(diff.storage[0].1.as_ref(), "0x6");
+        assert!(diff.deployed);
     }
 }
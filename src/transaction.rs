@@ -0,0 +1,631 @@
+use serde::Serialize;
+use starknet::core::utils::{get_contract_address, get_selector_from_name};
+use starknet_crypto::{poseidon_hash_many, FieldElement};
+
+use crate::block::hash_on_elements;
+use crate::gen::{
+    BlockTransaction, CommonReceiptProperties, DaMode, DeclareTxn,
+    DeployAccountTxn, DeployTxn, Felt, FunctionInvocation, InvokeTxn,
+    InvokeTxnTraceExecuteInvocation, L1HandlerTxn, ResourceBoundsMapping,
+    TransactionTrace, Txn, TxnExecutionStatus, U64,
+};
+use crate::proof::{as_felt, as_field_element, ProofError};
+
+/// Check that the transaction body hashes to its transaction hash.
+pub fn verify_transaction_hash(
+    transaction: &BlockTransaction,
+    chain_id: &Felt,
+) -> Result<(), ProofError> {
+    let computed = transaction_hash(&transaction.txn, chain_id)?;
+    let provided = &transaction.transaction_hash.0;
+    if as_field_element(provided)? != as_field_element(&computed)? {
+        return Err(ProofError::ValueMismatch {
+            name: "transaction hash",
+            provided: provided.as_ref().clone(),
+            proven: computed.as_ref().clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Compute the hash of the transaction: pedersen hash on elements of the
+/// transaction fields for versions 0-2, poseidon hash of them for version 3.
+pub fn transaction_hash(
+    txn: &Txn,
+    chain_id: &Felt,
+) -> Result<Felt, ProofError> {
+    let chain_id = as_field_element(chain_id)?;
+    let hash = match txn {
+        Txn::InvokeTxn(InvokeTxn::InvokeTxnV0(tx)) => hash_on_elements(&[
+            prefix(b"invoke"),
+            version(&tx.version)?,
+            as_field_element(&tx.contract_address.0)?,
+            as_field_element(&tx.entry_point_selector)?,
+            hash_on_elements(&felts(&tx.calldata)?),
+            as_field_element(&tx.max_fee)?,
+            chain_id,
+        ]),
+        Txn::InvokeTxn(InvokeTxn::InvokeTxnV1(tx)) => hash_on_elements(&[
+            prefix(b"invoke"),
+            version(&tx.version)?,
+            as_field_element(&tx.sender_address.0)?,
+            FieldElement::ZERO,
+            hash_on_elements(&felts(&tx.calldata)?),
+            as_field_element(&tx.max_fee)?,
+            chain_id,
+            as_field_element(&tx.nonce)?,
+        ]),
+        Txn::InvokeTxn(InvokeTxn::InvokeTxnV3(tx)) => poseidon_hash_many(&[
+            prefix(b"invoke"),
+            version(&tx.version)?,
+            as_field_element(&tx.sender_address.0)?,
+            fee_fields_hash(&tx.tip, &tx.resource_bounds)?,
+            poseidon_hash_many(&felts(&tx.paymaster_data)?),
+            chain_id,
+            as_field_element(&tx.nonce)?,
+            data_availability_modes(
+                &tx.nonce_data_availability_mode,
+                &tx.fee_data_availability_mode,
+            ),
+            poseidon_hash_many(&felts(&tx.account_deployment_data)?),
+            poseidon_hash_many(&felts(&tx.calldata)?),
+        ]),
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV0(tx)) => hash_on_elements(&[
+            prefix(b"declare"),
+            version(&tx.version)?,
+            as_field_element(&tx.sender_address.0)?,
+            FieldElement::ZERO,
+            hash_on_elements(&[]),
+            as_field_element(&tx.max_fee)?,
+            chain_id,
+            as_field_element(&tx.class_hash)?,
+        ]),
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV1(tx)) => hash_on_elements(&[
+            prefix(b"declare"),
+            version(&tx.version)?,
+            as_field_element(&tx.sender_address.0)?,
+            FieldElement::ZERO,
+            hash_on_elements(&[as_field_element(&tx.class_hash)?]),
+            as_field_element(&tx.max_fee)?,
+            chain_id,
+            as_field_element(&tx.nonce)?,
+        ]),
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV2(tx)) => hash_on_elements(&[
+            prefix(b"declare"),
+            version(&tx.version)?,
+            as_field_element(&tx.sender_address.0)?,
+            FieldElement::ZERO,
+            hash_on_elements(&[as_field_element(&tx.class_hash)?]),
+            as_field_element(&tx.max_fee)?,
+            chain_id,
+            as_field_element(&tx.nonce)?,
+            as_field_element(&tx.compiled_class_hash)?,
+        ]),
+        Txn::DeclareTxn(DeclareTxn::DeclareTxnV3(tx)) => poseidon_hash_many(&[
+            prefix(b"declare"),
+            version(&tx.version)?,
+            as_field_element(&tx.sender_address.0)?,
+            fee_fields_hash(&tx.tip, &tx.resource_bounds)?,
+            poseidon_hash_many(&felts(&tx.paymaster_data)?),
+            chain_id,
+            as_field_element(&tx.nonce)?,
+            data_availability_modes(
+                &tx.nonce_data_availability_mode,
+                &tx.fee_data_availability_mode,
+            ),
+            poseidon_hash_many(&felts(&tx.account_deployment_data)?),
+            as_field_element(&tx.class_hash)?,
+            as_field_element(&tx.compiled_class_hash)?,
+        ]),
+        Txn::DeployAccountTxn(DeployAccountTxn::DeployAccountTxnV1(tx)) => {
+            let class_hash = as_field_element(&tx.class_hash)?;
+            let salt = as_field_element(&tx.contract_address_salt)?;
+            let calldata = felts(&tx.constructor_calldata)?;
+            let contract_address =
+                get_contract_address(salt, class_hash, &calldata, 0u64.into());
+            let mut elements = vec![class_hash, salt];
+            elements.extend(calldata);
+            hash_on_elements(&[
+                prefix(b"deploy_account"),
+                version(&tx.version)?,
+                contract_address,
+                FieldElement::ZERO,
+                hash_on_elements(&elements),
+                as_field_element(&tx.max_fee)?,
+                chain_id,
+                as_field_element(&tx.nonce)?,
+            ])
+        }
+        Txn::DeployAccountTxn(DeployAccountTxn::DeployAccountTxnV3(tx)) => {
+            let class_hash = as_field_element(&tx.class_hash)?;
+            let salt = as_field_element(&tx.contract_address_salt)?;
+            let calldata = felts(&tx.constructor_calldata)?;
+            let contract_address =
+                get_contract_address(salt, class_hash, &calldata, 0u64.into());
+            poseidon_hash_many(&[
+                prefix(b"deploy_account"),
+                version(&tx.version)?,
+                contract_address,
+                fee_fields_hash(&tx.tip, &tx.resource_bounds)?,
+                poseidon_hash_many(&felts(&tx.paymaster_data)?),
+                chain_id,
+                as_field_element(&tx.nonce)?,
+                data_availability_modes(
+                    &tx.nonce_data_availability_mode,
+                    &tx.fee_data_availability_mode,
+                ),
+                poseidon_hash_many(&calldata),
+                class_hash,
+                salt,
+            ])
+        }
+        Txn::DeployTxn(tx) => deploy_transaction_hash(tx, chain_id)?,
+        Txn::L1HandlerTxn(tx) => l1_handler_transaction_hash(tx, chain_id)?,
+    };
+    as_felt(hash)
+}
+
+/// Check that the trace of a re-executed transaction agrees with the receipt
+/// of the transaction: same execution status, and the same events and L2->L1
+/// messages emitted (in any order, as they are collected from the calls).
+pub fn verify_trace(
+    trace: &TransactionTrace,
+    receipt: &CommonReceiptProperties,
+) -> Result<(), ProofError> {
+    let mut invocations = Vec::new();
+    let mut reverted = false;
+    match trace {
+        TransactionTrace::InvokeTxnTrace(trace) => {
+            invocations.extend(trace.validate_invocation.iter());
+            match &trace.execute_invocation {
+                InvokeTxnTraceExecuteInvocation::FunctionInvocation(
+                    invocation,
+                ) => invocations.push(invocation),
+                InvokeTxnTraceExecuteInvocation::RevertReason { .. } => {
+                    reverted = true
+                }
+            }
+            invocations.extend(trace.fee_transfer_invocation.iter());
+        }
+        TransactionTrace::DeclareTxnTrace(trace) => {
+            invocations.extend(trace.validate_invocation.iter());
+            invocations.extend(trace.fee_transfer_invocation.iter());
+        }
+        TransactionTrace::DeployAccountTxnTrace(trace) => {
+            invocations.extend(trace.validate_invocation.iter());
+            invocations.push(&trace.constructor_invocation);
+            invocations.extend(trace.fee_transfer_invocation.iter());
+        }
+        TransactionTrace::L1HandlerTxnTrace(trace) => {
+            invocations.push(&trace.function_invocation);
+        }
+    }
+
+    let status = |reverted: bool| {
+        if reverted { "REVERTED" } else { "SUCCEEDED" }.to_owned()
+    };
+    let receipt_reverted =
+        matches!(receipt.execution_status, TxnExecutionStatus::Reverted);
+    if reverted != receipt_reverted {
+        return Err(ProofError::ValueMismatch {
+            name: "execution status",
+            provided: status(receipt_reverted),
+            proven: status(reverted),
+        });
+    }
+
+    let mut events = Vec::new();
+    let mut messages = Vec::new();
+    for invocation in invocations {
+        collect_emitted(invocation, &mut events, &mut messages)?;
+    }
+    let provided_events = receipt
+        .events
+        .iter()
+        .map(|event| {
+            normalized(&[
+                vec![event.from_address.0.clone()],
+                event.event_content.keys.clone(),
+                event.event_content.data.clone(),
+            ])
+        })
+        .collect::<Result<_, _>>()?;
+    check_same_emitted("events", provided_events, events)?;
+
+    let provided_messages = receipt
+        .messages_sent
+        .iter()
+        .map(|message| {
+            normalized(&[
+                vec![message.from_address.clone(), message.to_address.clone()],
+                message.payload.clone(),
+            ])
+        })
+        .collect::<Result<_, _>>()?;
+    check_same_emitted("messages", provided_messages, messages)
+}
+
+/// Collect the events and messages emitted by the invocation and all of its
+/// nested calls, normalized so that they can be compared with the receipt.
+fn collect_emitted(
+    invocation: &FunctionInvocation,
+    events: &mut Vec<Emitted>,
+    messages: &mut Vec<Emitted>,
+) -> Result<(), ProofError> {
+    let from_address = &invocation.function_call.contract_address.0;
+    for event in &invocation.events {
+        events.push(normalized(&[
+            vec![from_address.clone()],
+            event.event_content.keys.clone(),
+            event.event_content.data.clone(),
+        ])?);
+    }
+    for message in &invocation.messages {
+        let message = &message.msg_to_l1;
+        messages.push(normalized(&[
+            vec![message.from_address.clone(), message.to_address.clone()],
+            message.payload.clone(),
+        ])?);
+    }
+    for call in &invocation.calls {
+        collect_emitted(&call.0, events, messages)?;
+    }
+    Ok(())
+}
+
+/// Fields of an emitted event or message, as numbers so that differently
+/// padded felts compare equal.
+type Emitted = Vec<Vec<FieldElement>>;
+
+fn normalized(fields: &[Vec<Felt>]) -> Result<Emitted, ProofError> {
+    fields
+        .iter()
+        .map(|felts| felts.iter().map(as_field_element).collect())
+        .collect()
+}
+
+fn check_same_emitted(
+    name: &'static str,
+    mut provided: Vec<Emitted>,
+    mut proven: Vec<Emitted>,
+) -> Result<(), ProofError> {
+    provided.sort();
+    proven.sort();
+    if provided != proven {
+        return Err(ProofError::ValueMismatch {
+            name,
+            provided: format!("{} {name}", provided.len()),
+            proven: format!("{} {name}", proven.len()),
+        });
+    }
+    Ok(())
+}
+
+fn deploy_transaction_hash(
+    tx: &DeployTxn,
+    chain_id: FieldElement,
+) -> Result<FieldElement, ProofError> {
+    let class_hash = as_field_element(&tx.class_hash)?;
+    let salt = as_field_element(&tx.contract_address_salt)?;
+    let calldata = felts(&tx.constructor_calldata)?;
+    let contract_address =
+        get_contract_address(salt, class_hash, &calldata, 0u64.into());
+    let constructor = get_selector_from_name("constructor")
+        .map_err(|_| ProofError::InvalidFelt("constructor".to_owned()))?;
+    Ok(hash_on_elements(&[
+        prefix(b"deploy"),
+        as_field_element(&tx.version)?,
+        contract_address,
+        constructor,
+        hash_on_elements(&calldata),
+        FieldElement::ZERO,
+        chain_id,
+    ]))
+}
+
+fn l1_handler_transaction_hash(
+    tx: &L1HandlerTxn,
+    chain_id: FieldElement,
+) -> Result<FieldElement, ProofError> {
+    let call = &tx.function_call;
+    Ok(hash_on_elements(&[
+        prefix(b"l1_handler"),
+        as_field_element(&tx.version)?,
+        as_field_element(&call.contract_address.0)?,
+        as_field_element(&call.entry_point_selector)?,
+        hash_on_elements(&felts(&call.calldata)?),
+        FieldElement::ZERO,
+        chain_id,
+        parse(tx.nonce.as_ref())?,
+    ]))
+}
+
+/// Hash of the tip and the resource bounds, where each bound is packed as
+/// resource name (60 bits) | max amount (64 bits) | max price (128 bits).
+fn fee_fields_hash(
+    tip: &U64,
+    resource_bounds: &ResourceBoundsMapping,
+) -> Result<FieldElement, ProofError> {
+    let resources = [
+        (b"L1_GAS", &resource_bounds.l1_gas),
+        (b"L2_GAS", &resource_bounds.l2_gas),
+    ];
+    let mut elements = vec![parse(tip.as_ref())?];
+    for (resource, bounds) in resources {
+        let max_amount = parse_u64(bounds.max_amount.as_ref())?;
+        let max_price = parse_int(bounds.max_price_per_unit.as_ref())?;
+        let mut bytes = [0u8; 32];
+        bytes[2..8].copy_from_slice(resource);
+        bytes[8..16].copy_from_slice(&max_amount.to_be_bytes());
+        bytes[16..].copy_from_slice(&max_price.to_be_bytes());
+        let element = FieldElement::from_bytes_be(&bytes)
+            .map_err(|_| ProofError::InvalidFelt(format!("{bounds:?}")))?;
+        elements.push(element);
+    }
+    Ok(poseidon_hash_many(&elements))
+}
+
+/// Data availability modes packed as nonce mode << 32 | fee mode.
+fn data_availability_modes(nonce: &DaMode, fee: &DaMode) -> FieldElement {
+    let mode = |mode: &DaMode| match mode {
+        DaMode::L1 => 0u64,
+        DaMode::L2 => 1u64,
+    };
+    FieldElement::from((mode(nonce) << 32) + mode(fee))
+}
+
+/// The version of the transaction, as serialized (e.g. "0x1").
+fn version<T: Serialize>(version: &T) -> Result<FieldElement, ProofError> {
+    let version = serde_json::to_value(version)
+        .map_err(|e| ProofError::InvalidFelt(e.to_string()))?;
+    let version = version.as_str().unwrap_or_default();
+    parse(version)
+}
+
+fn prefix(prefix: &[u8]) -> FieldElement {
+    FieldElement::from_byte_slice_be(prefix)
+        .expect("transaction prefix fits into a felt")
+}
+
+fn felts(felts: &[Felt]) -> Result<Vec<FieldElement>, ProofError> {
+    felts.iter().map(as_field_element).collect()
+}
+
+fn parse(hex: &str) -> Result<FieldElement, ProofError> {
+    FieldElement::from_hex_be(hex)
+        .map_err(|_| ProofError::InvalidFelt(hex.to_owned()))
+}
+
+fn parse_int(hex: &str) -> Result<u128, ProofError> {
+    u128::from_str_radix(hex.trim_start_matches("0x"), 16)
+        .map_err(|_| ProofError::InvalidFelt(hex.to_owned()))
+}
+
+fn parse_u64(hex: &str) -> Result<u64, ProofError> {
+    u64::try_from(parse_int(hex)?)
+        .map_err(|_| ProofError::InvalidFelt(hex.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINNET: &str = "0x534e5f4d41494e";
+
+    fn chain_id() -> Felt {
+        Felt::try_new(MAINNET).unwrap()
+    }
+
+    fn check(json: serde_json::Value) {
+        let transaction: BlockTransaction =
+            serde_json::from_value(json).unwrap();
+        let computed = transaction_hash(&transaction.txn, &chain_id()).unwrap();
+        assert_eq!(
+            as_field_element(&computed).unwrap(),
+            as_field_element(&transaction.transaction_hash.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invoke_v1() {
+        check(serde_json::json!({
+            "transaction_hash": "0xcbb2b87d5378e682d650e0e7d36679b4557ba2bfa9d4e285b7168c04376b21",
+            "type": "INVOKE",
+            "version": "0x1",
+            "sender_address": "0x13e3ca9a377084c37dc7eacbd1d9f8c3e3733935bcbad887c32a0e213cd6fe0",
+            "calldata": [
+                "0x2",
+                "0x57c4b510d66eb1188a7173f31cccee47b9736d40185da8144377b896d5ff3",
+                "0x2f0b3c5710379609eb5495f1ecd348cb28167711b73609fe565a72734550354",
+                "0x0",
+                "0x1",
+                "0x57c4b510d66eb1188a7173f31cccee47b9736d40185da8144377b896d5ff3",
+                "0x2f0b3c5710379609eb5495f1ecd348cb28167711b73609fe565a72734550354",
+                "0x1",
+                "0x1",
+                "0x2",
+                "0x0",
+                "0x1"
+            ],
+            "max_fee": "0x28ed6103d0000",
+            "nonce": "0x1",
+            "signature": [
+                "0x42527ffe9912b338983cbed67e139cfcc26a4d8cf1d1c2a85e4125fdf5f59ed",
+                "0x636147d06fefd02ed37984b752556d4b9aefdac1a50b3df0528ec7c201ad84b"
+            ]
+        }));
+    }
+
+    /// Mainnet transactions of every type and version, from INVOKE v0 in
+    /// block 206864 to INVOKE v3 in block 636864.
+    #[test]
+    fn test_mainnet_transactions() {
+        let transactions: Vec<serde_json::Value> = serde_json::from_str(
+            include_str!("resources/mainnet_transactions.json"),
+        )
+        .unwrap();
+        assert_eq!(transactions.len(), 11);
+        for json in transactions {
+            check(json);
+        }
+    }
+
+    #[test]
+    fn test_mainnet_block_transactions() {
+        let json: serde_json::Value = serde_json::from_str(include_str!(
+            "resources/mainnet_block_183862.json"
+        ))
+        .unwrap();
+        let transactions = json["block"]["transactions"].as_array().unwrap();
+        assert_eq!(transactions.len(), 332);
+        for json in transactions {
+            check(json.clone());
+        }
+    }
+
+    #[test]
+    fn test_tampered_body() {
+        let mut transaction: BlockTransaction =
+            serde_json::from_value(serde_json::json!({
+                "transaction_hash": "0xcbb2b87d5378e682d650e0e7d36679b4557ba2bfa9d4e285b7168c04376b21",
+                "type": "INVOKE",
+                "version": "0x1",
+                "sender_address": "0x13e3ca9a377084c37dc7eacbd1d9f8c3e3733935bcbad887c32a0e213cd6fe0",
+                "calldata": ["0x0"],
+                "max_fee": "0x28ed6103d0000",
+                "nonce": "0x1",
+                "signature": []
+            }))
+            .unwrap();
+        let result = verify_transaction_hash(&transaction, &chain_id());
+        assert!(result.unwrap_err().is_invalid());
+
+        transaction.transaction_hash.0 =
+            transaction_hash(&transaction.txn, &chain_id()).unwrap();
+        assert!(verify_transaction_hash(&transaction, &chain_id()).is_ok());
+    }
+
+    #[test]
+    fn test_fee_fields_hash() {
+        let resource_bounds: ResourceBoundsMapping =
+            serde_json::from_value(serde_json::json!({
+                "l1_gas": {
+                    "max_amount": "0x186a0",
+                    "max_price_per_unit": "0x5af3107a4000"
+                },
+                "l2_gas": {
+                    "max_amount": "0x0",
+                    "max_price_per_unit": "0x0"
+                }
+            }))
+            .unwrap();
+        let tip = U64::try_new("0x0").unwrap();
+        let l1_gas = FieldElement::from_hex_be(
+            "0x4c315f47415300000000000186a0000000000000000000005af3107a4000",
+        )
+        .unwrap();
+        let l2_gas = FieldElement::from_hex_be(
+            "0x4c325f474153000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        assert_eq!(
+            fee_fields_hash(&tip, &resource_bounds).unwrap(),
+            poseidon_hash_many(&[FieldElement::ZERO, l1_gas, l2_gas])
+        );
+    }
+
+    #[test]
+    fn test_data_availability_modes() {
+        let modes = data_availability_modes(&DaMode::L2, &DaMode::L1);
+        assert_eq!(modes, FieldElement::from(1u64 << 32));
+    }
+
+    fn invocation(events: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "contract_address": "0x1",
+            "entry_point_selector": "0x2",
+            "calldata": [],
+            "caller_address": "0x0",
+            "class_hash": "0x3",
+            "entry_point_type": "EXTERNAL",
+            "call_type": "CALL",
+            "result": [],
+            "calls": [],
+            "events": events,
+            "messages": [{
+                "order": 0,
+                "from_address": "0x1",
+                "to_address": "0xabc",
+                "payload": ["0x5"]
+            }],
+            "execution_resources": { "steps": 1 }
+        })
+    }
+
+    fn invoke_trace(execute_invocation: serde_json::Value) -> TransactionTrace {
+        serde_json::from_value(serde_json::json!({
+            "type": "INVOKE",
+            "validate_invocation": invocation(serde_json::json!([])),
+            "execute_invocation": execute_invocation
+        }))
+        .unwrap()
+    }
+
+    fn receipt(
+        execution_status: &str,
+        events: serde_json::Value,
+    ) -> CommonReceiptProperties {
+        serde_json::from_value(serde_json::json!({
+            "transaction_hash": "0x123",
+            "actual_fee": { "amount": "0x1", "unit": "WEI" },
+            "block_hash": "0x456",
+            "block_number": 1,
+            "events": events,
+            "execution_resources": { "steps": 1 },
+            "execution_status": execution_status,
+            "finality_status": "ACCEPTED_ON_L2",
+            "messages_sent": [
+                { "from_address": "0x1", "to_address": "0xabc", "payload": ["0x5"] },
+                { "from_address": "0x1", "to_address": "0xabc", "payload": ["0x5"] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_trace() {
+        let trace = invoke_trace(invocation(serde_json::json!([
+            { "order": 0, "keys": ["0xa"], "data": ["0xb"] }
+        ])));
+        let events = serde_json::json!([
+            { "from_address": "0x1", "keys": ["0xA"], "data": ["0xb"] }
+        ]);
+        assert!(verify_trace(&trace, &receipt("SUCCEEDED", events)).is_ok());
+    }
+
+    #[test]
+    fn test_verify_trace_event_mismatch() {
+        let trace = invoke_trace(invocation(serde_json::json!([
+            { "order": 0, "keys": ["0xa"], "data": ["0xb"] }
+        ])));
+        let events = serde_json::json!([
+            { "from_address": "0x1", "keys": ["0xa"], "data": ["0xc"] }
+        ]);
+        assert!(matches!(
+            verify_trace(&trace, &receipt("SUCCEEDED", events)),
+            Err(ProofError::ValueMismatch { name: "events", .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_trace_status_mismatch() {
+        let trace = invoke_trace(serde_json::json!({
+            "revert_reason": "Error in the called contract"
+        }));
+        let events = serde_json::json!([]);
+        assert!(matches!(
+            verify_trace(&trace, &receipt("SUCCEEDED", events)),
+            Err(ProofError::ValueMismatch { name: "execution status", .. })
+        ));
+    }
+}
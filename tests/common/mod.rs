@@ -1,10 +1,16 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use beerus::client::State;
-use beerus::gen::Felt;
+use beerus::config::{
+    ETH_FEE_TOKEN_ADDRESS, MAINNET_FEEDER_GATEWAY, STRK_FEE_TOKEN_ADDRESS,
+};
+use beerus::gen::{
+    BlockId, BlockNumber, Felt, GetBlockWithTxHashesResult, Rpc, TxnHash,
+};
 use beerus::{
     gen::client::Client,
-    rpc::{serve, Server},
+    rpc::{serve, Options, Server},
 };
 use thiserror::Error;
 use tokio::sync::RwLock;
@@ -15,19 +21,88 @@ pub struct Context {
     pub server: Server,
 }
 
+const MAINNET_CHAIN_ID: &str = "0x534e5f4d41494e";
+
+/// Older blocks queried by the tests, which are too far behind the latest
+/// block to be verified by walking the parent hashes down from it.
+const ANCHOR_BLOCKS: [u64; 4] = [33482, 59999, 354824, 600612];
+
+/// Transactions queried by the tests, whose blocks are anchored as well.
+const ANCHOR_TRANSACTIONS: [&str; 2] = [
+    "0x2e2a98c1731ece2691edfbb4ed9b057182cec569735bd89825f17e3b342583a",
+    "0x4c1672e824b5cd7477fca31ee3ab5a1058534ed1820bb27abc976c2e6095151",
+];
+
+async fn get_state(client: &Client, block_number: u64) -> Option<State> {
+    let block_id = BlockId::BlockNumber {
+        block_number: BlockNumber::try_new(block_number as i64).ok()?,
+    };
+    let GetBlockWithTxHashesResult::BlockWithTxHashes(block) =
+        client.getBlockWithTxHashes(block_id).await.ok()?
+    else {
+        return None;
+    };
+    Some(State {
+        block_number,
+        block_hash: block.block_header.block_hash.0,
+        root: block.block_header.new_root,
+    })
+}
+
+/// Number of blocks between a queried block and its anchor, so that the
+/// queried block itself is verified by walking the parent hashes down from
+/// the anchor, rather than taken as settled.
+const ANCHOR_DEPTH: u64 = 2;
+
+/// States of the blocks right above the anchor blocks, standing in for the
+/// states the client sees settled on L1 (with their hashes read from the
+/// provider, like the hash of the latest block).
+async fn anchors(client: &Client) -> Option<BTreeMap<u64, State>> {
+    let mut block_numbers = ANCHOR_BLOCKS.to_vec();
+    for transaction_hash in ANCHOR_TRANSACTIONS {
+        let transaction_hash = TxnHash(Felt::try_new(transaction_hash).ok()?);
+        let receipt =
+            client.getTransactionReceipt(transaction_hash).await.ok()?;
+        let receipt = serde_json::to_value(receipt).ok()?;
+        block_numbers.push(receipt["block_number"].as_u64()?);
+    }
+    let mut states = BTreeMap::new();
+    for block_number in block_numbers {
+        let block_number = block_number + ANCHOR_DEPTH;
+        states.insert(block_number, get_state(client, block_number).await?);
+    }
+    Some(states)
+}
+
 #[allow(dead_code)] // used in macros
 pub async fn ctx() -> Option<Context> {
     let url = std::env::var("BEERUS_TEST_STARKNET_URL").ok()?;
 
+    let block_number = 652076;
     let root =
         "0x2a5aa70350b7d047cd3dd2f5ad01f8925409a64fc42e509e8e79c3a2c17425";
+
+    // Blocks older than this one are verified by walking the parent hashes
+    // down from its hash, so it has to be the real one.
+    let provider = Client::new(&url);
     let state = State {
-        block_number: 652076,
-        block_hash: Felt::try_new("0x0").unwrap(),
         root: Felt::try_new(root).unwrap(),
+        ..get_state(&provider, block_number).await?
     };
     let state = Arc::new(RwLock::new(state));
-    let server = serve(&url, "127.0.0.1:0", state.clone()).await.ok()?;
+    let settled = Arc::new(RwLock::new(anchors(&provider).await?));
+    let options = Options {
+        chain_id: Felt::try_new(MAINNET_CHAIN_ID).unwrap(),
+        eth_fee_token_address: Felt::try_new(ETH_FEE_TOKEN_ADDRESS).unwrap(),
+        strk_fee_token_address: Felt::try_new(STRK_FEE_TOKEN_ADDRESS).unwrap(),
+        verify_events: false,
+        eth_methods: false,
+        feeder_gateway: Some(MAINNET_FEEDER_GATEWAY.to_owned()),
+    };
+    let server =
+        serve(&url, "127.0.0.1:0", state.clone(), settled, None, options)
+            .await
+            .ok()?;
     tracing::info!(port = server.port(), "test server is up");
 
     let url = format!("http://localhost:{}/rpc", server.port());
@@ -1,6 +1,6 @@
 use beerus::{
-    client::State,
-    exe::call,
+    config::{ETH_FEE_TOKEN_ADDRESS, STRK_FEE_TOKEN_ADDRESS},
+    exe::{call, Chain},
     gen::{self, blocking::Rpc, client::blocking::Client, Felt, FunctionCall},
 };
 
@@ -33,8 +33,10 @@ fn test_call_deprecated_contract_class() -> Result<(), Error> {
     });
     let function_call: FunctionCall = serde_json::from_value(json)?;
 
-    let state_root = Felt::try_new("0x0")?;
-    let call_info = call(&client, function_call, state_root)?;
+    let mut block_header = get_latest_header(&client);
+    block_header.new_root = Felt::try_new("0x0")?;
+    let chain = get_chain(&client)?;
+    let call_info = call(&client, function_call, &block_header, &chain)?;
 
     assert!(call_info.execution.retdata.0.is_empty());
 
@@ -52,8 +54,9 @@ fn test_call_regular_contract_class() -> Result<(), Error> {
     });
     let function_call: FunctionCall = serde_json::from_value(json)?;
 
-    let state_root = get_latest_state(&client).root;
-    let call_info = call(&client, function_call, state_root)?;
+    let block_header = get_latest_header(&client);
+    let chain = get_chain(&client)?;
+    let call_info = call(&client, function_call, &block_header, &chain)?;
 
     assert_eq!(call_info.execution.retdata.0.len(), 1);
     assert_eq!(call_info.execution.retdata.0[0], "0x4574686572".try_into()?);
@@ -61,20 +64,50 @@ fn test_call_regular_contract_class() -> Result<(), Error> {
     Ok(())
 }
 
-fn get_state(client: &Client, block_id: gen::BlockId) -> State {
+#[test]
+fn test_call_historical_block() -> Result<(), Error> {
+    let client = client!();
+
+    let json = serde_json::json!({
+      "calldata": [],
+      "contract_address": "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7",
+      "entry_point_selector": "0x361458367e696363fbcc70777d07ebbd2394e89fd0adcaf147faccd1d294d60"
+    });
+    let function_call: FunctionCall = serde_json::from_value(json)?;
+
+    let latest = get_latest_header(&client);
+    let block_number =
+        gen::BlockNumber::try_new(*latest.block_number.as_ref() - 10)?;
+    let block_id = gen::BlockId::BlockNumber { block_number };
+    let block_header = get_header(&client, block_id);
+    let chain = get_chain(&client)?;
+    let call_info = call(&client, function_call, &block_header, &chain)?;
+
+    assert_eq!(call_info.execution.retdata.0.len(), 1);
+    assert_eq!(call_info.execution.retdata.0[0], "0x4574686572".try_into()?);
+
+    Ok(())
+}
+
+fn get_header(client: &Client, block_id: gen::BlockId) -> gen::BlockHeader {
     let block = client.getBlockWithTxHashes(block_id).unwrap();
     let gen::GetBlockWithTxHashesResult::BlockWithTxHashes(block) = block
     else {
         panic!("Pending block received");
     };
-    State {
-        block_number: *block.block_header.block_number.as_ref() as u64,
-        block_hash: block.block_header.block_hash.0,
-        root: block.block_header.new_root,
-    }
+    block.block_header
 }
 
-fn get_latest_state(client: &Client) -> State {
+fn get_latest_header(client: &Client) -> gen::BlockHeader {
     let block_id = gen::BlockId::BlockTag(gen::BlockTag::Latest);
-    get_state(client, block_id)
+    get_header(client, block_id)
+}
+
+fn get_chain(client: &Client) -> Result<Chain, Error> {
+    let chain_id = client.chainId()?;
+    Ok(Chain {
+        chain_id: Felt::try_new(chain_id.as_ref())?,
+        eth_fee_token_address: Felt::try_new(ETH_FEE_TOKEN_ADDRESS)?,
+        strk_fee_token_address: Felt::try_new(STRK_FEE_TOKEN_ADDRESS)?,
+    })
 }
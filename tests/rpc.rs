@@ -1,10 +1,13 @@
+use beerus::gen::client::Client;
 use beerus::gen::{
     Address, BlockHash, BlockId, BlockNumber, BlockTag, BroadcastedInvokeTxn,
     BroadcastedTxn, Felt, FunctionCall, GetBlockWithTxHashesResult,
     GetBlockWithTxsResult, GetClassAtResult, GetClassResult,
-    GetTransactionByBlockIdAndIndexIndex, GetTransactionReceiptResult,
-    InvokeTxn, InvokeTxnV1, InvokeTxnV1Version, PriceUnit, Rpc, StorageKey,
-    SyncingResult, Txn, TxnExecutionStatus, TxnHash, TxnReceipt, TxnStatus,
+    GetStateUpdateResult, GetTransactionByBlockIdAndIndexIndex,
+    GetTransactionReceiptResult, InvokeTxn, InvokeTxnTraceExecuteInvocation,
+    InvokeTxnV1, InvokeTxnV1Version, PriceUnit, Rpc, SimulationFlag,
+    StorageKey, SyncingResult, TransactionTrace, Txn, TxnExecutionStatus,
+    TxnHash, TxnReceipt, TxnStatus,
 };
 
 mod common;
@@ -81,6 +84,58 @@ async fn test_call() -> Result<(), Error> {
 async fn test_estimateFee() -> Result<(), Error> {
     let ctx = setup!();
 
+    let request = vec![invoke_transaction()?];
+
+    let simulation_flags = vec![];
+
+    let block_id =
+        BlockId::BlockNumber { block_number: BlockNumber::try_new(59999)? };
+
+    let ret =
+        ctx.client.estimateFee(request, simulation_flags, block_id).await?;
+    assert_eq!(ret.len(), 1);
+    assert_eq!(ret[0].overall_fee.as_ref(), "0x1abd7b153e472");
+    assert_eq!(ret[0].gas_price.as_ref(), "0x67edb4f57");
+    assert_eq!(ret[0].gas_consumed.as_ref(), "0x41de");
+    assert!(matches!(ret[0].unit, PriceUnit::Wei));
+    Ok(())
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_simulateTransactions() -> Result<(), Error> {
+    let ctx = setup!();
+
+    let transactions = vec![invoke_transaction()?];
+    let simulation_flags = vec![SimulationFlag::SkipFeeCharge];
+
+    let block_id =
+        BlockId::BlockNumber { block_number: BlockNumber::try_new(59999)? };
+
+    let ret = ctx
+        .client
+        .simulateTransactions(block_id, transactions, simulation_flags)
+        .await?;
+    assert_eq!(ret.len(), 1);
+    assert!(ret[0].fee_estimation.is_some());
+    let Some(TransactionTrace::InvokeTxnTrace(trace)) =
+        ret[0].transaction_trace.as_ref()
+    else {
+        panic!("unexpected transaction trace");
+    };
+    assert!(trace.validate_invocation.is_some());
+    assert!(trace.fee_transfer_invocation.is_none());
+    assert!(matches!(
+        trace.execute_invocation,
+        InvokeTxnTraceExecuteInvocation::FunctionInvocation(_)
+    ));
+    let state_diff = trace.state_diff.as_ref().expect("state diff");
+    assert_eq!(state_diff.nonces.len(), 1);
+    Ok(())
+}
+
+// TX: 0xcbb2b87d5378e682d650e0e7d36679b4557ba2bfa9d4e285b7168c04376b21
+fn invoke_transaction() -> Result<BroadcastedTxn, Error> {
     let calldata = vec![
         "0x2",
         "0x57c4b510d66eb1188a7173f31cccee47b9736d40185da8144377b896d5ff3",
@@ -105,37 +160,21 @@ async fn test_estimateFee() -> Result<(), Error> {
     let signature: Result<Vec<Felt>, _> =
         signature.into_iter().map(Felt::try_new).collect();
 
-    let request = vec![
-        BroadcastedTxn::BroadcastedInvokeTxn(
-            BroadcastedInvokeTxn(
-                InvokeTxn::InvokeTxnV1(
-                    InvokeTxnV1 {
-                        calldata: calldata?,
-                        signature: signature?,
-                        sender_address: Address(Felt::try_new("0x13e3ca9a377084c37dc7eacbd1d9f8c3e3733935bcbad887c32a0e213cd6fe0")?), 
-                        max_fee: Felt::try_new("0x28ed6103d0000")?, 
-                        nonce: Felt::try_new("0x1")?,
-                        version: InvokeTxnV1Version::V0x1,
-                        r#type: beerus::gen::InvokeTxnV1Type::Invoke, 
-                    }
-                )
-            )
-        )
-    ];
-
-    let simulation_flags = vec![];
-
-    let block_id =
-        BlockId::BlockNumber { block_number: BlockNumber::try_new(59999)? };
+    let sender_address = Address(Felt::try_new(
+        "0x13e3ca9a377084c37dc7eacbd1d9f8c3e3733935bcbad887c32a0e213cd6fe0",
+    )?);
 
-    let ret =
-        ctx.client.estimateFee(request, simulation_flags, block_id).await?;
-    assert_eq!(ret.len(), 1);
-    assert_eq!(ret[0].overall_fee.as_ref(), "0x1abd7b153e472");
-    assert_eq!(ret[0].gas_price.as_ref(), "0x67edb4f57");
-    assert_eq!(ret[0].gas_consumed.as_ref(), "0x41de");
-    assert!(matches!(ret[0].unit, PriceUnit::Wei));
-    Ok(())
+    Ok(BroadcastedTxn::BroadcastedInvokeTxn(BroadcastedInvokeTxn(
+        InvokeTxn::InvokeTxnV1(InvokeTxnV1 {
+            calldata: calldata?,
+            signature: signature?,
+            sender_address,
+            max_fee: Felt::try_new("0x28ed6103d0000")?,
+            nonce: Felt::try_new("0x1")?,
+            version: InvokeTxnV1Version::V0x1,
+            r#type: beerus::gen::InvokeTxnV1Type::Invoke,
+        }),
+    )))
 }
 
 #[tokio::test]
@@ -182,6 +221,25 @@ async fn test_getBlockWithTxs() -> Result<(), Error> {
     Ok(())
 }
 
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_getStateUpdate() -> Result<(), Error> {
+    let ctx = setup!();
+
+    let block_id = BlockId::BlockTag(BlockTag::Latest);
+
+    let ret = ctx.client.getStateUpdate(block_id).await?;
+    let GetStateUpdateResult::StateUpdate(ret) = ret else {
+        panic!("unexpected pending state update");
+    };
+    assert_eq!(
+        ret.new_root.as_ref(),
+        "0x2a5aa70350b7d047cd3dd2f5ad01f8925409a64fc42e509e8e79c3a2c17425"
+    );
+    assert!(!ret.state_diff.storage_diffs.is_empty());
+    Ok(())
+}
+
 #[tokio::test]
 #[allow(non_snake_case)]
 async fn test_syncing() -> Result<(), Error> {
@@ -425,3 +483,60 @@ async fn test_getClassHashAt() -> Result<(), Error> {
     );
     Ok(())
 }
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_traceTransaction() -> Result<(), Error> {
+    let ctx = setup!();
+
+    // Blocks since 0.13.2 cannot be re-executed with the bundled constants.
+    let block_id =
+        BlockId::BlockNumber { block_number: BlockNumber::try_new(59999)? };
+    let index = GetTransactionByBlockIdAndIndexIndex::try_new(0)?;
+    let transaction =
+        ctx.client.getTransactionByBlockIdAndIndex(block_id, index).await?;
+
+    let ret = ctx.client.traceTransaction(transaction.transaction_hash).await?;
+    let state_diff = match ret {
+        TransactionTrace::InvokeTxnTrace(trace) => trace.state_diff,
+        TransactionTrace::DeclareTxnTrace(trace) => trace.state_diff,
+        TransactionTrace::DeployAccountTxnTrace(trace) => trace.state_diff,
+        TransactionTrace::L1HandlerTxnTrace(trace) => trace.state_diff,
+    };
+    assert!(state_diff.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_traceTransaction_matches_recorded_trace() -> Result<(), Error> {
+    let ctx = setup!();
+    let provider = Client::new(&std::env::var("BEERUS_TEST_STARKNET_URL")?);
+
+    let transaction_hash = TxnHash(Felt::try_new(
+        "0x4c1672e824b5cd7477fca31ee3ab5a1058534ed1820bb27abc976c2e6095151",
+    )?);
+
+    // The trace recorded by the provider's node, which executed the block.
+    let recorded = provider.traceTransaction(transaction_hash.clone()).await?;
+    let ret = ctx.client.traceTransaction(transaction_hash).await?;
+    let (
+        TransactionTrace::InvokeTxnTrace(recorded),
+        TransactionTrace::InvokeTxnTrace(ret),
+    ) = (recorded, ret)
+    else {
+        panic!("unexpected transaction trace");
+    };
+
+    // The fee transfer is only the same if the block context is: the fee
+    // charged depends on the gas prices and the versioned constants.
+    assert_eq!(
+        serde_json::to_value(&ret.fee_transfer_invocation)?,
+        serde_json::to_value(&recorded.fee_transfer_invocation)?
+    );
+    assert_eq!(
+        serde_json::to_value(&ret.execute_invocation)?,
+        serde_json::to_value(&recorded.execute_invocation)?
+    );
+    Ok(())
+}